@@ -0,0 +1,200 @@
+use crate::error::ContractError;
+use crate::history;
+use crate::msg::BudgetRoundResponse;
+use crate::state::{
+    load_votes, BudgetRound, HistoryEvent, ScenarioLimits, ALLOCATIONS, BUDGET_ROUNDS, CITIZENS,
+    NEXT_ROUND_ID,
+};
+use cosmwasm_std::{Deps, DepsMut, Env, MessageInfo, Response, StdResult, Storage};
+use std::cmp::Reverse;
+
+// Checks the parameters of a budget round before it is put to a vote
+pub fn validate_round(
+    storage: &dyn Storage,
+    budget: u128,
+    allotment: u128,
+    limits: &[ScenarioLimits],
+) -> Result<(), ContractError> {
+    if budget == 0 || allotment == 0 {
+        return Err(ContractError::ZeroBudget {});
+    }
+    if limits.is_empty() {
+        return Err(ContractError::InvalidAllocation {});
+    }
+    let votes = load_votes(storage)?;
+    for (i, limit) in limits.iter().enumerate() {
+        if !votes
+            .scenario_votes
+            .iter()
+            .any(|(s, _)| *s == limit.scenario)
+        {
+            return Err(ContractError::ScenarioNotFound {});
+        }
+        if limit.max_support == 0
+            || limit.max_funding == 0
+            || limits[..i].iter().any(|l| l.scenario == limit.scenario)
+        {
+            return Err(ContractError::InvalidAllocation {});
+        }
+    }
+    Ok(())
+}
+
+// Opens a round once the proposal for it has passed, it lasts one voting period
+pub fn open_round(
+    storage: &mut dyn Storage,
+    env: &Env,
+    proposal_id: u64,
+    voting_period: u64,
+    budget: u128,
+    allotment: u128,
+    limits: Vec<ScenarioLimits>,
+) -> StdResult<u64> {
+    let id = NEXT_ROUND_ID.may_load(storage)?.unwrap_or_default();
+    let round = BudgetRound {
+        id,
+        proposal_id,
+        budget,
+        allotment,
+        support: limits.iter().map(|l| (l.scenario.clone(), 0)).collect(),
+        limits,
+        end_height: env.block.height + voting_period,
+        allocation: None,
+    };
+    BUDGET_ROUNDS.save(storage, id, &round)?;
+    NEXT_ROUND_ID.save(storage, &(id + 1))?;
+    Ok(id)
+}
+
+// Replaces the voter's previous allocation in the round
+pub fn allocate(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    round_id: u64,
+    allocations: Vec<(String, u128)>,
+) -> Result<Response, ContractError> {
+    let mut round = BUDGET_ROUNDS.load(deps.storage, round_id)?;
+    if round.allocation.is_some() || env.block.height >= round.end_height {
+        return Err(ContractError::RoundClosed { round_id });
+    }
+    if !CITIZENS.has(deps.storage, &info.sender) {
+        return Err(ContractError::NotACitizen {});
+    }
+    for (i, (scenario, amount)) in allocations.iter().enumerate() {
+        if !round.limits.iter().any(|l| l.scenario == *scenario) {
+            return Err(ContractError::ScenarioNotFound {});
+        }
+        if *amount == 0 || allocations[..i].iter().any(|(s, _)| s == scenario) {
+            return Err(ContractError::InvalidAllocation {});
+        }
+    }
+    let total = allocations
+        .iter()
+        .try_fold(0u128, |total, (_, amount)| total.checked_add(*amount))
+        .ok_or(ContractError::AllocationOverflow {})?;
+    if total > round.allotment {
+        return Err(ContractError::AllotmentExceeded {});
+    }
+
+    let previous = ALLOCATIONS
+        .may_load(deps.storage, (round_id, &info.sender))?
+        .unwrap_or_default();
+    for (scenario, support) in round.support.iter_mut() {
+        let removed = amount_for(&previous, scenario);
+        let added = amount_for(&allocations, scenario);
+        *support = (*support - removed)
+            .checked_add(added)
+            .ok_or(ContractError::AllocationOverflow {})?;
+    }
+    for ((scenario, support), limit) in round.support.iter().zip(round.limits.iter()) {
+        if *support > limit.max_support {
+            return Err(ContractError::ScenarioCapExceeded {
+                scenario: scenario.clone(),
+            });
+        }
+    }
+    BUDGET_ROUNDS.save(deps.storage, round_id, &round)?;
+    ALLOCATIONS.save(deps.storage, (round_id, &info.sender), &allocations)?;
+    let event = HistoryEvent::BudgetAllocated {
+        round_id,
+        allocations,
+    };
+    history::record(deps.storage, &env, &info.sender, None, event)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "allocate_budget")
+        .add_attribute("round_id", round_id.to_string())
+        .add_attribute("voter", info.sender)
+        .add_attribute("total", total.to_string()))
+}
+
+// Records how the budget is split, the payout itself is up to the executors of the scenarios
+pub fn finalize_round(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    round_id: u64,
+) -> Result<Response, ContractError> {
+    let mut round = BUDGET_ROUNDS.load(deps.storage, round_id)?;
+    if round.allocation.is_some() {
+        return Err(ContractError::RoundFinalized { round_id });
+    }
+    if env.block.height < round.end_height {
+        return Err(ContractError::RoundOpen { round_id });
+    }
+
+    let allocation = allocate_greedily(&round);
+    round.allocation = Some(allocation.clone());
+    BUDGET_ROUNDS.save(deps.storage, round_id, &round)?;
+    let event = HistoryEvent::RoundFinalized {
+        round_id,
+        allocation: allocation.clone(),
+    };
+    history::record(deps.storage, &env, &info.sender, None, event)?;
+
+    let funded: u128 = allocation.iter().map(|(_, amount)| *amount).sum();
+    Ok(Response::new()
+        .add_attribute("action", "finalize_round")
+        .add_attribute("round_id", round_id.to_string())
+        .add_attribute("funded", funded.to_string()))
+}
+
+pub fn query_round(deps: Deps, round_id: u64) -> StdResult<BudgetRoundResponse> {
+    Ok(BudgetRoundResponse {
+        round: BUDGET_ROUNDS.load(deps.storage, round_id)?,
+    })
+}
+
+// Funds scenarios up to their funding limit in order of support until the budget runs
+// out, ties keep the order the limits were proposed in
+fn allocate_greedily(round: &BudgetRound) -> Vec<(String, u128)> {
+    let mut ranked: Vec<_> = round
+        .support
+        .iter()
+        .zip(round.limits.iter())
+        .filter(|((_, support), _)| *support > 0)
+        .map(|((scenario, support), limit)| (scenario, *support, limit.max_funding))
+        .collect();
+    ranked.sort_by_key(|(_, support, _)| Reverse(*support));
+
+    let mut remaining = round.budget;
+    let mut allocation = vec![];
+    for (scenario, _, cap) in ranked {
+        if remaining == 0 {
+            break;
+        }
+        let amount = cap.min(remaining);
+        allocation.push((scenario.clone(), amount));
+        remaining -= amount;
+    }
+    allocation
+}
+
+fn amount_for(allocations: &[(String, u128)], scenario: &str) -> u128 {
+    allocations
+        .iter()
+        .find(|(s, _)| s == scenario)
+        .map(|(_, amount)| *amount)
+        .unwrap_or_default()
+}
@@ -0,0 +1,263 @@
+use crate::error::ContractError;
+use crate::history;
+use crate::msg::FundingRequestResponse;
+use crate::state::{
+    load_votes, ConvictionConfig, FundingRequest, HistoryEvent, CITIZENS, CONVICTION,
+    FUNDING_REQUESTS, NEXT_REQUEST_ID, STAKES, TOTAL_STAKED,
+};
+use cosmwasm_std::{
+    coin, BankMsg, Decimal, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult, Uint128,
+};
+use std::convert::TryFrom;
+
+pub fn validate_config(config: &ConvictionConfig) -> Result<(), ContractError> {
+    if config.decay.is_zero()
+        || config.decay >= Decimal::one()
+        || config.max_ratio.is_zero()
+        || config.max_ratio > Decimal::one()
+    {
+        return Err(ContractError::InvalidConvictionConfig {});
+    }
+    Ok(())
+}
+
+pub fn request_funding(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    scenario: String,
+    beneficiary: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    CONVICTION
+        .may_load(deps.storage)?
+        .ok_or(ContractError::ConvictionDisabled {})?;
+    if !CITIZENS.has(deps.storage, &info.sender) {
+        return Err(ContractError::NotACitizen {});
+    }
+    // The bank module rejects sends of zero coins, so the request could never be paid
+    if amount.is_zero() {
+        return Err(ContractError::ZeroFundingRequest {});
+    }
+    let votes = load_votes(deps.storage)?;
+    if !votes.scenario_votes.iter().any(|(s, _)| *s == scenario) {
+        return Err(ContractError::ScenarioNotFound {});
+    }
+
+    let id = NEXT_REQUEST_ID.may_load(deps.storage)?.unwrap_or_default();
+    let request = FundingRequest {
+        id,
+        scenario,
+        requester: info.sender,
+        beneficiary: deps.api.addr_validate(&beneficiary)?,
+        requested: amount,
+        staked: Uint128::zero(),
+        conviction: Uint128::zero(),
+        last_height: env.block.height,
+        funded: false,
+    };
+    FUNDING_REQUESTS.save(deps.storage, id, &request)?;
+    NEXT_REQUEST_ID.save(deps.storage, &(id + 1))?;
+
+    Ok(Response::new()
+        .add_attribute("action", "request_funding")
+        .add_attribute("request_id", id.to_string())
+        .add_attribute("amount", amount))
+}
+
+pub fn stake(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    request_id: u64,
+) -> Result<Response, ContractError> {
+    let config = CONVICTION
+        .may_load(deps.storage)?
+        .ok_or(ContractError::ConvictionDisabled {})?;
+    let amount = match info.funds.as_slice() {
+        [fund] if fund.denom == config.denom && !fund.amount.is_zero() => fund.amount,
+        _ => return Err(ContractError::InvalidStake {}),
+    };
+    let mut request = FUNDING_REQUESTS.load(deps.storage, request_id)?;
+    if request.funded {
+        return Err(ContractError::AlreadyFunded { request_id });
+    }
+
+    // Conviction accrues with the old stake up to this block
+    accrue(&config, &mut request, env.block.height)?;
+    request.staked += amount;
+    let stake = STAKES
+        .may_load(deps.storage, (request_id, &info.sender))?
+        .unwrap_or_default();
+    STAKES.save(deps.storage, (request_id, &info.sender), &(stake + amount))?;
+    let total_staked = TOTAL_STAKED.may_load(deps.storage)?.unwrap_or_default();
+    TOTAL_STAKED.save(deps.storage, &(total_staked + amount))?;
+    let event = HistoryEvent::Staked { request_id, amount };
+    history::record(deps.storage, &env, &info.sender, None, event)?;
+
+    let response = Response::new()
+        .add_attribute("action", "stake")
+        .add_attribute("request_id", request_id.to_string())
+        .add_attribute("amount", amount);
+    release_if_passed(deps, &env, &config, request, response)
+}
+
+pub fn unstake(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    request_id: u64,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let config = CONVICTION
+        .may_load(deps.storage)?
+        .ok_or(ContractError::ConvictionDisabled {})?;
+    let stake = STAKES
+        .may_load(deps.storage, (request_id, &info.sender))?
+        .unwrap_or_default();
+    if amount.is_zero() || amount > stake {
+        return Err(ContractError::InsufficientStake {});
+    }
+    let mut request = FUNDING_REQUESTS.load(deps.storage, request_id)?;
+
+    if !request.funded {
+        accrue(&config, &mut request, env.block.height)?;
+    }
+    request.staked -= amount;
+    if stake == amount {
+        STAKES.remove(deps.storage, (request_id, &info.sender));
+    } else {
+        STAKES.save(deps.storage, (request_id, &info.sender), &(stake - amount))?;
+    }
+    let total_staked = TOTAL_STAKED.load(deps.storage)?;
+    TOTAL_STAKED.save(deps.storage, &(total_staked - amount))?;
+    let event = HistoryEvent::Unstaked { request_id, amount };
+    history::record(deps.storage, &env, &info.sender, None, event)?;
+    // No release here, the refund is still in the balance until the send runs
+    // and would pass for treasury
+    FUNDING_REQUESTS.save(deps.storage, request_id, &request)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "unstake")
+        .add_attribute("request_id", request_id.to_string())
+        .add_attribute("amount", amount)
+        .add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: vec![coin(amount.u128(), &config.denom)],
+        }))
+}
+
+pub fn update_conviction(
+    deps: DepsMut,
+    env: Env,
+    request_id: u64,
+) -> Result<Response, ContractError> {
+    let config = CONVICTION
+        .may_load(deps.storage)?
+        .ok_or(ContractError::ConvictionDisabled {})?;
+    let mut request = FUNDING_REQUESTS.load(deps.storage, request_id)?;
+    if request.funded {
+        return Err(ContractError::AlreadyFunded { request_id });
+    }
+    accrue(&config, &mut request, env.block.height)?;
+
+    let response = Response::new()
+        .add_attribute("action", "update_conviction")
+        .add_attribute("request_id", request_id.to_string())
+        .add_attribute("conviction", request.conviction);
+    release_if_passed(deps, &env, &config, request, response)
+}
+
+pub fn query_request(deps: Deps, env: Env, request_id: u64) -> StdResult<FundingRequestResponse> {
+    let config = CONVICTION.load(deps.storage)?;
+    let mut request = FUNDING_REQUESTS.load(deps.storage, request_id)?;
+    let stored = request.clone();
+    if !request.funded {
+        accrue(&config, &mut request, env.block.height)?;
+    }
+    Ok(FundingRequestResponse {
+        request: stored,
+        conviction: request.conviction,
+        threshold: threshold(deps, &env, &config, &request)?,
+    })
+}
+
+// y' = y * decay^blocks + staked * (1 - decay^blocks) / (1 - decay)
+fn accrue(config: &ConvictionConfig, request: &mut FundingRequest, height: u64) -> StdResult<()> {
+    let blocks = u32::try_from(height.saturating_sub(request.last_height)).unwrap_or(u32::MAX);
+    let retained = config.decay.checked_pow(blocks)?;
+    // Growth nears 1 / (1 - decay) for a decay close to one
+    let growth = (Decimal::one() - retained) / (Decimal::one() - config.decay);
+    let accrued = request
+        .staked
+        .checked_mul_floor(growth)
+        .map_err(|_| StdError::generic_err("Conviction overflows"))?;
+    request.conviction = request
+        .conviction
+        .mul_floor(retained)
+        .checked_add(accrued)?;
+    request.last_height = height;
+    Ok(())
+}
+
+fn threshold(
+    deps: Deps,
+    env: &Env,
+    config: &ConvictionConfig,
+    request: &FundingRequest,
+) -> StdResult<Option<Uint128>> {
+    let total_staked = TOTAL_STAKED.may_load(deps.storage)?.unwrap_or_default();
+    let balance = deps
+        .querier
+        .query_balance(&env.contract.address, &config.denom)?
+        .amount;
+    // Staked tokens are held by the contract but are not part of the treasury
+    let treasury = balance.saturating_sub(total_staked);
+    if treasury.is_zero() {
+        return Ok(None);
+    }
+    let share = Decimal::from_ratio(request.requested, treasury);
+    if share >= config.max_ratio {
+        return Ok(None);
+    }
+    let gap = config.max_ratio - share;
+    // A share just below max_ratio rounds the denominator to zero, a threshold
+    // too large to hold can never be reached either
+    let denominator = (Decimal::one() - config.decay) * gap * gap;
+    let factor = match config.weight.checked_div(denominator) {
+        Ok(factor) => factor,
+        Err(_) => return Ok(None),
+    };
+    Ok(total_staked.checked_mul_ceil(factor).ok())
+}
+
+fn release_if_passed(
+    deps: DepsMut,
+    env: &Env,
+    config: &ConvictionConfig,
+    mut request: FundingRequest,
+    response: Response,
+) -> Result<Response, ContractError> {
+    let passed = match threshold(deps.as_ref(), env, config, &request)? {
+        Some(threshold) => !request.funded && request.conviction >= threshold.max(Uint128::one()),
+        None => false,
+    };
+    if !passed {
+        FUNDING_REQUESTS.save(deps.storage, request.id, &request)?;
+        return Ok(response);
+    }
+
+    request.funded = true;
+    FUNDING_REQUESTS.save(deps.storage, request.id, &request)?;
+    let event = HistoryEvent::Funded {
+        request_id: request.id,
+        amount: request.requested,
+    };
+    history::record(deps.storage, env, &request.beneficiary, None, event)?;
+    Ok(response
+        .add_attribute("funded", request.id.to_string())
+        .add_message(BankMsg::Send {
+            to_address: request.beneficiary.to_string(),
+            amount: vec![coin(request.requested.u128(), &config.denom)],
+        }))
+}
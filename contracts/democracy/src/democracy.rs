@@ -1,12 +1,32 @@
-use crate::msg::{Action, ExecuteMsg, InstantiateMsg, QueryAction, QueryMsg, Votes};
-use cosmwasm_std::StdError;
+use crate::budgeting;
+use crate::conviction;
+use crate::error::ContractError;
+use crate::history::{self, MAX_LIMIT};
+use crate::msg::{
+    Action, CitizenResponse, ExecuteMsg, InstantiateMsg, Member, ProposalKind, ProposalResponse,
+    QueryAction, QueryMsg, SecretBallot, SubNetworksResponse, TotalWeightResponse, VoteOption,
+    Votes,
+};
+use crate::secret;
+use crate::state::{
+    load_votes, Ballot, Citizen, CitizenKind, Config, HistoryEvent, Proposal, ProposalStatus,
+    SubNetwork, Tally, BALLOTS, CITIZENS, CONFIG, CONVICTION, DEFAULT_VOTING_PERIOD,
+    NEXT_PROPOSAL_ID, PROPOSALS, SUB_NETWORKS, TOTAL_WEIGHT, VOTES,
+};
 use cosmwasm_std::{
-    entry_point, from_binary, to_binary, Binary, Deps, DepsMut, Env, MessageInfo, Response,
-    StdResult,
+    entry_point, from_json, to_json_binary, to_json_vec, Addr, Binary, Decimal, Deps, DepsMut, Env,
+    MessageInfo, Order, Reply, Response, StdError, StdResult, SubMsg, WasmMsg,
 };
-use std::fmt::Binary;
+use std::collections::BTreeSet;
 
 pub const MAX_BUDGET: u128 = 1000;
+pub const REPLY_SUB_NETWORK: u64 = 1;
+pub const REPLY_PARENT_VOTE: u64 = 2;
+// Bounds the weight queries network_weight sends to sub-networks
+pub const MAX_SUB_NETWORKS: usize = 10;
+// Together with MAX_SUB_NETWORKS bounds the queries a weight lookup sets off down
+// the tree, the root network sits at depth 0
+pub const MAX_NETWORK_DEPTH: u32 = 2;
 
 #[entry_point]
 pub fn instantiate(
@@ -14,162 +34,737 @@ pub fn instantiate(
     _env: Env,
     _info: MessageInfo,
     msg: InstantiateMsg,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
     let votes = Votes {
         scenario_votes: msg.scenarios.into_iter().map(|s| (s, 0)).collect(),
     };
+    VOTES.save(deps.storage, &votes)?;
+
+    let parent = msg
+        .parent
+        .map(|parent| deps.api.addr_validate(&parent))
+        .transpose()?;
+    let config = Config {
+        parent,
+        depth: msg.depth,
+        sub_network_code_id: msg.sub_network_code_id,
+        voting_period: msg.voting_period.unwrap_or(DEFAULT_VOTING_PERIOD),
+        quorum: msg.quorum.unwrap_or_else(|| Decimal::percent(50)),
+    };
+    CONFIG.save(deps.storage, &config)?;
+    if let Some(conviction) = msg.conviction {
+        conviction::validate_config(&conviction)?;
+        CONVICTION.save(deps.storage, &conviction)?;
+    }
+
+    let mut total_weight: u64 = 0;
+    for member in msg.members {
+        let addr = deps.api.addr_validate(&member.addr)?;
+        if CITIZENS.has(deps.storage, &addr) {
+            return Err(ContractError::DuplicateMember {
+                address: member.addr,
+            });
+        }
+        let citizen = Citizen {
+            kind: CitizenKind::Individual,
+            weight: member.weight,
+        };
+        CITIZENS.save(deps.storage, &addr, &citizen)?;
+        total_weight = total_weight
+            .checked_add(member.weight)
+            .ok_or_else(|| StdError::generic_err("Total weight overflows"))?;
+    }
+    TOTAL_WEIGHT.save(deps.storage, &total_weight)?;
+    NEXT_PROPOSAL_ID.save(deps.storage, &0)?;
 
-    deps.storage.set(b"votes", &to_binary(&votes)?);
-    Ok(Response::default())
+    Ok(Response::new()
+        .add_attribute("action", "instantiate")
+        .add_attribute("total_weight", total_weight.to_string()))
 }
 
 #[entry_point]
 pub fn execute(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     msg: ExecuteMsg,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
     match msg.action {
-        //why did you add voting in storage? voting in latch?
-        ExecuteMsg::Vote { proposal_id, vote } => vote(deps, info, proposal_id, vote),
-        // Action::SubmitVote { scenario, budget } => submit_vote(deps, info, scenario, budget),
+        Action::SubmitVote { scenario, budget } => submit_vote(deps, env, info, scenario, budget),
+        Action::Propose {
+            title,
+            kind,
+            secret,
+        } => propose(deps, env, info, title, kind, secret),
+        Action::CastVote { proposal_id, vote } => cast_vote(deps, env, info, proposal_id, vote),
+        Action::ExecuteProposal { proposal_id } => execute_proposal(deps, env, info, proposal_id),
+        Action::CommitVote {
+            proposal_id,
+            commitment,
+        } => secret::commit_vote(deps, env, info, proposal_id, commitment),
+        Action::RevealVote {
+            proposal_id,
+            vote,
+            salt,
+        } => secret::reveal_vote(deps, env, info, proposal_id, vote, salt),
+        Action::DiscardCommitments { proposal_id, limit } => {
+            secret::discard_leftover_commitments(deps, env, proposal_id, limit)
+        }
+        Action::RequestFunding {
+            scenario,
+            beneficiary,
+            amount,
+        } => conviction::request_funding(deps, env, info, scenario, beneficiary, amount),
+        Action::Stake { request_id } => conviction::stake(deps, env, info, request_id),
+        Action::Unstake { request_id, amount } => {
+            conviction::unstake(deps, env, info, request_id, amount)
+        }
+        Action::UpdateConviction { request_id } => {
+            conviction::update_conviction(deps, env, request_id)
+        }
+        Action::AllocateBudget {
+            round_id,
+            allocations,
+        } => budgeting::allocate(deps, env, info, round_id, allocations),
+        Action::FinalizeRound { round_id } => budgeting::finalize_round(deps, env, info, round_id),
     }
 }
 
 #[entry_point]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        REPLY_SUB_NETWORK => register_sub_network(deps, env, msg),
+        REPLY_PARENT_VOTE => fail_parent_vote(deps, env, msg),
+        id => Err(ContractError::UnknownReply { id }),
+    }
+}
+
+#[entry_point]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg.action {
-        QueryAction::GetVotes {} => to_binary(&get_votes(deps)),
+        QueryAction::GetVotes {} => to_json_binary(&load_votes(deps.storage)?),
+        QueryAction::GetProposal { proposal_id } => to_json_binary(&ProposalResponse {
+            proposal: PROPOSALS.load(deps.storage, proposal_id)?,
+        }),
+        QueryAction::GetCitizen { address } => to_json_binary(&query_citizen(deps, address)?),
+        QueryAction::TotalWeight {} => to_json_binary(&TotalWeightResponse {
+            weight: network_weight(deps)?,
+        }),
+        QueryAction::ListSubNetworks {} => to_json_binary(&SubNetworksResponse {
+            sub_networks: SUB_NETWORKS
+                .range(deps.storage, None, None, Order::Ascending)
+                .collect::<StdResult<_>>()?,
+        }),
+        QueryAction::HistoryByProposal {
+            proposal_id,
+            start_after,
+            limit,
+        } => to_json_binary(&history::by_proposal(
+            deps,
+            proposal_id,
+            start_after,
+            limit,
+        )?),
+        QueryAction::HistoryByVoter {
+            voter,
+            start_after,
+            limit,
+        } => to_json_binary(&history::by_voter(deps, voter, start_after, limit)?),
+        QueryAction::HistoryByBlocks {
+            start_height,
+            end_height,
+            start_after,
+            limit,
+        } => to_json_binary(&history::by_blocks(
+            deps,
+            start_height,
+            end_height,
+            start_after,
+            limit,
+        )?),
+        QueryAction::GetFundingRequest { request_id } => {
+            to_json_binary(&conviction::query_request(deps, env, request_id)?)
+        }
+        QueryAction::GetBudgetRound { round_id } => {
+            to_json_binary(&budgeting::query_round(deps, round_id)?)
+        }
     }
 }
 
 fn submit_vote(
     deps: DepsMut,
-    _info: MessageInfo,
+    env: Env,
+    info: MessageInfo,
     scenario: String,
     budget: u128,
-) -> StdResult<Response> {
-    let mut votes: Votes = deps
-        .storage
-        .get(b"votes")
-        .unwrap_or_default()
-        .map(|data| from_binary(&data).unwrap());
+) -> Result<Response, ContractError> {
+    let mut votes = load_votes(deps.storage)?;
+    validate(&scenario, budget, &votes)?;
 
     for (s, b) in votes.scenario_votes.iter_mut() {
         if *s == scenario {
-            // Check that the total budget does not exceed the maximum allowed amount
-            let total_budget: u128 = votes.scenario_votes.iter().map(|(_, b)| *b).sum();
-
-            if let Some(value) = validate(&scenario, budget, &mut votes, total_budget) {
-                return value;
-            }
             *b += budget;
         }
     }
-    deps.storage.set(b"votes", &to_binary(&votes)?);
-    Ok(Response::default())
+    VOTES.save(deps.storage, &votes)?;
+    let event = HistoryEvent::BudgetVote {
+        scenario: scenario.clone(),
+        budget,
+    };
+    history::record(deps.storage, &env, &info.sender, None, event)?;
+    Ok(Response::new()
+        .add_attribute("action", "submit_vote")
+        .add_attribute("scenario", scenario)
+        .add_attribute("budget", budget.to_string()))
 }
 
-
-fn validate(scenario: &String, budget: u128, mut votes: &mut Votes, total_budget: u128) -> Option<StdResult<Response>> {
+fn validate(scenario: &str, budget: u128, votes: &Votes) -> Result<(), ContractError> {
+    // Check that the total budget does not exceed the maximum allowed amount
+    let total_budget: u128 = votes.scenario_votes.iter().map(|(_, b)| *b).sum();
     if total_budget + budget > MAX_BUDGET {
-        return Some(Err(StdError::generic_err(
-            "Total budget exceeds the maximum allowed amount",
-        )));
-    }
-
-    if let Some(value) = check_has_budget(budget) {
-        return Some(value);
+        return Err(ContractError::BudgetExceeded {});
     }
 
-    if let Some(value) = check_scenario_exists(&scenario, &mut votes) {
-        return Some(value);
-    }
-
-    if let Some(value) = check_has_not_voted_for_scenario(&scenario, &mut votes) {
-        return Some(value);
-    }
-
-    if let Some(value) = check_has_not_voted_for_other_scenarios(&scenario, &mut votes) {
-        return Some(value);
-    }
-    None
+    check_has_budget(budget)?;
+    check_scenario_exists(scenario, votes)?;
+    check_has_not_voted_for_scenario(scenario, votes)?;
+    check_has_not_voted_for_other_scenarios(scenario, votes)
 }
 
 fn check_has_not_voted_for_other_scenarios(
-    scenario: &String,
-    votes: &mut Votes,
-) -> Option<StdResult<Response>> {
+    scenario: &str,
+    votes: &Votes,
+) -> Result<(), ContractError> {
     // Check that the user has not voted for other scenarios
     if votes
         .scenario_votes
         .iter()
         .any(|(s, b)| s != scenario && *b > 0)
     {
-        Some(Err(StdError::generic_err(
-            "User has already voted for another scenario",
-        )))
-    } else {
-        None
+        return Err(ContractError::AlreadyVotedForOtherScenario {});
     }
+    Ok(())
 }
 
-fn check_has_not_voted_for_scenario(
-    scenario: &String,
-    votes: &mut Votes,
-) -> Option<StdResult<Response>> {
+fn check_has_not_voted_for_scenario(scenario: &str, votes: &Votes) -> Result<(), ContractError> {
     // Check that the user has not already voted for this scenario
     if votes
         .scenario_votes
         .iter()
         .any(|(s, b)| s == scenario && *b > 0)
     {
-        return Some(Err(StdError::generic_err(
-            "User has already voted for this scenario",
-        )));
+        return Err(ContractError::AlreadyVotedForScenario {});
     }
-    None
+    Ok(())
 }
 
-fn check_scenario_exists(scenario: &String, votes: &Votes) -> Option<StdResult<Response>> {
+fn check_scenario_exists(scenario: &str, votes: &Votes) -> Result<(), ContractError> {
     if !votes.scenario_votes.iter().any(|(s, _)| s == scenario) {
-        return Some(Err(StdError::generic_err("Scenario does not exist")));
+        return Err(ContractError::ScenarioNotFound {});
+    }
+    Ok(())
+}
+
+fn check_has_budget(budget: u128) -> Result<(), ContractError> {
+    if budget == 0 {
+        return Err(ContractError::ZeroBudget {});
+    }
+    Ok(())
+}
+
+fn propose(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    title: String,
+    kind: ProposalKind,
+    secret: Option<SecretBallot>,
+) -> Result<Response, ContractError> {
+    if !CITIZENS.has(deps.storage, &info.sender) {
+        return Err(ContractError::NotACitizen {});
+    }
+    if let Some(secret) = &secret {
+        secret::validate_ballot(secret)?;
+    }
+    let config = CONFIG.load(deps.storage)?;
+    match &kind {
+        ProposalKind::CreateSubNetwork { members, .. } => {
+            if config.sub_network_code_id.is_none() {
+                return Err(ContractError::SubNetworksDisabled {});
+            }
+            check_sub_network_room(deps.as_ref(), &config)?;
+            check_members(deps.as_ref(), members)?;
+        }
+        ProposalKind::ParentVote { proposal_id, .. } => {
+            let parent = config.parent.as_ref().ok_or(ContractError::NoParent {})?;
+            let response: ProposalResponse = deps.querier.query_wasm_smart(
+                parent,
+                &QueryMsg {
+                    action: QueryAction::GetProposal {
+                        proposal_id: *proposal_id,
+                    },
+                },
+            )?;
+            // A sub-network cannot commit and reveal as one voter, so it stays out
+            if response.proposal.secret.is_some() {
+                return Err(ContractError::ParentSecretBallot {
+                    proposal_id: *proposal_id,
+                });
+            }
+        }
+        ProposalKind::BudgetRound {
+            budget,
+            allotment,
+            limits,
+        } => budgeting::validate_round(deps.storage, *budget, *allotment, limits)?,
+    }
+
+    let id = NEXT_PROPOSAL_ID.load(deps.storage)?;
+    let event = HistoryEvent::ProposalStatus {
+        status: ProposalStatus::Open,
+    };
+    history::record(deps.storage, &env, &info.sender, Some(id), event)?;
+    let proposal = Proposal {
+        id,
+        title,
+        proposer: info.sender,
+        kind,
+        start_height: env.block.height,
+        end_height: env.block.height + config.voting_period,
+        tally: Tally::default(),
+        status: ProposalStatus::Open,
+        secret,
+        weight: Some(network_weight(deps.as_ref())?),
+    };
+    PROPOSALS.save(deps.storage, id, &proposal)?;
+    NEXT_PROPOSAL_ID.save(deps.storage, &(id + 1))?;
+
+    Ok(Response::new()
+        .add_attribute("action", "propose")
+        .add_attribute("proposal_id", id.to_string()))
+}
+
+fn cast_vote(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    proposal_id: u64,
+    vote: VoteOption,
+) -> Result<Response, ContractError> {
+    let mut proposal = PROPOSALS.load(deps.storage, proposal_id)?;
+    if proposal.status != ProposalStatus::Open || env.block.height >= proposal.end_height {
+        return Err(ContractError::VotingClosed { proposal_id });
+    }
+    if proposal.secret.is_some() {
+        return Err(ContractError::SecretBallot { proposal_id });
+    }
+    let weight = apply_ballot(deps, &env, &mut proposal, &info.sender, vote)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "cast_vote")
+        .add_attribute("proposal_id", proposal_id.to_string())
+        .add_attribute("voter", info.sender)
+        .add_attribute("weight", weight.to_string()))
+}
+
+// Counts a ballot into the tally, saves the proposal and records it in the history
+pub(crate) fn apply_ballot(
+    deps: DepsMut,
+    env: &Env,
+    proposal: &mut Proposal,
+    voter: &Addr,
+    vote: VoteOption,
+) -> Result<u64, ContractError> {
+    let proposal_id = proposal.id;
+    // Citizens may change their mind while the vote is open, keeping the weight they voted with
+    let previous = BALLOTS.may_load(deps.storage, (proposal_id, voter))?;
+    let (weight, event) = match previous {
+        Some(previous) => {
+            proposal.tally.sub(previous.vote, previous.weight)?;
+            let event = HistoryEvent::VoteChanged {
+                previous: previous.vote,
+                vote,
+                weight: previous.weight,
+            };
+            (previous.weight, event)
+        }
+        None => {
+            let weight = citizen_weight(deps.as_ref(), voter, proposal)?;
+            (weight, HistoryEvent::Ballot { vote, weight })
+        }
+    };
+    proposal.tally.add(vote, weight)?;
+    BALLOTS.save(deps.storage, (proposal_id, voter), &Ballot { vote, weight })?;
+    PROPOSALS.save(deps.storage, proposal_id, proposal)?;
+    history::record(deps.storage, env, voter, Some(proposal_id), event)?;
+    Ok(weight)
+}
+
+fn execute_proposal(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    proposal_id: u64,
+) -> Result<Response, ContractError> {
+    let mut proposal = PROPOSALS.load(deps.storage, proposal_id)?;
+    if proposal.status != ProposalStatus::Open {
+        return Err(ContractError::AlreadyFinalized { proposal_id });
+    }
+    if env.block.height < proposal.tally_height()? {
+        return Err(ContractError::VotingOpen { proposal_id });
+    }
+    let (refunds, more) =
+        secret::discard_commitments(deps.branch(), &env, &proposal, MAX_LIMIT as usize)?;
+
+    let config = CONFIG.load(deps.storage)?;
+    let weight = match proposal.weight {
+        Some(weight) => weight,
+        None => network_weight(deps.as_ref())?,
+    };
+    let quorum = config.quorum * Decimal::from_ratio(weight, 1u64);
+    let passed = Decimal::from_ratio(proposal.tally.turnout()?, 1u64) >= quorum
+        && proposal.tally.yes > proposal.tally.no;
+    let response = Response::new()
+        .add_attribute("action", "execute_proposal")
+        .add_attribute("proposal_id", proposal_id.to_string())
+        .add_attribute("passed", passed.to_string())
+        .add_attribute("more_commitments", more.to_string())
+        .add_messages(refunds);
+    // Members may have moved or the network filled up since the proposal opened, the
+    // proposal fails then rather than staying open for good
+    let failure = match &proposal.kind {
+        ProposalKind::CreateSubNetwork { members, .. } if passed => {
+            check_sub_network_room(deps.as_ref(), &config)
+                .and_then(|_| check_members(deps.as_ref(), members))
+                .err()
+        }
+        _ => None,
+    };
+    proposal.status = match (passed, &failure) {
+        (false, _) => ProposalStatus::Rejected,
+        (true, Some(_)) => ProposalStatus::Failed,
+        (true, None) => ProposalStatus::Executed,
+    };
+    PROPOSALS.save(deps.storage, proposal_id, &proposal)?;
+    let event = HistoryEvent::ProposalStatus {
+        status: proposal.status.clone(),
+    };
+    history::record(deps.storage, &env, &info.sender, Some(proposal_id), event)?;
+    if let Some(err) = failure {
+        return Ok(response.add_attribute("failure", err.to_string()));
+    }
+    if !passed {
+        return Ok(response);
+    }
+
+    match proposal.kind {
+        ProposalKind::CreateSubNetwork {
+            label,
+            scenarios,
+            members,
+        } => {
+            let code_id = config
+                .sub_network_code_id
+                .ok_or(ContractError::SubNetworksDisabled {})?;
+            // Members move into the sub-network, from now on their weight reaches
+            // this network only through it
+            let moved: u64 = members.iter().map(|member| member.weight).sum();
+            for member in &members {
+                CITIZENS.remove(deps.storage, &deps.api.addr_validate(&member.addr)?);
+            }
+            TOTAL_WEIGHT.update(deps.storage, |weight| -> StdResult<_> {
+                Ok(weight - moved)
+            })?;
+            let instantiate = WasmMsg::Instantiate {
+                admin: Some(env.contract.address.to_string()),
+                code_id,
+                msg: to_json_binary(&InstantiateMsg {
+                    scenarios,
+                    members,
+                    parent: Some(env.contract.address.to_string()),
+                    depth: config.depth + 1,
+                    sub_network_code_id: config.sub_network_code_id,
+                    voting_period: Some(config.voting_period),
+                    quorum: Some(config.quorum),
+                    conviction: None,
+                })?,
+                funds: vec![],
+                label,
+            };
+            Ok(response.add_submessage(
+                SubMsg::reply_on_success(instantiate, REPLY_SUB_NETWORK)
+                    .with_payload(to_json_vec(&proposal_id)?),
+            ))
+        }
+        ProposalKind::ParentVote {
+            proposal_id: parent_proposal_id,
+            vote,
+        } => {
+            let parent = config.parent.ok_or(ContractError::NoParent {})?;
+            let vote = WasmMsg::Execute {
+                contract_addr: parent.to_string(),
+                msg: to_json_binary(&ExecuteMsg {
+                    action: Action::CastVote {
+                        proposal_id: parent_proposal_id,
+                        vote,
+                    },
+                })?,
+                funds: vec![],
+            };
+            // The parent's vote may have closed in the meantime
+            Ok(response.add_submessage(
+                SubMsg::reply_on_error(vote, REPLY_PARENT_VOTE)
+                    .with_payload(to_json_vec(&proposal_id)?),
+            ))
+        }
+        ProposalKind::BudgetRound {
+            budget,
+            allotment,
+            limits,
+        } => {
+            let round_id = budgeting::open_round(
+                deps.storage,
+                &env,
+                proposal_id,
+                config.voting_period,
+                budget,
+                allotment,
+                limits,
+            )?;
+            Ok(response.add_attribute("round_id", round_id.to_string()))
+        }
+    }
+}
+
+// Sub-network members must be distinct individual citizens here with their weight
+// here, so every address holds its weight in exactly one network of the tree.
+// Returns the weight they take along
+fn check_members(deps: Deps, members: &[Member]) -> Result<u64, ContractError> {
+    let mut seen = BTreeSet::new();
+    let mut weight = 0;
+    for member in members {
+        let address = deps.api.addr_validate(&member.addr)?;
+        let citizen = CITIZENS.may_load(deps.storage, &address)?;
+        let valid = citizen.is_some_and(|citizen| {
+            citizen.kind == CitizenKind::Individual && citizen.weight == member.weight
+        });
+        if !valid || !seen.insert(address) {
+            return Err(ContractError::InvalidMember {
+                address: member.addr.clone(),
+            });
+        }
+        weight += member.weight;
+    }
+    Ok(weight)
+}
+
+fn check_sub_network_room(deps: Deps, config: &Config) -> Result<(), ContractError> {
+    if config.depth >= MAX_NETWORK_DEPTH {
+        return Err(ContractError::SubNetworkTooDeep {
+            max: MAX_NETWORK_DEPTH,
+        });
     }
-    None
+    let count = SUB_NETWORKS
+        .keys(deps.storage, None, None, Order::Ascending)
+        .take(MAX_SUB_NETWORKS)
+        .count();
+    if count >= MAX_SUB_NETWORKS {
+        return Err(ContractError::TooManySubNetworks {
+            max: MAX_SUB_NETWORKS,
+        });
+    }
+    Ok(())
+}
+
+fn register_sub_network(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    let proposal_id: u64 = from_json(&msg.payload)?;
+    let response = msg.result.into_result().map_err(StdError::generic_err)?;
+    let address = response
+        .events
+        .iter()
+        .filter(|event| event.ty == "instantiate")
+        .flat_map(|event| event.attributes.iter())
+        .find(|attr| attr.key == "_contract_address")
+        .map(|attr| deps.api.addr_validate(&attr.value))
+        .transpose()?
+        .ok_or_else(|| StdError::generic_err("Sub-network address missing from reply"))?;
+
+    let label = match PROPOSALS.load(deps.storage, proposal_id)?.kind {
+        ProposalKind::CreateSubNetwork { label, .. } => label,
+        _ => return Err(StdError::generic_err("Proposal did not create a sub-network").into()),
+    };
+    let citizen = Citizen {
+        kind: CitizenKind::SubNetwork,
+        weight: 0,
+    };
+    CITIZENS.save(deps.storage, &address, &citizen)?;
+    let sub_network = SubNetwork {
+        label,
+        proposal_id,
+        height: env.block.height,
+    };
+    SUB_NETWORKS.save(deps.storage, &address, &sub_network)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "register_sub_network")
+        .add_attribute("proposal_id", proposal_id.to_string())
+        .add_attribute("sub_network", address))
 }
 
-fn check_has_budget(budget: u128) -> Option<StdResult<Response>> {
-    if budget <= 0 {
-        return Some(Err(StdError::generic_err(
-            "Budget must be greater than zero",
-        )));
+// The parent refused the vote, the proposal stays finalized as failed
+fn fail_parent_vote(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    let proposal_id: u64 = from_json(&msg.payload)?;
+    let failure = match msg.result.into_result() {
+        Ok(_) => return Err(StdError::generic_err("Parent vote did not fail").into()),
+        Err(err) => err,
+    };
+    let mut proposal = PROPOSALS.load(deps.storage, proposal_id)?;
+    proposal.status = ProposalStatus::Failed;
+    PROPOSALS.save(deps.storage, proposal_id, &proposal)?;
+    let event = HistoryEvent::ProposalStatus {
+        status: ProposalStatus::Failed,
+    };
+    history::record(
+        deps.storage,
+        &env,
+        &env.contract.address,
+        Some(proposal_id),
+        event,
+    )?;
+
+    Ok(Response::new()
+        .add_attribute("action", "fail_parent_vote")
+        .add_attribute("proposal_id", proposal_id.to_string())
+        .add_attribute("failure", failure))
+}
+
+// Members who moved into a sub-network may have voted here already, so a sub-network
+// only votes on proposals opened after it joined
+fn citizen_weight(deps: Deps, address: &Addr, proposal: &Proposal) -> Result<u64, ContractError> {
+    let citizen = CITIZENS
+        .may_load(deps.storage, address)?
+        .ok_or(ContractError::NotACitizen {})?;
+    match citizen.kind {
+        CitizenKind::Individual => Ok(citizen.weight),
+        CitizenKind::SubNetwork => {
+            if SUB_NETWORKS.load(deps.storage, address)?.height >= proposal.start_height {
+                return Err(ContractError::JoinedAfterProposal {
+                    proposal_id: proposal.id,
+                });
+            }
+            Ok(sub_network_weight(deps, address)?)
+        }
     }
-    None
 }
 
-fn get_votes(deps: Deps) -> Vec<u8> {
-    deps.storage.get(b"votes").unwrap_or_default()
+// A sub-network votes with the aggregated weight of its own members
+fn sub_network_weight(deps: Deps, address: &Addr) -> StdResult<u64> {
+    let response: TotalWeightResponse = deps.querier.query_wasm_smart(
+        address,
+        &QueryMsg {
+            action: QueryAction::TotalWeight {},
+        },
+    )?;
+    Ok(response.weight)
 }
 
+// Individual citizens plus the sub-networks, at most MAX_SUB_NETWORKS queries
+fn network_weight(deps: Deps) -> StdResult<u64> {
+    let mut weight = TOTAL_WEIGHT.load(deps.storage)?;
+    for address in SUB_NETWORKS.keys(deps.storage, None, None, Order::Ascending) {
+        weight = weight
+            .checked_add(sub_network_weight(deps, &address?)?)
+            .ok_or_else(|| StdError::generic_err("Total weight overflows"))?;
+    }
+    Ok(weight)
+}
+
+fn query_citizen(deps: Deps, address: String) -> StdResult<CitizenResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let citizen = CITIZENS.load(deps.storage, &address)?;
+    let weight = match citizen.kind {
+        CitizenKind::Individual => citizen.weight,
+        CitizenKind::SubNetwork => sub_network_weight(deps, &address)?,
+    };
+    Ok(CitizenResponse {
+        address,
+        kind: citizen.kind,
+        weight,
+    })
+}
 
 #[cfg(test)]
 mod tests {
+    use crate::democracy::{
+        execute, instantiate, query, reply, MAX_NETWORK_DEPTH, MAX_SUB_NETWORKS, REPLY_PARENT_VOTE,
+        REPLY_SUB_NETWORK,
+    };
+    use crate::error::ContractError;
+    use crate::history::MAX_LIMIT;
+    use crate::msg::{
+        Action, BudgetRoundResponse, CitizenResponse, ExecuteMsg, FundingRequestResponse,
+        HistoryResponse, InstantiateMsg, Member, ProposalKind, ProposalResponse, QueryAction,
+        QueryMsg, SecretBallot, TotalWeightResponse, VoteOption, Votes,
+    };
+    use crate::secret::{commitment, MAX_REVEAL_PERIOD};
+    use crate::state::{
+        load_votes, CitizenKind, ConvictionConfig, HistoryEvent, Proposal, ProposalStatus,
+        ScenarioLimits, Tally, CONFIG, PROPOSALS,
+    };
     use cosmwasm_std::{
-        from_binary,
-        testing::{mock_dependencies, mock_env, mock_info},
+        coins, from_json,
+        testing::{
+            message_info, mock_dependencies, mock_dependencies_with_balance, mock_env, MockApi,
+        },
+        to_json_binary, Addr, BankMsg, ContractResult, CosmosMsg, Decimal, Deps, Event, Reply,
+        ReplyOn, SubMsgResponse, SubMsgResult, SystemResult, Uint128, WasmMsg, WasmQuery,
     };
-    use crate::democracy::{execute, instantiate, query};
-    use crate::msg::{ExecuteMsg, InstantiateMsg, QueryAction, QueryMsg, Votes};
+
+    fn scenarios() -> Vec<String> {
+        vec!["Scenario 1".to_string(), "Scenario 2".to_string()]
+    }
+
+    fn init_msg(api: &MockApi) -> InstantiateMsg {
+        InstantiateMsg {
+            scenarios: scenarios(),
+            members: vec![
+                Member {
+                    addr: api.addr_make("alice").to_string(),
+                    weight: 3,
+                },
+                Member {
+                    addr: api.addr_make("bob").to_string(),
+                    weight: 2,
+                },
+            ],
+            parent: None,
+            depth: 0,
+            sub_network_code_id: Some(7),
+            voting_period: Some(10),
+            quorum: None,
+            conviction: None,
+        }
+    }
+
+    fn act(action: Action) -> ExecuteMsg {
+        ExecuteMsg { action }
+    }
 
     #[test]
     fn test_instantiate() {
         let mut deps = mock_dependencies();
 
         let msg = InstantiateMsg {
-            scenarios: vec!["Scenario 1".to_string(), "Scenario 2".to_string()],
+            scenarios: scenarios(),
+            members: vec![],
+            parent: None,
+            depth: 0,
+            sub_network_code_id: None,
+            voting_period: None,
+            quorum: None,
+            conviction: None,
         };
-        let info = mock_info("creator", &[]);
+        let info = message_info(&deps.api.addr_make("creator"), &[]);
 
         let res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
         assert_eq!(res.attributes[0].value, "instantiate");
@@ -177,6 +772,18 @@ mod tests {
         let votes = load_votes(&deps.storage).unwrap();
         assert_eq!(votes.scenario_votes.len(), 2);
         assert_eq!(votes.scenario_votes[0], ("Scenario 1".to_string(), 0));
+
+        // Listing a member twice would count its weight twice
+        let mut msg = init_msg(&deps.api);
+        msg.members[1].addr = msg.members[0].addr.clone();
+        let info = message_info(&deps.api.addr_make("creator"), &[]);
+        let mut deps = mock_dependencies();
+        let err = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+        assert!(matches!(err, ContractError::DuplicateMember { .. }));
+        let mut msg = init_msg(&deps.api);
+        msg.members[1].weight = u64::MAX;
+        let mut deps = mock_dependencies();
+        instantiate(deps.as_mut(), mock_env(), info, msg).unwrap_err();
     }
 
     #[test]
@@ -184,20 +791,16 @@ mod tests {
         let mut deps = mock_dependencies();
 
         // Инициализация
-        let msg = InstantiateMsg {
-            scenarios: vec!["Scenario 1".to_string(), "Scenario 2".to_string()],
-        };
-        let info = mock_info("creator", &[]);
+        let msg = init_msg(&deps.api);
+        let info = message_info(&deps.api.addr_make("creator"), &[]);
         instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
 
         // Успешное голосование
-        let vote_msg = ExecuteMsg {
-            action: Action::SubmitVote {
-                scenario: "Scenario 1".to_string(),
-                budget: 100,
-            },
-        };
-        let info = mock_info("voter1", &[]);
+        let vote_msg = act(Action::SubmitVote {
+            scenario: "Scenario 1".to_string(),
+            budget: 100,
+        });
+        let info = message_info(&deps.api.addr_make("voter1"), &[]);
         let res = execute(deps.as_mut(), mock_env(), info, vote_msg).unwrap();
         assert_eq!(res.attributes[0].value, "submit_vote");
 
@@ -205,44 +808,1208 @@ mod tests {
         assert_eq!(votes.scenario_votes[0], ("Scenario 1".to_string(), 100));
 
         // Ошибка: превышение бюджета
-        let vote_msg = ExecuteMsg {
-            action: Action::SubmitVote {
-                scenario: "Scenario 2".to_string(),
-                budget: 1001,
-            },
-        };
-        let info = mock_info("voter2", &[]);
-        let err = execute(deps.as_mut(), mock_env(), info, vote_msg).unwrap_err();
-        assert_eq!(err.to_string(), ERR_BUDGET_EXCEEDED);
+        let vote_msg = act(Action::SubmitVote {
+            scenario: "Scenario 2".to_string(),
+            budget: 1001,
+        });
+        let info = message_info(&deps.api.addr_make("voter2"), &[]);
+        let err = execute(deps.as_mut(), mock_env(), info.clone(), vote_msg).unwrap_err();
+        assert!(matches!(err, ContractError::BudgetExceeded {}));
 
         // Ошибка: голос за несуществующий сценарий
-        let vote_msg = ExecuteMsg {
-            action: Action::SubmitVote {
-                scenario: "Invalid".to_string(),
-                budget: 50,
-            },
-        };
+        let vote_msg = act(Action::SubmitVote {
+            scenario: "Invalid".to_string(),
+            budget: 50,
+        });
         let err = execute(deps.as_mut(), mock_env(), info, vote_msg).unwrap_err();
-        assert_eq!(err.to_string(), ERR_SCENARIO_NOT_FOUND);
+        assert!(matches!(err, ContractError::ScenarioNotFound {}));
     }
 
     #[test]
     fn test_query_votes() {
         let mut deps = mock_dependencies();
 
-        let msg = InstantiateMsg {
-            scenarios: vec!["Scenario 1".to_string(), "Scenario 2".to_string()],
-        };
-        let info = mock_info("creator", &[]);
+        let msg = init_msg(&deps.api);
+        let info = message_info(&deps.api.addr_make("creator"), &[]);
         instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
 
         let query_msg = QueryMsg {
-            action: QueryAction::GetVotes,
+            action: QueryAction::GetVotes {},
         };
         let bin = query(deps.as_ref(), mock_env(), query_msg).unwrap();
-        let votes: Votes = from_binary(&bin).unwrap();
+        let votes: Votes = from_json(&bin).unwrap();
 
         assert_eq!(votes.scenario_votes.len(), 2);
         assert_eq!(votes.scenario_votes[0], ("Scenario 1".to_string(), 0));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_vote_change_and_rejection() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let alice = message_info(&deps.api.addr_make("alice"), &[]);
+        let bob = message_info(&deps.api.addr_make("bob"), &[]);
+        let msg = init_msg(&deps.api);
+        instantiate(deps.as_mut(), env.clone(), alice.clone(), msg).unwrap();
+
+        let propose = act(Action::Propose {
+            title: "Empty parent vote".to_string(),
+            kind: ProposalKind::ParentVote {
+                proposal_id: 0,
+                vote: VoteOption::Yes,
+            },
+            secret: None,
+        });
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), propose).unwrap_err();
+        assert!(matches!(err, ContractError::NoParent {}));
+
+        let propose = act(Action::Propose {
+            title: "Spawn".to_string(),
+            kind: ProposalKind::CreateSubNetwork {
+                label: "child".to_string(),
+                scenarios: scenarios(),
+                members: vec![],
+            },
+            secret: None,
+        });
+        let stranger = message_info(&deps.api.addr_make("stranger"), &[]);
+        let err = execute(deps.as_mut(), env.clone(), stranger, propose.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NotACitizen {}));
+        execute(deps.as_mut(), env.clone(), alice.clone(), propose).unwrap();
+
+        let vote = |vote| {
+            act(Action::CastVote {
+                proposal_id: 0,
+                vote,
+            })
+        };
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            alice.clone(),
+            vote(VoteOption::Yes),
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            bob.clone(),
+            vote(VoteOption::No),
+        )
+        .unwrap();
+        // Alice changes her mind, the tally follows
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            alice.clone(),
+            vote(VoteOption::No),
+        )
+        .unwrap();
+
+        let finalize = act(Action::ExecuteProposal { proposal_id: 0 });
+        let err = execute(deps.as_mut(), env.clone(), bob.clone(), finalize.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::VotingOpen { proposal_id: 0 }));
+
+        env.block.height += 10;
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            bob.clone(),
+            vote(VoteOption::Yes),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::VotingClosed { proposal_id: 0 }
+        ));
+
+        let res = execute(deps.as_mut(), env, bob, finalize).unwrap();
+        assert_eq!(res.attributes[2].value, "false");
+        assert!(res.messages.is_empty());
+    }
+
+    #[test]
+    fn test_create_sub_network() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let alice = message_info(&deps.api.addr_make("alice"), &[]);
+        let msg = init_msg(&deps.api);
+        instantiate(deps.as_mut(), env.clone(), alice.clone(), msg).unwrap();
+
+        let bob = message_info(&deps.api.addr_make("bob"), &[]);
+        let member = |name: &str, weight| Member {
+            addr: deps.api.addr_make(name).to_string(),
+            weight,
+        };
+        let spawn = |label: &str, members| {
+            act(Action::Propose {
+                title: "Spawn".to_string(),
+                kind: ProposalKind::CreateSubNetwork {
+                    label: label.to_string(),
+                    scenarios: scenarios(),
+                    members,
+                },
+                secret: None,
+            })
+        };
+        // Members must be citizens here, listed once with the weight they hold here
+        let strangers = spawn("child", vec![member("carol", 4)]);
+        let inflated = spawn("child", vec![member("bob", 5)]);
+        let twice = spawn("child", vec![member("bob", 2), member("bob", 2)]);
+        let child_members = vec![member("bob", 2)];
+        let propose = spawn("child", child_members.clone());
+        for invalid in [strangers, inflated, twice] {
+            let err = execute(deps.as_mut(), env.clone(), alice.clone(), invalid).unwrap_err();
+            assert!(matches!(err, ContractError::InvalidMember { .. }));
+        }
+        execute(deps.as_mut(), env.clone(), alice.clone(), propose).unwrap();
+        let vote = act(Action::CastVote {
+            proposal_id: 0,
+            vote: VoteOption::Yes,
+        });
+        execute(deps.as_mut(), env.clone(), alice.clone(), vote).unwrap();
+
+        env.block.height += 10;
+        let finalize = act(Action::ExecuteProposal { proposal_id: 0 });
+        let res = execute(deps.as_mut(), env.clone(), alice.clone(), finalize).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        match &res.messages[0].msg {
+            CosmosMsg::Wasm(WasmMsg::Instantiate { code_id, msg, .. }) => {
+                assert_eq!(*code_id, 7);
+                let child: InstantiateMsg = from_json(msg).unwrap();
+                assert_eq!(child.members, child_members);
+                assert_eq!(child.parent, Some(env.contract.address.to_string()));
+                assert_eq!(child.depth, 1);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+
+        let child = deps.api.addr_make("child");
+        #[allow(deprecated)]
+        let result = SubMsgResult::Ok(SubMsgResponse {
+            events: vec![Event::new("instantiate").add_attribute("_contract_address", &child)],
+            data: None,
+            msg_responses: vec![],
+        });
+        let reply_msg = Reply {
+            id: REPLY_SUB_NETWORK,
+            payload: res.messages[0].payload.clone(),
+            gas_used: 0,
+            result,
+        };
+        reply(deps.as_mut(), env.clone(), reply_msg).unwrap();
+
+        // Bob moved into the child, which votes with his weight, later children are empty
+        let child_addr = child.clone();
+        deps.querier.update_wasm(move |query| match query {
+            WasmQuery::Smart { contract_addr, .. } => {
+                let weight = if *contract_addr == child_addr.to_string() {
+                    2
+                } else {
+                    0
+                };
+                SystemResult::Ok(ContractResult::Ok(
+                    to_json_binary(&TotalWeightResponse { weight }).unwrap(),
+                ))
+            }
+            _ => panic!("unexpected query"),
+        });
+        let query_msg = QueryMsg {
+            action: QueryAction::GetCitizen {
+                address: child.to_string(),
+            },
+        };
+        let citizen: CitizenResponse =
+            from_json(query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
+        assert_eq!(citizen.kind, CitizenKind::SubNetwork);
+        assert_eq!(citizen.weight, 2);
+
+        // Bob counts once, through the child
+        let query_msg = QueryMsg {
+            action: QueryAction::TotalWeight {},
+        };
+        let total: TotalWeightResponse =
+            from_json(query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
+        assert_eq!(total.weight, 5);
+
+        // Bob may have voted on proposals opened before the child joined
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            alice.clone(),
+            spawn("early", vec![]),
+        )
+        .unwrap();
+        let vote = |proposal_id| {
+            act(Action::CastVote {
+                proposal_id,
+                vote: VoteOption::Yes,
+            })
+        };
+        let child_info = message_info(&child, &[]);
+        let err = execute(deps.as_mut(), env.clone(), child_info.clone(), vote(1)).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::JoinedAfterProposal { proposal_id: 1 }
+        ));
+
+        env.block.height += 1;
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            alice.clone(),
+            spawn("late", vec![]),
+        )
+        .unwrap();
+        let res = execute(deps.as_mut(), env.clone(), child_info, vote(2)).unwrap();
+        assert_eq!(res.attributes[3].value, "2");
+        let err = execute(deps.as_mut(), env.clone(), bob, vote(2)).unwrap_err();
+        assert!(matches!(err, ContractError::NotACitizen {}));
+
+        // Fill up the network with empty children
+        for (i, proposal_id) in (3..3 + MAX_SUB_NETWORKS as u64 - 1).enumerate() {
+            let label = format!("child {}", i);
+            execute(
+                deps.as_mut(),
+                env.clone(),
+                alice.clone(),
+                spawn(&label, vec![]),
+            )
+            .unwrap();
+            execute(deps.as_mut(), env.clone(), alice.clone(), vote(proposal_id)).unwrap();
+            env.block.height += 10;
+            let finalize = act(Action::ExecuteProposal { proposal_id });
+            let res = execute(deps.as_mut(), env.clone(), alice.clone(), finalize).unwrap();
+            let address = deps.api.addr_make(&label);
+            #[allow(deprecated)]
+            let result = SubMsgResult::Ok(SubMsgResponse {
+                events: vec![Event::new("instantiate").add_attribute("_contract_address", &address)],
+                data: None,
+                msg_responses: vec![],
+            });
+            let reply_msg = Reply {
+                id: REPLY_SUB_NETWORK,
+                payload: res.messages[0].payload.clone(),
+                gas_used: 0,
+                result,
+            };
+            reply(deps.as_mut(), env.clone(), reply_msg).unwrap();
+        }
+        let err = execute(deps.as_mut(), env.clone(), alice, spawn("full", vec![])).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::TooManySubNetworks {
+                max: MAX_SUB_NETWORKS
+            }
+        ));
+        let query_msg = QueryMsg {
+            action: QueryAction::TotalWeight {},
+        };
+        let total: TotalWeightResponse =
+            from_json(query(deps.as_ref(), env, query_msg).unwrap()).unwrap();
+        assert_eq!(total.weight, 5);
+
+        // Networks at the deepest level spawn no further sub-networks
+        let mut deps = mock_dependencies();
+        let mut msg = init_msg(&deps.api);
+        msg.depth = MAX_NETWORK_DEPTH;
+        let alice = message_info(&deps.api.addr_make("alice"), &[]);
+        instantiate(deps.as_mut(), mock_env(), alice.clone(), msg).unwrap();
+        let spawn = act(Action::Propose {
+            title: "Spawn".to_string(),
+            kind: ProposalKind::CreateSubNetwork {
+                label: "deep".to_string(),
+                scenarios: scenarios(),
+                members: vec![],
+            },
+            secret: None,
+        });
+        let err = execute(deps.as_mut(), mock_env(), alice, spawn).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::SubNetworkTooDeep {
+                max: MAX_NETWORK_DEPTH
+            }
+        ));
+    }
+
+    #[test]
+    fn test_failed_actions() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let alice = message_info(&deps.api.addr_make("alice"), &[]);
+        let msg = init_msg(&deps.api);
+        instantiate(deps.as_mut(), env.clone(), alice.clone(), msg).unwrap();
+
+        // Both proposals move bob, only the first one executed can
+        for label in ["first", "second"] {
+            let propose = act(Action::Propose {
+                title: "Spawn".to_string(),
+                kind: ProposalKind::CreateSubNetwork {
+                    label: label.to_string(),
+                    scenarios: scenarios(),
+                    members: vec![Member {
+                        addr: deps.api.addr_make("bob").to_string(),
+                        weight: 2,
+                    }],
+                },
+                secret: None,
+            });
+            execute(deps.as_mut(), env.clone(), alice.clone(), propose).unwrap();
+        }
+        for proposal_id in [0, 1] {
+            let vote = act(Action::CastVote {
+                proposal_id,
+                vote: VoteOption::Yes,
+            });
+            execute(deps.as_mut(), env.clone(), alice.clone(), vote).unwrap();
+        }
+
+        env.block.height += 10;
+        let finalize = |proposal_id| act(Action::ExecuteProposal { proposal_id });
+        let res = execute(deps.as_mut(), env.clone(), alice.clone(), finalize(0)).unwrap();
+        let child = deps.api.addr_make("first");
+        #[allow(deprecated)]
+        let result = SubMsgResult::Ok(SubMsgResponse {
+            events: vec![Event::new("instantiate").add_attribute("_contract_address", &child)],
+            data: None,
+            msg_responses: vec![],
+        });
+        let reply_msg = Reply {
+            id: REPLY_SUB_NETWORK,
+            payload: res.messages[0].payload.clone(),
+            gas_used: 0,
+            result,
+        };
+        reply(deps.as_mut(), env.clone(), reply_msg).unwrap();
+
+        // The child has grown since, the quorum is still taken of the weight at the
+        // time the proposal opened
+        deps.querier.update_wasm(|_| {
+            SystemResult::Ok(ContractResult::Ok(
+                to_json_binary(&TotalWeightResponse { weight: 100 }).unwrap(),
+            ))
+        });
+        let res = execute(deps.as_mut(), env.clone(), alice, finalize(1)).unwrap();
+        assert_eq!(res.attributes[2].value, "true");
+        assert!(res.attributes.iter().any(|attr| attr.key == "failure"));
+        assert!(res.messages.is_empty());
+        let proposal = PROPOSALS.load(&deps.storage, 1).unwrap();
+        assert_eq!(proposal.status, ProposalStatus::Failed);
+    }
+
+    #[test]
+    fn test_sub_network_votes_in_parent() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let parent = deps.api.addr_make("parent");
+        let carol = message_info(&deps.api.addr_make("carol"), &[]);
+        let msg = InstantiateMsg {
+            scenarios: scenarios(),
+            members: vec![Member {
+                addr: carol.sender.to_string(),
+                weight: 4,
+            }],
+            parent: Some(parent.to_string()),
+            depth: 1,
+            sub_network_code_id: None,
+            voting_period: Some(10),
+            quorum: None,
+            conviction: None,
+        };
+        instantiate(deps.as_mut(), env.clone(), message_info(&parent, &[]), msg).unwrap();
+        assert_eq!(
+            CONFIG.load(&deps.storage).unwrap().parent,
+            Some(parent.clone())
+        );
+
+        // Parent proposal 4 uses secret ballots
+        let parent_addr = parent.clone();
+        deps.querier.update_wasm(move |query| match query {
+            WasmQuery::Smart { contract_addr, msg }
+                if *contract_addr == parent_addr.to_string() =>
+            {
+                let proposal_id = match from_json::<QueryMsg>(msg).unwrap().action {
+                    QueryAction::GetProposal { proposal_id } => proposal_id,
+                    other => panic!("unexpected query {:?}", other),
+                };
+                let secret = SecretBallot {
+                    reveal_period: 5,
+                    deposit: None,
+                    forfeit: false,
+                };
+                let proposal = Proposal {
+                    id: proposal_id,
+                    title: "Parent".to_string(),
+                    proposer: parent_addr.clone(),
+                    kind: ProposalKind::ParentVote {
+                        proposal_id: 0,
+                        vote: VoteOption::No,
+                    },
+                    start_height: 0,
+                    end_height: 10,
+                    tally: Tally::default(),
+                    status: ProposalStatus::Open,
+                    secret: Some(secret).filter(|_| proposal_id == 4),
+                    weight: None,
+                };
+                SystemResult::Ok(ContractResult::Ok(
+                    to_json_binary(&ProposalResponse { proposal }).unwrap(),
+                ))
+            }
+            _ => panic!("unexpected query"),
+        });
+        let propose = act(Action::Propose {
+            title: "Back the parent in secret".to_string(),
+            kind: ProposalKind::ParentVote {
+                proposal_id: 4,
+                vote: VoteOption::Yes,
+            },
+            secret: None,
+        });
+        let err = execute(deps.as_mut(), env.clone(), carol.clone(), propose).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::ParentSecretBallot { proposal_id: 4 }
+        ));
+
+        let propose = act(Action::Propose {
+            title: "Back the parent".to_string(),
+            kind: ProposalKind::ParentVote {
+                proposal_id: 3,
+                vote: VoteOption::Yes,
+            },
+            secret: None,
+        });
+        execute(deps.as_mut(), env.clone(), carol.clone(), propose).unwrap();
+        let vote = act(Action::CastVote {
+            proposal_id: 0,
+            vote: VoteOption::Yes,
+        });
+        execute(deps.as_mut(), env.clone(), carol.clone(), vote).unwrap();
+
+        env.block.height += 10;
+        let finalize = act(Action::ExecuteProposal { proposal_id: 0 });
+        let res = execute(deps.as_mut(), env.clone(), carol, finalize).unwrap();
+        assert_eq!(res.messages[0].reply_on, ReplyOn::Error);
+        match &res.messages[0].msg {
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr, msg, ..
+            }) => {
+                assert_eq!(Addr::unchecked(contract_addr), parent);
+                let forwarded: ExecuteMsg = from_json(msg).unwrap();
+                assert_eq!(
+                    forwarded.action,
+                    Action::CastVote {
+                        proposal_id: 3,
+                        vote: VoteOption::Yes
+                    }
+                );
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+
+        // The parent's vote closed before the vote arrived
+        let reply_msg = Reply {
+            id: REPLY_PARENT_VOTE,
+            payload: res.messages[0].payload.clone(),
+            gas_used: 0,
+            result: SubMsgResult::Err("Voting on proposal 3 is closed".to_string()),
+        };
+        reply(deps.as_mut(), env, reply_msg).unwrap();
+        let proposal = PROPOSALS.load(&deps.storage, 0).unwrap();
+        assert_eq!(proposal.status, ProposalStatus::Failed);
+    }
+
+    #[test]
+    fn test_vote_history() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let alice = message_info(&deps.api.addr_make("alice"), &[]);
+        let bob = message_info(&deps.api.addr_make("bob"), &[]);
+        let msg = init_msg(&deps.api);
+        instantiate(deps.as_mut(), env.clone(), alice.clone(), msg).unwrap();
+
+        env.block.height += 1;
+        let propose = act(Action::Propose {
+            title: "Spawn".to_string(),
+            kind: ProposalKind::CreateSubNetwork {
+                label: "child".to_string(),
+                scenarios: scenarios(),
+                members: vec![],
+            },
+            secret: None,
+        });
+        execute(deps.as_mut(), env.clone(), alice.clone(), propose).unwrap();
+
+        env.block.height += 1;
+        let vote = |vote| {
+            act(Action::CastVote {
+                proposal_id: 0,
+                vote,
+            })
+        };
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            alice.clone(),
+            vote(VoteOption::Yes),
+        )
+        .unwrap();
+        assert_eq!(res.attributes[3].value, "3");
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            bob.clone(),
+            vote(VoteOption::No),
+        )
+        .unwrap();
+        env.block.height += 1;
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            alice.clone(),
+            vote(VoteOption::Abstain),
+        )
+        .unwrap();
+
+        let query_msg = QueryMsg {
+            action: QueryAction::GetProposal { proposal_id: 0 },
+        };
+        let res: ProposalResponse =
+            from_json(query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
+        assert_eq!(
+            res.proposal.tally,
+            Tally {
+                yes: 0,
+                no: 2,
+                abstain: 3
+            }
+        );
+
+        env.block.height += 10;
+        let finalize = act(Action::ExecuteProposal { proposal_id: 0 });
+        execute(deps.as_mut(), env.clone(), bob.clone(), finalize).unwrap();
+
+        let history = |action| -> HistoryResponse {
+            from_json(query(deps.as_ref(), mock_env(), QueryMsg { action }).unwrap()).unwrap()
+        };
+        let by_proposal = history(QueryAction::HistoryByProposal {
+            proposal_id: 0,
+            start_after: None,
+            limit: None,
+        });
+        let events: Vec<_> = by_proposal
+            .entries
+            .iter()
+            .map(|e| e.event.clone())
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                HistoryEvent::ProposalStatus {
+                    status: ProposalStatus::Open
+                },
+                HistoryEvent::Ballot {
+                    vote: VoteOption::Yes,
+                    weight: 3
+                },
+                HistoryEvent::Ballot {
+                    vote: VoteOption::No,
+                    weight: 2
+                },
+                HistoryEvent::VoteChanged {
+                    previous: VoteOption::Yes,
+                    vote: VoteOption::Abstain,
+                    weight: 3
+                },
+                HistoryEvent::ProposalStatus {
+                    status: ProposalStatus::Rejected
+                },
+            ]
+        );
+
+        let by_voter = history(QueryAction::HistoryByVoter {
+            voter: bob.sender.to_string(),
+            start_after: None,
+            limit: None,
+        });
+        assert_eq!(by_voter.entries.len(), 2);
+        assert_eq!(
+            by_voter.entries[0].event,
+            HistoryEvent::Ballot {
+                vote: VoteOption::No,
+                weight: 2
+            }
+        );
+
+        let start = mock_env().block.height + 1;
+        let by_blocks = history(QueryAction::HistoryByBlocks {
+            start_height: start,
+            end_height: start + 1,
+            start_after: None,
+            limit: Some(2),
+        });
+        assert_eq!(by_blocks.entries.len(), 2);
+        let next = history(QueryAction::HistoryByBlocks {
+            start_height: start,
+            end_height: start + 1,
+            start_after: Some(by_blocks.entries[1].id),
+            limit: None,
+        });
+        assert_eq!(next.entries.len(), 1);
+        assert_eq!(next.entries[0].actor, bob.sender);
+    }
+
+    #[test]
+    fn test_commit_reveal() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let alice = message_info(&deps.api.addr_make("alice"), &[]);
+        let bob = message_info(&deps.api.addr_make("bob"), &[]);
+        let msg = init_msg(&deps.api);
+        instantiate(deps.as_mut(), env.clone(), alice.clone(), msg).unwrap();
+
+        let propose = |reveal_period, deposit| {
+            act(Action::Propose {
+                title: "Spawn in secret".to_string(),
+                kind: ProposalKind::CreateSubNetwork {
+                    label: "child".to_string(),
+                    scenarios: scenarios(),
+                    members: vec![],
+                },
+                secret: Some(SecretBallot {
+                    reveal_period,
+                    deposit: Some(coins(deposit, "ucosm").remove(0)),
+                    forfeit: true,
+                }),
+            })
+        };
+        for reveal_period in [0, MAX_REVEAL_PERIOD + 1, u64::MAX] {
+            let err = execute(
+                deps.as_mut(),
+                env.clone(),
+                alice.clone(),
+                propose(reveal_period, 10),
+            )
+            .unwrap_err();
+            assert!(matches!(
+                err,
+                ContractError::InvalidRevealPeriod {
+                    max: MAX_REVEAL_PERIOD
+                }
+            ));
+        }
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), propose(5, 0)).unwrap_err();
+        assert!(matches!(err, ContractError::ZeroDeposit {}));
+        execute(deps.as_mut(), env.clone(), alice.clone(), propose(5, 10)).unwrap();
+
+        let plaintext = act(Action::CastVote {
+            proposal_id: 0,
+            vote: VoteOption::Yes,
+        });
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), plaintext).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::SecretBallot { proposal_id: 0 }
+        ));
+
+        let commit = |voter: &Addr, vote, salt: &str| {
+            act(Action::CommitVote {
+                proposal_id: 0,
+                commitment: commitment(0, voter, vote, salt).unwrap(),
+            })
+        };
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            alice.clone(),
+            commit(&alice.sender, VoteOption::Yes, "pepper"),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::InvalidDeposit {}));
+        let alice_paid = message_info(&alice.sender, &coins(10, "ucosm"));
+        let bob_paid = message_info(&bob.sender, &coins(10, "ucosm"));
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            alice_paid,
+            commit(&alice.sender, VoteOption::Yes, "pepper"),
+        )
+        .unwrap();
+        // Bob copies Alice's commitment to reveal her vote after her
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            bob_paid,
+            commit(&alice.sender, VoteOption::Yes, "pepper"),
+        )
+        .unwrap();
+
+        let reveal = |salt: &str| {
+            act(Action::RevealVote {
+                proposal_id: 0,
+                vote: VoteOption::Yes,
+                salt: salt.to_string(),
+            })
+        };
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), reveal("pepper")).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::NotRevealing { proposal_id: 0 }
+        ));
+
+        env.block.height += 10;
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), reveal("salt")).unwrap_err();
+        assert!(matches!(err, ContractError::CommitmentMismatch {}));
+        let res = execute(deps.as_mut(), env.clone(), alice.clone(), reveal("pepper")).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), bob.clone(), reveal("pepper")).unwrap_err();
+        assert!(matches!(err, ContractError::CommitmentMismatch {}));
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: alice.sender.to_string(),
+                amount: coins(10, "ucosm"),
+            })
+        );
+
+        // Bob never reveals a vote of his own, his deposit is forfeited once the reveal period ends
+        let finalize = act(Action::ExecuteProposal { proposal_id: 0 });
+        let err = execute(deps.as_mut(), env.clone(), bob.clone(), finalize.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::VotingOpen { proposal_id: 0 }));
+        env.block.height += 5;
+        let res = execute(deps.as_mut(), env.clone(), bob.clone(), finalize).unwrap();
+        assert_eq!(res.attributes[2].value, "true");
+        assert_eq!(res.messages.len(), 1);
+        assert!(matches!(
+            res.messages[0].msg,
+            CosmosMsg::Wasm(WasmMsg::Instantiate { .. })
+        ));
+
+        let query_msg = QueryMsg {
+            action: QueryAction::HistoryByVoter {
+                voter: bob.sender.to_string(),
+                start_after: None,
+                limit: None,
+            },
+        };
+        let history: HistoryResponse =
+            from_json(query(deps.as_ref(), env, query_msg).unwrap()).unwrap();
+        let events: Vec<_> = history.entries.into_iter().map(|e| e.event).collect();
+        assert_eq!(
+            events,
+            vec![
+                HistoryEvent::VoteCommitted {},
+                HistoryEvent::CommitmentDiscarded { forfeited: true },
+                HistoryEvent::ProposalStatus {
+                    status: ProposalStatus::Executed
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_discard_commitments() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let voters: Vec<_> = (0..MAX_LIMIT + 2)
+            .map(|i| deps.api.addr_make(&format!("voter {}", i)))
+            .collect();
+        let mut msg = init_msg(&deps.api);
+        msg.members = voters
+            .iter()
+            .map(|voter| Member {
+                addr: voter.to_string(),
+                weight: 1,
+            })
+            .collect();
+        let first = message_info(&voters[0], &[]);
+        instantiate(deps.as_mut(), env.clone(), first.clone(), msg).unwrap();
+        let propose = act(Action::Propose {
+            title: "Spawn in secret".to_string(),
+            kind: ProposalKind::CreateSubNetwork {
+                label: "child".to_string(),
+                scenarios: scenarios(),
+                members: vec![],
+            },
+            secret: Some(SecretBallot {
+                reveal_period: 5,
+                deposit: Some(coins(10, "ucosm").remove(0)),
+                forfeit: false,
+            }),
+        });
+        execute(deps.as_mut(), env.clone(), first.clone(), propose).unwrap();
+        for voter in &voters {
+            let commit = act(Action::CommitVote {
+                proposal_id: 0,
+                commitment: commitment(0, voter, VoteOption::Yes, "salt").unwrap(),
+            });
+            let paid = message_info(voter, &coins(10, "ucosm"));
+            execute(deps.as_mut(), env.clone(), paid, commit).unwrap();
+        }
+
+        // Nobody reveals, executing refunds the first page of deposits
+        let discard = |limit| {
+            act(Action::DiscardCommitments {
+                proposal_id: 0,
+                limit,
+            })
+        };
+        let err = execute(deps.as_mut(), env.clone(), first.clone(), discard(None)).unwrap_err();
+        assert!(matches!(err, ContractError::VotingOpen { proposal_id: 0 }));
+        env.block.height += 15;
+        let finalize = act(Action::ExecuteProposal { proposal_id: 0 });
+        let res = execute(deps.as_mut(), env.clone(), first.clone(), finalize).unwrap();
+        assert_eq!(res.attributes[2].value, "false");
+        assert_eq!(res.attributes[3].value, "true");
+        assert_eq!(res.messages.len(), MAX_LIMIT as usize);
+        let res = execute(deps.as_mut(), env.clone(), first.clone(), discard(Some(1))).unwrap();
+        assert_eq!(res.attributes[2].value, "true");
+        assert_eq!(res.messages.len(), 1);
+        let res = execute(deps.as_mut(), env, first, discard(None)).unwrap();
+        assert_eq!(res.attributes[2].value, "false");
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: voters.iter().max().unwrap().to_string(),
+                amount: coins(10, "ucosm"),
+            })
+        );
+    }
+
+    #[test]
+    fn test_conviction_voting() {
+        // The treasury holds 1000 on top of the 100 staked below
+        let mut deps = mock_dependencies_with_balance(&coins(1100, "ucosm"));
+        let mut env = mock_env();
+        let alice = message_info(&deps.api.addr_make("alice"), &[]);
+        let bob = message_info(&deps.api.addr_make("bob"), &[]);
+        let carol = deps.api.addr_make("carol");
+        let mut msg = init_msg(&deps.api);
+        msg.conviction = Some(ConvictionConfig {
+            denom: "ucosm".to_string(),
+            decay: Decimal::percent(90),
+            max_ratio: Decimal::percent(50),
+            weight: Decimal::percent(1),
+        });
+        instantiate(deps.as_mut(), env.clone(), alice.clone(), msg).unwrap();
+
+        let request = |scenario: &str, amount: u128| {
+            act(Action::RequestFunding {
+                scenario: scenario.to_string(),
+                beneficiary: carol.to_string(),
+                amount: Uint128::new(amount),
+            })
+        };
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            alice.clone(),
+            request("Nope", 100),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::ScenarioNotFound {}));
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            alice.clone(),
+            request("Scenario 1", 0),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::ZeroFundingRequest {}));
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            alice.clone(),
+            request("Scenario 1", 100),
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            alice.clone(),
+            request("Scenario 2", 600),
+        )
+        .unwrap();
+
+        let stake = act(Action::Stake { request_id: 0 });
+        let wrong_denom = message_info(&bob.sender, &coins(100, "uatom"));
+        let err = execute(deps.as_mut(), env.clone(), wrong_denom, stake.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidStake {}));
+        let paid = message_info(&bob.sender, &coins(100, "ucosm"));
+        let res = execute(deps.as_mut(), env.clone(), paid, stake).unwrap();
+        assert!(res.messages.is_empty());
+
+        let query_request = |request_id, env| -> FundingRequestResponse {
+            let query_msg = QueryMsg {
+                action: QueryAction::GetFundingRequest { request_id },
+            };
+            from_json(query(deps.as_ref(), env, query_msg).unwrap()).unwrap()
+        };
+        let res = query_request(0, env.clone());
+        assert_eq!(res.conviction, Uint128::zero());
+        // 0.01 * 100 / (0.1 * (0.5 - 0.1)^2)
+        assert_eq!(res.threshold, Some(Uint128::new(63)));
+        // Asking for more than half of the treasury never passes
+        assert_eq!(query_request(1, env.clone()).threshold, None);
+
+        env.block.height += 1;
+        assert_eq!(query_request(0, env.clone()).conviction, Uint128::new(100));
+        let update = act(Action::UpdateConviction { request_id: 0 });
+        let res = execute(deps.as_mut(), env.clone(), alice.clone(), update.clone()).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: carol.to_string(),
+                amount: coins(100, "ucosm"),
+            })
+        );
+        let err = execute(deps.as_mut(), env.clone(), alice.clone(), update).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::AlreadyFunded { request_id: 0 }
+        ));
+
+        let unstake = |amount| {
+            act(Action::Unstake {
+                request_id: 0,
+                amount: Uint128::new(amount),
+            })
+        };
+        let err = execute(deps.as_mut(), env.clone(), bob.clone(), unstake(101)).unwrap_err();
+        assert!(matches!(err, ContractError::InsufficientStake {}));
+        let res = execute(deps.as_mut(), env.clone(), bob.clone(), unstake(100)).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: bob.sender.to_string(),
+                amount: coins(100, "ucosm"),
+            })
+        );
+
+        // Unstaking only refunds, the refund still sits in the balance and
+        // must not be paid out as treasury
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            alice.clone(),
+            request("Scenario 1", 100),
+        )
+        .unwrap();
+        let paid = message_info(&bob.sender, &coins(100, "ucosm"));
+        let stake = act(Action::Stake { request_id: 2 });
+        execute(deps.as_mut(), env.clone(), paid, stake).unwrap();
+        env.block.height += 1;
+        let unstake = act(Action::Unstake {
+            request_id: 2,
+            amount: Uint128::new(50),
+        });
+        let res = execute(deps.as_mut(), env.clone(), bob.clone(), unstake).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        let query_msg = QueryMsg {
+            action: QueryAction::GetFundingRequest { request_id: 2 },
+        };
+        let res: FundingRequestResponse =
+            from_json(query(deps.as_ref(), env, query_msg).unwrap()).unwrap();
+        assert!(!res.request.funded);
+    }
+
+    #[test]
+    fn test_conviction_threshold_limits() {
+        let mut deps = mock_dependencies_with_balance(&coins(1_000_000_000_000, "ucosm"));
+        let env = mock_env();
+        let alice = message_info(&deps.api.addr_make("alice"), &[]);
+        let mut msg = init_msg(&deps.api);
+        msg.conviction = Some(ConvictionConfig {
+            denom: "ucosm".to_string(),
+            decay: Decimal::percent(90),
+            max_ratio: Decimal::percent(50),
+            weight: Decimal::percent(1),
+        });
+        instantiate(deps.as_mut(), env.clone(), alice.clone(), msg).unwrap();
+        // Next to the 100 staked below, a share 10^-12 under max_ratio leaves no room
+        // for the threshold
+        let request = act(Action::RequestFunding {
+            scenario: "Scenario 1".to_string(),
+            beneficiary: alice.sender.to_string(),
+            amount: Uint128::new(499_999_999_949),
+        });
+        execute(deps.as_mut(), env.clone(), alice.clone(), request).unwrap();
+        let paid = message_info(&alice.sender, &coins(100, "ucosm"));
+        let stake = act(Action::Stake { request_id: 0 });
+        let res = execute(deps.as_mut(), env.clone(), paid, stake).unwrap();
+        assert!(res.messages.is_empty());
+        let update = act(Action::UpdateConviction { request_id: 0 });
+        let res = execute(deps.as_mut(), env.clone(), alice, update).unwrap();
+        assert!(res.messages.is_empty());
+        let query_msg = QueryMsg {
+            action: QueryAction::GetFundingRequest { request_id: 0 },
+        };
+        let res: FundingRequestResponse =
+            from_json(query(deps.as_ref(), env, query_msg).unwrap()).unwrap();
+        assert_eq!(res.threshold, None);
+    }
+
+    #[test]
+    fn test_participatory_budgeting() {
+        let mut deps = mock_dependencies();
+        let mut env = mock_env();
+        let alice = message_info(&deps.api.addr_make("alice"), &[]);
+        let bob = message_info(&deps.api.addr_make("bob"), &[]);
+        let msg = init_msg(&deps.api);
+        instantiate(deps.as_mut(), env.clone(), alice.clone(), msg).unwrap();
+
+        let round = |limits: Vec<(&str, u128, u128)>| {
+            act(Action::Propose {
+                title: "Spring budget".to_string(),
+                kind: ProposalKind::BudgetRound {
+                    budget: 500,
+                    allotment: 100,
+                    limits: limits
+                        .into_iter()
+                        .map(|(scenario, max_support, max_funding)| ScenarioLimits {
+                            scenario: scenario.to_string(),
+                            max_support,
+                            max_funding,
+                        })
+                        .collect(),
+                },
+                secret: None,
+            })
+        };
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            alice.clone(),
+            round(vec![("Nope", 100, 100)]),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::ScenarioNotFound {}));
+        let propose = round(vec![("Scenario 1", 100, 400), ("Scenario 2", 90, 300)]);
+        execute(deps.as_mut(), env.clone(), alice.clone(), propose).unwrap();
+        let vote = act(Action::CastVote {
+            proposal_id: 0,
+            vote: VoteOption::Yes,
+        });
+        execute(deps.as_mut(), env.clone(), alice.clone(), vote).unwrap();
+        env.block.height += 10;
+        let finalize = act(Action::ExecuteProposal { proposal_id: 0 });
+        let res = execute(deps.as_mut(), env.clone(), alice.clone(), finalize).unwrap();
+        assert_eq!(res.attributes[4].key, "round_id");
+
+        let allocate = |allocations: Vec<(&str, u128)>| {
+            act(Action::AllocateBudget {
+                round_id: 0,
+                allocations: allocations
+                    .into_iter()
+                    .map(|(s, a)| (s.to_string(), a))
+                    .collect(),
+            })
+        };
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            alice.clone(),
+            allocate(vec![("Scenario 1", 60), ("Scenario 2", 50)]),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::AllotmentExceeded {}));
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            alice.clone(),
+            allocate(vec![("Scenario 1", u128::MAX), ("Scenario 2", 1)]),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::AllocationOverflow {}));
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            alice.clone(),
+            allocate(vec![("Scenario 1", 10), ("Scenario 1", 10)]),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::InvalidAllocation {}));
+        let stranger = message_info(&deps.api.addr_make("stranger"), &[]);
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            stranger,
+            allocate(vec![("Scenario 1", 10)]),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::NotACitizen {}));
+
+        // Spreading the allotment over several scenarios is allowed, and can be revised
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            alice.clone(),
+            allocate(vec![("Scenario 1", 30), ("Scenario 2", 70)]),
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            alice.clone(),
+            allocate(vec![("Scenario 1", 20), ("Scenario 2", 40)]),
+        )
+        .unwrap();
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            bob.clone(),
+            allocate(vec![("Scenario 2", 60)]),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::ScenarioCapExceeded { .. }));
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            bob.clone(),
+            allocate(vec![("Scenario 1", 10), ("Scenario 2", 50)]),
+        )
+        .unwrap();
+
+        let query_round = |deps: Deps, env| -> BudgetRoundResponse {
+            let query_msg = QueryMsg {
+                action: QueryAction::GetBudgetRound { round_id: 0 },
+            };
+            from_json(query(deps, env, query_msg).unwrap()).unwrap()
+        };
+        let round = query_round(deps.as_ref(), env.clone()).round;
+        assert_eq!(
+            round.support,
+            vec![
+                ("Scenario 1".to_string(), 30),
+                ("Scenario 2".to_string(), 90)
+            ]
+        );
+
+        let finalize = act(Action::FinalizeRound { round_id: 0 });
+        let err = execute(deps.as_mut(), env.clone(), bob.clone(), finalize.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::RoundOpen { round_id: 0 }));
+        env.block.height += 10;
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            bob.clone(),
+            allocate(vec![("Scenario 1", 10)]),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::RoundClosed { round_id: 0 }));
+
+        // Scenario 2 has more support and is funded up to its limit, Scenario 1 gets the rest
+        let res = execute(deps.as_mut(), env.clone(), bob.clone(), finalize.clone()).unwrap();
+        assert_eq!(res.attributes[2].value, "500");
+        assert_eq!(
+            query_round(deps.as_ref(), env.clone()).round.allocation,
+            Some(vec![
+                ("Scenario 2".to_string(), 300),
+                ("Scenario 1".to_string(), 200)
+            ])
+        );
+        let err = execute(deps.as_mut(), env, bob, finalize).unwrap_err();
+        assert!(matches!(err, ContractError::RoundFinalized { round_id: 0 }));
+    }
+}
@@ -0,0 +1,88 @@
+use cosmwasm_std::StdError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ContractError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+    #[error("Total budget exceeds the maximum allowed amount")]
+    BudgetExceeded {},
+    #[error("Budget must be greater than zero")]
+    ZeroBudget {},
+    #[error("Scenario does not exist")]
+    ScenarioNotFound {},
+    #[error("User has already voted for this scenario")]
+    AlreadyVotedForScenario {},
+    #[error("User has already voted for another scenario")]
+    AlreadyVotedForOtherScenario {},
+    #[error("Only citizens may take part in this democracy")]
+    NotACitizen {},
+    #[error("Voting on proposal {proposal_id} is closed")]
+    VotingClosed { proposal_id: u64 },
+    #[error("Voting on proposal {proposal_id} is still open")]
+    VotingOpen { proposal_id: u64 },
+    #[error("Proposal {proposal_id} was already finalized")]
+    AlreadyFinalized { proposal_id: u64 },
+    #[error("This network has no parent to vote in")]
+    NoParent {},
+    #[error("Sub-network creation is disabled for this network")]
+    SubNetworksDisabled {},
+    #[error("Proposal {proposal_id} uses secret ballots, commit and reveal your vote instead")]
+    SecretBallot { proposal_id: u64 },
+    #[error("Proposal {proposal_id} uses plaintext ballots")]
+    NotASecretBallot { proposal_id: u64 },
+    #[error("A secret ballot needs a reveal period of 1 to {max} blocks")]
+    InvalidRevealPeriod { max: u64 },
+    #[error("Proposal {proposal_id} is not in its reveal period")]
+    NotRevealing { proposal_id: u64 },
+    #[error("No commitment to reveal for proposal {proposal_id}")]
+    NoCommitment { proposal_id: u64 },
+    #[error("Revealed vote does not match the commitment")]
+    CommitmentMismatch {},
+    #[error("Commitments must be accompanied by exactly the configured deposit")]
+    InvalidDeposit {},
+    #[error("A secret ballot deposit must not be zero")]
+    ZeroDeposit {},
+    #[error("Conviction voting is not enabled for this network")]
+    ConvictionDisabled {},
+    #[error("Invalid conviction voting parameters")]
+    InvalidConvictionConfig {},
+    #[error("Stakes must be paid in the conviction denom")]
+    InvalidStake {},
+    #[error("Cannot unstake more than was staked")]
+    InsufficientStake {},
+    #[error("Funding requests must ask for a positive amount")]
+    ZeroFundingRequest {},
+    #[error("Funding request {request_id} was already funded")]
+    AlreadyFunded { request_id: u64 },
+    #[error("Allocations must name distinct scenarios of the round with a positive amount")]
+    InvalidAllocation {},
+    #[error("Allocations overflow the support of the round")]
+    AllocationOverflow {},
+    #[error("Allocations exceed the allotment of the round")]
+    AllotmentExceeded {},
+    #[error("Scenario {scenario} would receive more support than its limit")]
+    ScenarioCapExceeded { scenario: String },
+    #[error("Budget round {round_id} is closed")]
+    RoundClosed { round_id: u64 },
+    #[error("Budget round {round_id} is still open")]
+    RoundOpen { round_id: u64 },
+    #[error("Budget round {round_id} was already finalized")]
+    RoundFinalized { round_id: u64 },
+    #[error("A network holds at most {max} sub-networks")]
+    TooManySubNetworks { max: usize },
+    #[error("Networks nest at most {max} levels below the root")]
+    SubNetworkTooDeep { max: u32 },
+    #[error(
+        "{address} must be an individual citizen of this network, listed once with its weight here"
+    )]
+    InvalidMember { address: String },
+    #[error("{address} is listed more than once")]
+    DuplicateMember { address: String },
+    #[error("The sub-network joined after proposal {proposal_id} opened")]
+    JoinedAfterProposal { proposal_id: u64 },
+    #[error("Parent proposal {proposal_id} uses secret ballots, which sub-networks cannot cast")]
+    ParentSecretBallot { proposal_id: u64 },
+    #[error("Unknown reply id {id}")]
+    UnknownReply { id: u64 },
+}
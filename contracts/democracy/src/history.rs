@@ -0,0 +1,105 @@
+use crate::msg::HistoryResponse;
+use crate::state::{
+    HistoryEntry, HistoryEvent, HISTORY, HISTORY_BY_ACTOR, HISTORY_BY_HEIGHT, HISTORY_BY_PROPOSAL,
+    HISTORY_SEQ,
+};
+use cosmwasm_std::{Addr, Deps, Empty, Env, Order, StdResult, Storage};
+use cw_storage_plus::Bound;
+
+pub(crate) const DEFAULT_LIMIT: u32 = 10;
+pub(crate) const MAX_LIMIT: u32 = 30;
+
+// Appends an entry to the audit trail and indexes it by height, actor and proposal
+pub fn record(
+    storage: &mut dyn Storage,
+    env: &Env,
+    actor: &Addr,
+    proposal_id: Option<u64>,
+    event: HistoryEvent,
+) -> StdResult<u64> {
+    let id = HISTORY_SEQ.may_load(storage)?.unwrap_or_default();
+    let entry = HistoryEntry {
+        id,
+        height: env.block.height,
+        actor: actor.clone(),
+        proposal_id,
+        event,
+    };
+    HISTORY.save(storage, id, &entry)?;
+    HISTORY_BY_HEIGHT.save(storage, (entry.height, id), &Empty {})?;
+    HISTORY_BY_ACTOR.save(storage, (actor, id), &Empty {})?;
+    if let Some(proposal_id) = proposal_id {
+        HISTORY_BY_PROPOSAL.save(storage, (proposal_id, id), &Empty {})?;
+    }
+    HISTORY_SEQ.save(storage, &(id + 1))?;
+    Ok(id)
+}
+
+pub fn by_proposal(
+    deps: Deps,
+    proposal_id: u64,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<HistoryResponse> {
+    let ids = HISTORY_BY_PROPOSAL.prefix(proposal_id).keys(
+        deps.storage,
+        start_after.map(Bound::exclusive),
+        None,
+        Order::Ascending,
+    );
+    load_entries(deps, ids, limit)
+}
+
+pub fn by_voter(
+    deps: Deps,
+    voter: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<HistoryResponse> {
+    let voter = deps.api.addr_validate(&voter)?;
+    let ids = HISTORY_BY_ACTOR.prefix(&voter).keys(
+        deps.storage,
+        start_after.map(Bound::exclusive),
+        None,
+        Order::Ascending,
+    );
+    load_entries(deps, ids, limit)
+}
+
+pub fn by_blocks(
+    deps: Deps,
+    start_height: u64,
+    end_height: u64,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<HistoryResponse> {
+    let min = match start_after {
+        Some(id) => {
+            let height = HISTORY.load(deps.storage, id)?.height;
+            if height >= start_height {
+                Bound::exclusive((height, id))
+            } else {
+                Bound::inclusive((start_height, 0))
+            }
+        }
+        None => Bound::inclusive((start_height, 0)),
+    };
+    let max = Bound::inclusive((end_height, u64::MAX));
+    let ids = HISTORY_BY_HEIGHT
+        .keys(deps.storage, Some(min), Some(max), Order::Ascending)
+        .map(|key| key.map(|(_, id)| id));
+    load_entries(deps, ids, limit)
+}
+
+fn load_entries(
+    deps: Deps,
+    ids: impl Iterator<Item = StdResult<u64>>,
+    limit: Option<u32>,
+) -> StdResult<HistoryResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let entries = ids
+        .take(limit)
+        .map(|id| HISTORY.load(deps.storage, id?))
+        .collect::<StdResult<_>>()?;
+    Ok(HistoryResponse { entries })
+}
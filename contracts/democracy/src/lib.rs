@@ -1,3 +1,8 @@
+mod budgeting;
+mod conviction;
+pub mod democracy;
+mod error;
+mod history;
 mod msg;
+mod secret;
 mod state;
-pub mod democracy;
\ No newline at end of file
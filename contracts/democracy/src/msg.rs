@@ -1,5 +1,11 @@
+use cosmwasm_std::{Addr, Binary, Coin, Decimal, Uint128};
 use serde::{Deserialize, Serialize};
 
+use crate::state::{
+    BudgetRound, CitizenKind, ConvictionConfig, FundingRequest, HistoryEntry, Proposal,
+    ScenarioLimits, SubNetwork,
+};
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
 pub struct Votes {
     pub scenario_votes: Vec<(String, u128)>,
@@ -8,6 +14,32 @@ pub struct Votes {
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct InstantiateMsg {
     pub scenarios: Vec<String>,
+    #[serde(default)]
+    pub members: Vec<Member>,
+    // Set by the parent network when this contract is spawned as a sub-network
+    #[serde(default)]
+    pub parent: Option<String>,
+    // Levels below the root network, set by the parent along with parent
+    #[serde(default)]
+    pub depth: u32,
+    // Code id used to spawn sub-networks, None disables sub-network proposals
+    #[serde(default)]
+    pub sub_network_code_id: Option<u64>,
+    // Voting period in blocks
+    #[serde(default)]
+    pub voting_period: Option<u64>,
+    // Share of the total weight that has to take part in a vote
+    #[serde(default)]
+    pub quorum: Option<Decimal>,
+    // Enables conviction voting for continuous funding
+    #[serde(default)]
+    pub conviction: Option<ConvictionConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Member {
+    pub addr: String,
+    pub weight: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -15,6 +47,104 @@ pub struct ExecuteMsg {
     pub action: Action,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Action {
+    SubmitVote {
+        scenario: String,
+        budget: u128,
+    },
+    Propose {
+        title: String,
+        kind: ProposalKind,
+        #[serde(default)]
+        secret: Option<SecretBallot>,
+    },
+    CastVote {
+        proposal_id: u64,
+        vote: VoteOption,
+    },
+    ExecuteProposal {
+        proposal_id: u64,
+    },
+    // sha256 of the json encoded vote followed by the salt, see secret::commitment
+    CommitVote {
+        proposal_id: u64,
+        commitment: Binary,
+    },
+    RevealVote {
+        proposal_id: u64,
+        vote: VoteOption,
+        salt: String,
+    },
+    // Executing a secret ballot discards only a page of the unrevealed commitments,
+    // anyone may discard the rest afterwards
+    DiscardCommitments {
+        proposal_id: u64,
+        limit: Option<u32>,
+    },
+    RequestFunding {
+        scenario: String,
+        beneficiary: String,
+        amount: Uint128,
+    },
+    // Funds attached in the conviction denom are staked on the request
+    Stake {
+        request_id: u64,
+    },
+    Unstake {
+        request_id: u64,
+        amount: Uint128,
+    },
+    // Brings the conviction up to date and releases the funding once it passes
+    UpdateConviction {
+        request_id: u64,
+    },
+    // Replaces any earlier allocation of the sender in the round
+    AllocateBudget {
+        round_id: u64,
+        allocations: Vec<(String, u128)>,
+    },
+    FinalizeRound {
+        round_id: u64,
+    },
+}
+
+// Commit-reveal mode: votes are committed during the voting period and revealed afterwards
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SecretBallot {
+    pub reveal_period: u64, // in blocks, following the voting period
+    pub deposit: Option<Coin>,
+    pub forfeit: bool, // Keep the deposit of commitments which were never revealed
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ProposalKind {
+    // Spawns a child democracy which becomes a citizen of this one
+    CreateSubNetwork {
+        label: String,
+        scenarios: Vec<String>,
+        members: Vec<Member>,
+    },
+    // Casts this network's vote on a proposal of the parent network
+    ParentVote {
+        proposal_id: u64,
+        vote: VoteOption,
+    },
+    // Opens a participatory budgeting round, see budgeting::allocate
+    BudgetRound {
+        budget: u128,
+        allotment: u128, // Each citizen may spread this much over the scenarios
+        limits: Vec<ScenarioLimits>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum VoteOption {
+    Yes,
+    No,
+    Abstain,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct QueryMsg {
     pub action: QueryAction,
@@ -22,5 +152,76 @@ pub struct QueryMsg {
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum QueryAction {
-    GetVotes {}
+    GetVotes {},
+    GetProposal {
+        proposal_id: u64,
+    },
+    GetCitizen {
+        address: String,
+    },
+    TotalWeight {},
+    ListSubNetworks {},
+    HistoryByProposal {
+        proposal_id: u64,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    HistoryByVoter {
+        voter: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    HistoryByBlocks {
+        start_height: u64,
+        end_height: u64,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    GetFundingRequest {
+        request_id: u64,
+    },
+    GetBudgetRound {
+        round_id: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ProposalResponse {
+    pub proposal: Proposal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CitizenResponse {
+    pub address: Addr,
+    pub kind: CitizenKind,
+    pub weight: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TotalWeightResponse {
+    pub weight: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SubNetworksResponse {
+    pub sub_networks: Vec<(Addr, SubNetwork)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HistoryResponse {
+    pub entries: Vec<HistoryEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FundingRequestResponse {
+    pub request: FundingRequest,
+    // Conviction as of the current block
+    pub conviction: Uint128,
+    // None while the request asks for too large a share of the treasury
+    pub threshold: Option<Uint128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BudgetRoundResponse {
+    pub round: BudgetRound,
 }
@@ -0,0 +1,196 @@
+use crate::democracy::apply_ballot;
+use crate::error::ContractError;
+use crate::history::{self, DEFAULT_LIMIT, MAX_LIMIT};
+use crate::msg::{SecretBallot, VoteOption};
+use crate::state::{
+    Commitment, HistoryEvent, Proposal, ProposalStatus, CITIZENS, COMMITMENTS, PROPOSALS,
+};
+use cosmwasm_std::{
+    to_json_vec, Addr, BankMsg, Binary, Coin, DepsMut, Env, MessageInfo, Order, Response, StdResult,
+};
+use sha2::{Digest, Sha256};
+
+// About a week of 6 second blocks
+pub const MAX_REVEAL_PERIOD: u64 = 100_000;
+
+// The hash citizens commit to: sha256 of the big endian proposal id, the voter's
+// address, the json encoded vote and the salt. Binding the proposal and the voter
+// keeps others from copying a commitment and revealing it as their own
+pub fn commitment(
+    proposal_id: u64,
+    voter: &Addr,
+    vote: VoteOption,
+    salt: &str,
+) -> StdResult<Binary> {
+    let mut hasher = Sha256::new();
+    hasher.update(proposal_id.to_be_bytes());
+    hasher.update(voter.as_bytes());
+    hasher.update(to_json_vec(&vote)?);
+    hasher.update(salt.as_bytes());
+    Ok(Binary::from(hasher.finalize().to_vec()))
+}
+
+// Without a reveal period no vote could ever be counted
+pub fn validate_ballot(secret: &SecretBallot) -> Result<(), ContractError> {
+    if secret.reveal_period == 0 || secret.reveal_period > MAX_REVEAL_PERIOD {
+        return Err(ContractError::InvalidRevealPeriod {
+            max: MAX_REVEAL_PERIOD,
+        });
+    }
+    if secret
+        .deposit
+        .as_ref()
+        .is_some_and(|deposit| deposit.amount.is_zero())
+    {
+        return Err(ContractError::ZeroDeposit {});
+    }
+    Ok(())
+}
+
+pub fn commit_vote(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    proposal_id: u64,
+    hash: Binary,
+) -> Result<Response, ContractError> {
+    let proposal = PROPOSALS.load(deps.storage, proposal_id)?;
+    if proposal.status != ProposalStatus::Open || env.block.height >= proposal.end_height {
+        return Err(ContractError::VotingClosed { proposal_id });
+    }
+    let secret = proposal
+        .secret
+        .ok_or(ContractError::NotASecretBallot { proposal_id })?;
+    if !CITIZENS.has(deps.storage, &info.sender) {
+        return Err(ContractError::NotACitizen {});
+    }
+
+    // The deposit is paid once, committing again only replaces the hash
+    let deposit = match COMMITMENTS.may_load(deps.storage, (proposal_id, &info.sender))? {
+        Some(previous) => {
+            check_deposit(&info.funds, None)?;
+            previous.deposit
+        }
+        None => {
+            check_deposit(&info.funds, secret.deposit.as_ref())?;
+            secret.deposit
+        }
+    };
+    let commitment = Commitment { hash, deposit };
+    COMMITMENTS.save(deps.storage, (proposal_id, &info.sender), &commitment)?;
+    let event = HistoryEvent::VoteCommitted {};
+    history::record(deps.storage, &env, &info.sender, Some(proposal_id), event)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "commit_vote")
+        .add_attribute("proposal_id", proposal_id.to_string())
+        .add_attribute("voter", info.sender))
+}
+
+pub fn reveal_vote(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    proposal_id: u64,
+    vote: VoteOption,
+    salt: String,
+) -> Result<Response, ContractError> {
+    let mut proposal = PROPOSALS.load(deps.storage, proposal_id)?;
+    if proposal.secret.is_none() {
+        return Err(ContractError::NotASecretBallot { proposal_id });
+    }
+    if proposal.status != ProposalStatus::Open
+        || env.block.height < proposal.end_height
+        || env.block.height >= proposal.tally_height()?
+    {
+        return Err(ContractError::NotRevealing { proposal_id });
+    }
+    let stored = COMMITMENTS
+        .may_load(deps.storage, (proposal_id, &info.sender))?
+        .ok_or(ContractError::NoCommitment { proposal_id })?;
+    if stored.hash != commitment(proposal_id, &info.sender, vote, &salt)? {
+        return Err(ContractError::CommitmentMismatch {});
+    }
+
+    COMMITMENTS.remove(deps.storage, (proposal_id, &info.sender));
+    let weight = apply_ballot(deps.branch(), &env, &mut proposal, &info.sender, vote)?;
+    let mut response = Response::new()
+        .add_attribute("action", "reveal_vote")
+        .add_attribute("proposal_id", proposal_id.to_string())
+        .add_attribute("voter", info.sender.clone())
+        .add_attribute("weight", weight.to_string());
+    if let Some(deposit) = stored.deposit {
+        response = response.add_message(refund(&info.sender, deposit));
+    }
+    Ok(response)
+}
+
+pub fn discard_leftover_commitments(
+    deps: DepsMut,
+    env: Env,
+    proposal_id: u64,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let proposal = PROPOSALS.load(deps.storage, proposal_id)?;
+    if proposal.secret.is_none() {
+        return Err(ContractError::NotASecretBallot { proposal_id });
+    }
+    if proposal.status == ProposalStatus::Open {
+        return Err(ContractError::VotingOpen { proposal_id });
+    }
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let (refunds, more) = discard_commitments(deps, &env, &proposal, limit)?;
+    Ok(Response::new()
+        .add_attribute("action", "discard_commitments")
+        .add_attribute("proposal_id", proposal_id.to_string())
+        .add_attribute("more_commitments", more.to_string())
+        .add_messages(refunds))
+}
+
+// Drops up to limit commitments which were never revealed and tells whether any are
+// left, forfeited deposits stay in the treasury
+pub fn discard_commitments(
+    deps: DepsMut,
+    env: &Env,
+    proposal: &Proposal,
+    limit: usize,
+) -> Result<(Vec<BankMsg>, bool), ContractError> {
+    let forfeit = match &proposal.secret {
+        Some(secret) => secret.forfeit,
+        None => return Ok((vec![], false)),
+    };
+    let mut pending = COMMITMENTS
+        .prefix(proposal.id)
+        .range(deps.storage, None, None, Order::Ascending)
+        .take(limit + 1)
+        .collect::<StdResult<Vec<_>>>()?;
+    let more = pending.len() > limit;
+    pending.truncate(limit);
+
+    let mut refunds = vec![];
+    for (voter, commitment) in pending {
+        COMMITMENTS.remove(deps.storage, (proposal.id, &voter));
+        let forfeited = forfeit && commitment.deposit.is_some();
+        if let Some(deposit) = commitment.deposit.filter(|_| !forfeit) {
+            refunds.push(refund(&voter, deposit));
+        }
+        let event = HistoryEvent::CommitmentDiscarded { forfeited };
+        history::record(deps.storage, env, &voter, Some(proposal.id), event)?;
+    }
+    Ok((refunds, more))
+}
+
+fn check_deposit(funds: &[Coin], deposit: Option<&Coin>) -> Result<(), ContractError> {
+    let expected: Vec<Coin> = deposit.into_iter().cloned().collect();
+    if funds != expected.as_slice() {
+        return Err(ContractError::InvalidDeposit {});
+    }
+    Ok(())
+}
+
+fn refund(voter: &Addr, deposit: Coin) -> BankMsg {
+    BankMsg::Send {
+        to_address: voter.to_string(),
+        amount: vec![deposit],
+    }
+}
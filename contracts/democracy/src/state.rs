@@ -0,0 +1,258 @@
+use cosmwasm_std::{
+    Addr, Binary, Coin, Decimal, Empty, OverflowError, OverflowOperation, StdResult, Storage,
+    Uint128,
+};
+use cw_storage_plus::{Item, Map};
+use serde::{Deserialize, Serialize};
+
+use crate::msg::{ProposalKind, SecretBallot, VoteOption, Votes};
+
+pub const DEFAULT_VOTING_PERIOD: u64 = 100;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Config {
+    pub parent: Option<Addr>,
+    #[serde(default)]
+    pub depth: u32, // Levels below the root network
+    pub sub_network_code_id: Option<u64>,
+    pub voting_period: u64, // in blocks
+    pub quorum: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum CitizenKind {
+    Individual,
+    // A child democracy voting with the aggregated weight of its members
+    SubNetwork,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Citizen {
+    pub kind: CitizenKind,
+    pub weight: u64, // Always 0 for sub-networks, their weight is queried live
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SubNetwork {
+    pub label: String,
+    pub proposal_id: u64, // Proposal which created the sub-network
+    pub height: u64,      // Block it joined at, it votes on proposals opened afterwards
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ProposalStatus {
+    Open,
+    Rejected,
+    Executed,
+    // Passed, but its action could no longer be carried out
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct Tally {
+    pub yes: u64,
+    pub no: u64,
+    pub abstain: u64,
+}
+
+impl Tally {
+    pub fn add(&mut self, vote: VoteOption, weight: u64) -> StdResult<()> {
+        let count = self.count(vote);
+        *count = count
+            .checked_add(weight)
+            .ok_or_else(|| OverflowError::new(OverflowOperation::Add))?;
+        Ok(())
+    }
+
+    pub fn sub(&mut self, vote: VoteOption, weight: u64) -> StdResult<()> {
+        let count = self.count(vote);
+        *count = count
+            .checked_sub(weight)
+            .ok_or_else(|| OverflowError::new(OverflowOperation::Sub))?;
+        Ok(())
+    }
+
+    pub fn turnout(&self) -> StdResult<u64> {
+        self.yes
+            .checked_add(self.no)
+            .and_then(|turnout| turnout.checked_add(self.abstain))
+            .ok_or_else(|| OverflowError::new(OverflowOperation::Add).into())
+    }
+
+    fn count(&mut self, vote: VoteOption) -> &mut u64 {
+        match vote {
+            VoteOption::Yes => &mut self.yes,
+            VoteOption::No => &mut self.no,
+            VoteOption::Abstain => &mut self.abstain,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Proposal {
+    pub id: u64,
+    pub title: String,
+    pub proposer: Addr,
+    pub kind: ProposalKind,
+    pub start_height: u64,
+    pub end_height: u64,
+    pub tally: Tally,
+    pub status: ProposalStatus,
+    #[serde(default)]
+    pub secret: Option<SecretBallot>,
+    #[serde(default)]
+    pub weight: Option<u64>, // Network weight when the proposal opened, the quorum is taken of it
+}
+
+impl Proposal {
+    // Secret ballots are only counted once the reveal period is over
+    pub fn tally_height(&self) -> StdResult<u64> {
+        match &self.secret {
+            Some(secret) => self
+                .end_height
+                .checked_add(secret.reveal_period)
+                .ok_or_else(|| OverflowError::new(OverflowOperation::Add).into()),
+            None => Ok(self.end_height),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Ballot {
+    pub vote: VoteOption,
+    pub weight: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Commitment {
+    pub hash: Binary,
+    pub deposit: Option<Coin>,
+}
+
+// Conviction grows towards staked / (1 - decay) and has to pass
+// weight * total_staked / ((1 - decay) * (max_ratio - requested / treasury)^2)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ConvictionConfig {
+    pub denom: String,
+    pub decay: Decimal,     // Share of the conviction retained every block
+    pub max_ratio: Decimal, // Largest share of the treasury a single request may ask for
+    pub weight: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FundingRequest {
+    pub id: u64,
+    pub scenario: String,
+    pub requester: Addr,
+    pub beneficiary: Addr,
+    pub requested: Uint128,
+    pub staked: Uint128,
+    pub conviction: Uint128,
+    pub last_height: u64, // Height the conviction was last brought up to date
+    pub funded: bool,
+}
+
+// Participatory budgeting: every citizen spreads an allotment over the scenarios of the round
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BudgetRound {
+    pub id: u64,
+    pub proposal_id: u64, // Proposal which opened the round
+    pub budget: u128,
+    pub allotment: u128,
+    pub limits: Vec<ScenarioLimits>,
+    pub support: Vec<(String, u128)>,
+    pub end_height: u64,
+    // Set once the round is finalized. The contract holds no budget of its own, paying
+    // out the allocation is left to whoever carries out the scenarios
+    pub allocation: Option<Vec<(String, u128)>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ScenarioLimits {
+    pub scenario: String,
+    pub max_support: u128, // Most support citizens may allocate to the scenario
+    pub max_funding: u128, // Most of the budget the scenario may receive
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum HistoryEvent {
+    BudgetVote {
+        scenario: String,
+        budget: u128,
+    },
+    Ballot {
+        vote: VoteOption,
+        weight: u64,
+    },
+    VoteChanged {
+        previous: VoteOption,
+        vote: VoteOption,
+        weight: u64,
+    },
+    VoteCommitted {},
+    CommitmentDiscarded {
+        forfeited: bool,
+    },
+    Staked {
+        request_id: u64,
+        amount: Uint128,
+    },
+    Unstaked {
+        request_id: u64,
+        amount: Uint128,
+    },
+    Funded {
+        request_id: u64,
+        amount: Uint128,
+    },
+    BudgetAllocated {
+        round_id: u64,
+        allocations: Vec<(String, u128)>,
+    },
+    RoundFinalized {
+        round_id: u64,
+        allocation: Vec<(String, u128)>,
+    },
+    ProposalStatus {
+        status: ProposalStatus,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub id: u64,
+    pub height: u64,
+    pub actor: Addr,
+    pub proposal_id: Option<u64>,
+    pub event: HistoryEvent,
+}
+
+// Shares the "votes" key with the original raw storage layout
+pub const VOTES: Item<Votes> = Item::new("votes");
+pub const CONFIG: Item<Config> = Item::new("config");
+pub const CITIZENS: Map<&Addr, Citizen> = Map::new("citizens");
+pub const TOTAL_WEIGHT: Item<u64> = Item::new("total_weight"); // Individual citizens only
+pub const SUB_NETWORKS: Map<&Addr, SubNetwork> = Map::new("sub_networks");
+pub const PROPOSALS: Map<u64, Proposal> = Map::new("proposals");
+pub const NEXT_PROPOSAL_ID: Item<u64> = Item::new("next_proposal_id");
+pub const BALLOTS: Map<(u64, &Addr), Ballot> = Map::new("ballots");
+pub const COMMITMENTS: Map<(u64, &Addr), Commitment> = Map::new("commitments");
+pub const CONVICTION: Item<ConvictionConfig> = Item::new("conviction");
+pub const FUNDING_REQUESTS: Map<u64, FundingRequest> = Map::new("funding_requests");
+pub const NEXT_REQUEST_ID: Item<u64> = Item::new("next_request_id");
+pub const STAKES: Map<(u64, &Addr), Uint128> = Map::new("stakes");
+pub const TOTAL_STAKED: Item<Uint128> = Item::new("total_staked");
+pub const BUDGET_ROUNDS: Map<u64, BudgetRound> = Map::new("budget_rounds");
+pub const NEXT_ROUND_ID: Item<u64> = Item::new("next_round_id");
+pub const ALLOCATIONS: Map<(u64, &Addr), Vec<(String, u128)>> = Map::new("allocations");
+
+// Append-only audit trail, entries are never rewritten or removed
+pub const HISTORY: Map<u64, HistoryEntry> = Map::new("history");
+pub const HISTORY_SEQ: Item<u64> = Item::new("history_seq");
+pub const HISTORY_BY_HEIGHT: Map<(u64, u64), Empty> = Map::new("history_by_height");
+pub const HISTORY_BY_PROPOSAL: Map<(u64, u64), Empty> = Map::new("history_by_proposal");
+pub const HISTORY_BY_ACTOR: Map<(&Addr, u64), Empty> = Map::new("history_by_actor");
+
+pub fn load_votes(storage: &dyn Storage) -> StdResult<Votes> {
+    VOTES.load(storage)
+}
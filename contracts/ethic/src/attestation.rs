@@ -0,0 +1,385 @@
+use crate::attester::check_attester;
+use crate::error::ContractError;
+use crate::msg::AttestationsResponse;
+use crate::species::SapienceLevel;
+use crate::state::{ATTESTATIONS, ATTESTERS, ISSUED, NONCES};
+use cosmwasm_std::{
+    to_json_vec, Api, Binary, Deps, DepsMut, Empty, Env, MessageInfo, Order, Response, StdResult,
+    Timestamp,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// What an attester vouches for
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Claim {
+    pub subject: String,
+    pub level: SapienceLevel,
+    pub expires: Timestamp,
+    pub nonce: u64, // Single use per attester key
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+pub enum KeyType {
+    Secp256k1, // 33 or 65 byte public key, 64 byte r || s signature
+    Ed25519,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Attestation {
+    pub claim: Claim,
+    pub key_type: KeyType,
+    pub pubkey: Binary,
+    pub signature: Binary, // Over sign_bytes of the claim
+}
+
+// Binds the claim to one chain and registry so it cannot be replayed elsewhere
+#[derive(Serialize)]
+struct SignDoc<'a> {
+    chain_id: &'a str,
+    registry: &'a str,
+    claim: &'a Claim,
+}
+
+// sha256 of the json encoded sign doc, attesters sign this off-chain
+pub fn sign_bytes(env: &Env, claim: &Claim) -> StdResult<Vec<u8>> {
+    let doc = SignDoc {
+        chain_id: &env.block.chain_id,
+        registry: env.contract.address.as_str(),
+        claim,
+    };
+    Ok(Sha256::digest(to_json_vec(&doc)?).to_vec())
+}
+
+// Checks expiry and signature, nonces are only tracked by submit_attestation
+pub fn verify_attestation(
+    api: &dyn Api,
+    env: &Env,
+    attestation: &Attestation,
+) -> Result<(), ContractError> {
+    if attestation.claim.expires <= env.block.time {
+        return Err(ContractError::AttestationExpired {});
+    }
+    let hash = sign_bytes(env, &attestation.claim)?;
+    let valid = match attestation.key_type {
+        KeyType::Secp256k1 => {
+            api.secp256k1_verify(&hash, &attestation.signature, &attestation.pubkey)
+        }
+        KeyType::Ed25519 => api.ed25519_verify(&hash, &attestation.signature, &attestation.pubkey),
+    };
+    // Malformed keys and signatures are as good as wrong ones
+    if !valid.unwrap_or(false) {
+        return Err(ContractError::InvalidSignature {});
+    }
+    Ok(())
+}
+
+pub fn submit_attestation(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    attestation: Attestation,
+) -> Result<Response, ContractError> {
+    verify_attestation(deps.api, &env, &attestation)?;
+    let pubkey = attestation.pubkey.as_slice();
+    check_attester(
+        deps.as_ref(),
+        &env,
+        pubkey,
+        attestation.key_type,
+        attestation.claim.level,
+    )?;
+    let subject = deps.api.addr_validate(&attestation.claim.subject)?;
+    let nonce = attestation.claim.nonce;
+    if NONCES.has(deps.storage, (pubkey, nonce)) {
+        return Err(ContractError::NonceUsed { nonce });
+    }
+    NONCES.save(deps.storage, (pubkey, nonce), &Empty {})?;
+
+    // The latest attestation of an attester replaces its earlier ones
+    ATTESTATIONS.save(deps.storage, (&subject, pubkey), &attestation)?;
+    ISSUED.save(deps.storage, (pubkey, &subject), &Empty {})?;
+    Ok(Response::new()
+        .add_attribute("action", "submit_attestation")
+        .add_attribute("subject", subject)
+        .add_attribute("level", attestation.claim.level.as_str())
+        .add_attribute("nonce", nonce.to_string()))
+}
+
+// Expired attestations and leftovers of revoked keys are left out
+pub fn query_attestations(
+    deps: Deps,
+    env: Env,
+    subject: String,
+) -> StdResult<AttestationsResponse> {
+    let subject = deps.api.addr_validate(&subject)?;
+    let attestations = ATTESTATIONS
+        .prefix(&subject)
+        .range(deps.storage, None, None, Order::Ascending)
+        .filter(|item| {
+            item.as_ref().map_or(true, |(pubkey, attestation)| {
+                attestation.claim.expires > env.block.time && ATTESTERS.has(deps.storage, pubkey)
+            })
+        })
+        .map(|item| item.map(|(_, attestation)| attestation))
+        .collect::<StdResult<_>>()?;
+    Ok(AttestationsResponse { attestations })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::attestation::{sign_bytes, Attestation, Claim, KeyType};
+    use crate::error::ContractError;
+    use crate::msg::{
+        Action, AttestationsResponse, CertifiedLevelResponse, ExecuteMsg, QueryAction, QueryMsg,
+    };
+    use crate::registry::{execute, query};
+    use crate::species::{Capability, SapienceLevel, SapienceResponse, Sapient, Specie};
+    use crate::testing::{claim, ed25519_attestation, init_msg, register_attester, setup, submit};
+    use cosmwasm_std::{
+        from_json,
+        testing::{message_info, mock_env},
+        Binary, Deps, Env,
+    };
+    use k256::ecdsa::{signature::hazmat::PrehashSigner, Signature, SigningKey};
+
+    fn secp256k1_key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32].into()).unwrap()
+    }
+
+    fn secp256k1_attestation(env: &Env, seed: u8, claim: Claim) -> Attestation {
+        let key = secp256k1_key(seed);
+        let signature: Signature = key.sign_prehash(&sign_bytes(env, &claim).unwrap()).unwrap();
+        Attestation {
+            claim,
+            key_type: KeyType::Secp256k1,
+            pubkey: Binary::from(key.verifying_key().to_encoded_point(true).as_bytes()),
+            signature: Binary::from(signature.to_vec()),
+        }
+    }
+
+    fn certified_level(deps: Deps, env: Env, subject: &str) -> Option<SapienceLevel> {
+        let query_msg = QueryMsg {
+            action: QueryAction::CertifiedLevel {
+                subject: subject.to_string(),
+            },
+        };
+        let res: CertifiedLevelResponse = from_json(query(deps, env, query_msg).unwrap()).unwrap();
+        res.level
+    }
+
+    #[test]
+    fn test_signed_attestations() {
+        let (mut deps, admin) = setup(init_msg());
+        let mut env = mock_env();
+        let relayer = message_info(&deps.api.addr_make("relayer"), &[]);
+        let subject = deps.api.addr_make("raven").to_string();
+        let level = SapienceLevel::Medium;
+
+        let secp = secp256k1_attestation(&env, 7, claim(&env, &subject, level, 1));
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            relayer.clone(),
+            submit(secp.clone()),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::UnknownAttester {}));
+        register_attester(&mut deps, &admin, "oracle", SapienceLevel::High, &[], &secp);
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            relayer.clone(),
+            submit(secp.clone()),
+        )
+        .unwrap();
+        let err = execute(deps.as_mut(), env.clone(), relayer.clone(), submit(secp)).unwrap_err();
+        assert!(matches!(err, ContractError::NonceUsed { nonce: 1 }));
+
+        // The same nonce is free for another attester key
+        let ed = ed25519_attestation(&env, 9, claim(&env, &subject, level, 1));
+        register_attester(&mut deps, &admin, "seer", SapienceLevel::High, &[], &ed);
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            relayer.clone(),
+            submit(ed.clone()),
+        )
+        .unwrap();
+
+        let mut forged = ed25519_attestation(&env, 9, claim(&env, &subject, level, 2));
+        forged.claim.level = SapienceLevel::Low;
+        let err = execute(deps.as_mut(), env.clone(), relayer.clone(), submit(forged)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidSignature {}));
+
+        let query_msg = QueryMsg {
+            action: QueryAction::GetAttestations { subject },
+        };
+        let res: AttestationsResponse =
+            from_json(query(deps.as_ref(), env.clone(), query_msg.clone()).unwrap()).unwrap();
+        assert_eq!(res.attestations.len(), 2);
+
+        env.block.time = env.block.time.plus_seconds(60);
+        let err = execute(deps.as_mut(), env.clone(), relayer, submit(ed)).unwrap_err();
+        assert!(matches!(err, ContractError::AttestationExpired {}));
+        let res: AttestationsResponse =
+            from_json(query(deps.as_ref(), env, query_msg).unwrap()).unwrap();
+        assert!(res.attestations.is_empty());
+    }
+
+    #[test]
+    fn test_web_of_trust() {
+        let (mut deps, admin) = setup(init_msg());
+        let env = mock_env();
+        let relayer = message_info(&deps.api.addr_make("relayer"), &[]);
+        let subject = deps.api.addr_make("human").to_string();
+        let attest = |seed, level, nonce| {
+            secp256k1_attestation(&env, seed, claim(&env, &subject, level, nonce))
+        };
+
+        let first = attest(1, SapienceLevel::Unreachable, 1);
+        let second = attest(2, SapienceLevel::Sentient, 1);
+        let sage_key = second.pubkey.clone();
+        register_attester(
+            &mut deps,
+            &admin,
+            "elder",
+            SapienceLevel::Sentient,
+            &[Capability::Loving],
+            &first,
+        );
+        register_attester(
+            &mut deps,
+            &admin,
+            "sage",
+            SapienceLevel::MettaPlanar,
+            &[Capability::Transcendent],
+            &second,
+        );
+
+        // Nobody can vouch for their own level or above
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            relayer.clone(),
+            submit(attest(1, SapienceLevel::Sentient, 2)),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::LevelTooHigh {}));
+
+        // A single attester suffices up to High, above it two have to agree
+        execute(deps.as_mut(), env.clone(), relayer.clone(), submit(first)).unwrap();
+        assert_eq!(certified_level(deps.as_ref(), env.clone(), &subject), None);
+        let high = attest(2, SapienceLevel::High, 2);
+        execute(deps.as_mut(), env.clone(), relayer.clone(), submit(high)).unwrap();
+        assert_eq!(
+            certified_level(deps.as_ref(), env.clone(), &subject),
+            Some(SapienceLevel::High)
+        );
+        execute(deps.as_mut(), env.clone(), relayer.clone(), submit(second)).unwrap();
+        assert_eq!(
+            certified_level(deps.as_ref(), env.clone(), &subject),
+            Some(SapienceLevel::Unreachable)
+        );
+        let classify = QueryMsg {
+            action: QueryAction::Classify {
+                sapient: Sapient {
+                    name: "Human".to_string(),
+                    telepathic: false,
+                    chaotic: false,
+                    transcendent: false,
+                    omnipresent: false,
+                    loving: true,
+                },
+                subject: subject.clone(),
+            },
+        };
+        let res: SapienceResponse =
+            from_json(query(deps.as_ref(), env.clone(), classify).unwrap()).unwrap();
+        assert_eq!(res.level, SapienceLevel::Unreachable);
+
+        // Attestations count only while their attester could still vouch for them
+        let elder = deps.api.addr_make("elder").to_string();
+        let change = |action| ExecuteMsg { action };
+        let changes = [
+            (
+                Action::SuspendPassport {
+                    address: elder.clone(),
+                    until: env.block.time.plus_seconds(60),
+                },
+                None,
+            ),
+            (
+                Action::ReinstatePassport {
+                    address: elder.clone(),
+                },
+                Some(SapienceLevel::Unreachable),
+            ),
+            (
+                Action::UpdatePassport {
+                    address: elder.clone(),
+                    specie: Specie {
+                        name: "elder".to_string(),
+                        sapience_level: SapienceLevel::Unreachable,
+                    },
+                },
+                None,
+            ),
+        ];
+        for (action, level) in changes {
+            execute(deps.as_mut(), env.clone(), admin.clone(), change(action)).unwrap();
+            assert_eq!(certified_level(deps.as_ref(), env.clone(), &subject), level);
+        }
+
+        // Revoking an attester drops everything it vouched for, a page at a time
+        for nonce in 3..34 {
+            let other = deps.api.addr_make(&format!("subject{}", nonce)).to_string();
+            let attestation =
+                secp256k1_attestation(&env, 2, claim(&env, &other, SapienceLevel::Low, nonce));
+            execute(
+                deps.as_mut(),
+                env.clone(),
+                relayer.clone(),
+                submit(attestation),
+            )
+            .unwrap();
+        }
+        let revoke = ExecuteMsg {
+            action: Action::RevokeAttester {
+                address: deps.api.addr_make("sage").to_string(),
+            },
+        };
+        let err = execute(deps.as_mut(), env.clone(), relayer.clone(), revoke.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let res = execute(deps.as_mut(), env.clone(), admin.clone(), revoke).unwrap();
+        assert_eq!(res.attributes[2].value, "30");
+        assert_eq!(res.attributes[3].value, "true");
+        assert_eq!(certified_level(deps.as_ref(), env.clone(), &subject), None);
+        let register = ExecuteMsg {
+            action: Action::RegisterAttester {
+                address: deps.api.addr_make("sage").to_string(),
+                key_type: KeyType::Secp256k1,
+                pubkey: sage_key.clone(),
+            },
+        };
+        let err = execute(deps.as_mut(), env.clone(), admin.clone(), register.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::AttestationsPending {}));
+        let prune = ExecuteMsg {
+            action: Action::PruneAttestations {
+                pubkey: sage_key,
+                limit: None,
+            },
+        };
+        let res = execute(deps.as_mut(), env.clone(), relayer, prune).unwrap();
+        assert_eq!(res.attributes[1].value, "2");
+        assert_eq!(res.attributes[2].value, "false");
+        execute(deps.as_mut(), env.clone(), admin, register).unwrap();
+        let query_msg = QueryMsg {
+            action: QueryAction::GetAttestations { subject },
+        };
+        let res: AttestationsResponse =
+            from_json(query(deps.as_ref(), env, query_msg).unwrap()).unwrap();
+        assert_eq!(res.attestations.len(), 1);
+    }
+}
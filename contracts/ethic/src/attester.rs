@@ -0,0 +1,219 @@
+use crate::attestation::KeyType;
+use crate::error::ContractError;
+use crate::lifecycle::check_active;
+use crate::msg::{AttesterResponse, CertifiedLevelResponse};
+use crate::registry::{check_admin, DEFAULT_LIMIT, MAX_LIMIT};
+use crate::roles::has_role;
+use crate::species::{Role, SapienceLevel, SapienceResponse, Sapient};
+use crate::state::{passports, Attester, ATTESTATIONS, ATTESTERS, ATTESTER_KEYS, CONFIG, ISSUED};
+use cosmwasm_std::{
+    Addr, Binary, Deps, DepsMut, Env, MessageInfo, Order, Response, StdResult, Storage,
+};
+
+// Attesters vouch with the level of their own passport while they hold the attester
+// role, one key per address
+pub fn register_attester(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+    key_type: KeyType,
+    pubkey: Binary,
+) -> Result<Response, ContractError> {
+    check_admin(deps.as_ref(), &env, &info.sender)?;
+    let address = deps.api.addr_validate(&address)?;
+    check_active(deps.as_ref(), &env, &address)?;
+    check_attester_role(deps.as_ref(), &env, &address)?;
+    if ATTESTER_KEYS.has(deps.storage, &address) || ATTESTERS.has(deps.storage, &pubkey) {
+        return Err(ContractError::AttesterExists {});
+    }
+    // Leftovers of a revoked key would count again
+    if ISSUED
+        .prefix(&pubkey)
+        .keys(deps.storage, None, None, Order::Ascending)
+        .next()
+        .is_some()
+    {
+        return Err(ContractError::AttestationsPending {});
+    }
+
+    let attester = Attester {
+        address: address.clone(),
+        key_type,
+    };
+    ATTESTERS.save(deps.storage, &pubkey, &attester)?;
+    ATTESTER_KEYS.save(deps.storage, &address, &pubkey)?;
+    Ok(Response::new()
+        .add_attribute("action", "register_attester")
+        .add_attribute("attester", address))
+}
+
+// Revocation takes every attestation the attester issued down with it, they stop
+// counting with the key and are removed a page at a time
+pub fn revoke_attester(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+) -> Result<Response, ContractError> {
+    check_admin(deps.as_ref(), &env, &info.sender)?;
+    let address = deps.api.addr_validate(&address)?;
+    let pubkey = ATTESTER_KEYS
+        .may_load(deps.storage, &address)?
+        .ok_or(ContractError::UnknownAttester {})?;
+
+    ATTESTERS.remove(deps.storage, &pubkey);
+    ATTESTER_KEYS.remove(deps.storage, &address);
+    let (removed, more) = prune(deps.storage, &pubkey, MAX_LIMIT as usize)?;
+    Ok(Response::new()
+        .add_attribute("action", "revoke_attester")
+        .add_attribute("attester", address)
+        .add_attribute("revoked_attestations", removed.to_string())
+        .add_attribute("more", more.to_string()))
+}
+
+pub fn prune_attestations(
+    deps: DepsMut,
+    pubkey: Binary,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    if ATTESTERS.has(deps.storage, &pubkey) {
+        return Err(ContractError::AttesterExists {});
+    }
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let (removed, more) = prune(deps.storage, &pubkey, limit)?;
+    Ok(Response::new()
+        .add_attribute("action", "prune_attestations")
+        .add_attribute("revoked_attestations", removed.to_string())
+        .add_attribute("more", more.to_string()))
+}
+
+// Removes up to limit attestations of the key, tells whether any are left
+fn prune(storage: &mut dyn Storage, pubkey: &[u8], limit: usize) -> StdResult<(usize, bool)> {
+    let mut subjects = ISSUED
+        .prefix(pubkey)
+        .keys(storage, None, None, Order::Ascending)
+        .take(limit + 1)
+        .collect::<StdResult<Vec<_>>>()?;
+    let more = subjects.len() > limit;
+    subjects.truncate(limit);
+    for subject in &subjects {
+        ATTESTATIONS.remove(storage, (subject, pubkey));
+        ISSUED.remove(storage, (pubkey, subject));
+    }
+    Ok((subjects.len(), more))
+}
+
+// Looks up the attester behind a key and the highest level it may vouch for,
+// attesters whose passport is not active cannot vouch at all
+pub fn check_attester(
+    deps: Deps,
+    env: &Env,
+    pubkey: &[u8],
+    key_type: KeyType,
+    level: SapienceLevel,
+) -> Result<Attester, ContractError> {
+    let attester = ATTESTERS
+        .may_load(deps.storage, pubkey)?
+        .filter(|attester| attester.key_type == key_type)
+        .ok_or(ContractError::UnknownAttester {})?;
+    let own = check_active(deps, env, &attester.address)?
+        .specie
+        .sapience_level;
+    check_attester_role(deps, env, &attester.address)?;
+    if !own.can_attest(level) {
+        return Err(ContractError::LevelTooHigh {});
+    }
+    Ok(attester)
+}
+
+fn check_attester_role(deps: Deps, env: &Env, address: &Addr) -> Result<(), ContractError> {
+    if !has_role(deps, env, address, Role::Attester)? {
+        return Err(ContractError::RoleRequired {
+            address: address.to_string(),
+            role: Role::Attester,
+        });
+    }
+    Ok(())
+}
+
+// Level of the attester behind the key while its passport is active and it holds
+// the attester role
+pub(crate) fn attester_level(
+    deps: Deps,
+    env: &Env,
+    pubkey: &[u8],
+) -> StdResult<Option<SapienceLevel>> {
+    let attester = match ATTESTERS.may_load(deps.storage, pubkey)? {
+        Some(attester) => attester,
+        None => return Ok(None),
+    };
+    if !has_role(deps, env, &attester.address, Role::Attester)? {
+        return Ok(None);
+    }
+    Ok(passports()
+        .may_load(deps.storage, &attester.address)?
+        .filter(|passport| passport.is_active(env.block.time))
+        .map(|passport| passport.specie.sapience_level))
+}
+
+pub fn query_attester(deps: Deps, address: String) -> StdResult<AttesterResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let pubkey = ATTESTER_KEYS.load(deps.storage, &address)?;
+    let attester = ATTESTERS.load(deps.storage, &pubkey)?;
+    Ok(AttesterResponse {
+        address,
+        key_type: attester.key_type,
+        pubkey,
+    })
+}
+
+// The highest level enough unexpired attestations agree on, above the configured level
+// that takes several independent attesters, below it a single one will do. Only
+// attesters who could still vouch for the claim today count
+pub fn certified_level(deps: Deps, env: Env, subject: String) -> StdResult<CertifiedLevelResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let subject = deps.api.addr_validate(&subject)?;
+    let mut levels = vec![];
+    for item in ATTESTATIONS
+        .prefix(&subject)
+        .range(deps.storage, None, None, Order::Ascending)
+    {
+        let (pubkey, attestation) = item?;
+        if attestation.claim.expires <= env.block.time {
+            continue;
+        }
+        let own = attester_level(deps, &env, &pubkey)?;
+        if own.is_some_and(|own| own.can_attest(attestation.claim.level)) {
+            levels.push(attestation.claim.level);
+        }
+    }
+    levels.sort_by_key(|level| std::cmp::Reverse(*level));
+
+    let level = levels
+        .iter()
+        .enumerate()
+        .find(|(i, level)| {
+            let required = if **level > config.multi_attestation_level {
+                config.required_attestations
+            } else {
+                1
+            };
+            // Everyone up to here vouched for at least this level
+            *i as u32 + 1 >= required
+        })
+        .map(|(_, level)| *level);
+    Ok(CertifiedLevelResponse { subject, level })
+}
+
+pub fn classify(
+    deps: Deps,
+    env: Env,
+    sapient: Sapient,
+    subject: String,
+) -> StdResult<SapienceResponse> {
+    let evidence = certified_level(deps, env, subject)?.level;
+    Ok(SapienceResponse {
+        level: sapient.classify(evidence),
+    })
+}
@@ -0,0 +1,256 @@
+use crate::error::ContractError;
+use crate::msg::{CitizenCountResponse, SpeciesListResponse, SpeciesResponse};
+use crate::registry::{check_admin, DEFAULT_LIMIT, MAX_LIMIT};
+use crate::species::{SapienceLevel, Specie, SpeciesInfo};
+use crate::state::{CITIZENS, SPECIES};
+use cosmwasm_std::{Deps, DepsMut, Env, MessageInfo, Order, Response, StdResult, Storage};
+use cw_storage_plus::Bound;
+
+pub fn register_species(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    species: SpeciesInfo,
+) -> Result<Response, ContractError> {
+    check_admin(deps.as_ref(), &env, &info.sender)?;
+    if SPECIES.has(deps.storage, &species.name) {
+        return Err(ContractError::SpeciesExists { name: species.name });
+    }
+    if species.default_level > species.profile().ceiling() {
+        return Err(ContractError::InvalidSpecies {});
+    }
+    SPECIES.save(deps.storage, &species.name, &species)?;
+    Ok(Response::new()
+        .add_attribute("action", "register_species")
+        .add_attribute("species", species.name)
+        .add_attribute("level", species.default_level.as_str()))
+}
+
+pub(crate) fn check_species(storage: &dyn Storage, name: &str) -> Result<(), ContractError> {
+    if !SPECIES.has(storage, name) {
+        return Err(ContractError::UnknownSpecies {
+            name: name.to_string(),
+        });
+    }
+    Ok(())
+}
+
+pub(crate) fn default_level(
+    storage: &dyn Storage,
+    name: &str,
+) -> Result<SapienceLevel, ContractError> {
+    SPECIES
+        .may_load(storage, name)?
+        .map(|species| species.default_level)
+        .ok_or_else(|| ContractError::UnknownSpecies {
+            name: name.to_string(),
+        })
+}
+
+// Passports may not claim more than the catalog grants their species
+pub(crate) fn check_level(storage: &dyn Storage, specie: &Specie) -> Result<(), ContractError> {
+    let default_level = default_level(storage, &specie.name)?;
+    if specie.sapience_level > default_level {
+        return Err(ContractError::LevelAboveSpecies {
+            name: specie.name.clone(),
+            level: default_level,
+        });
+    }
+    Ok(())
+}
+
+pub(crate) fn add_citizen(storage: &mut dyn Storage, name: &str) -> StdResult<()> {
+    let citizens = CITIZENS.may_load(storage, name)?.unwrap_or_default();
+    CITIZENS.save(storage, name, &(citizens + 1))
+}
+
+pub(crate) fn remove_citizen(storage: &mut dyn Storage, name: &str) -> StdResult<()> {
+    let citizens = CITIZENS.may_load(storage, name)?.unwrap_or_default();
+    CITIZENS.save(storage, name, &citizens.saturating_sub(1))
+}
+
+pub fn list_species(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<SpeciesListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let species = SPECIES
+        .range(
+            deps.storage,
+            start_after.as_deref().map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| {
+            let (name, species) = item?;
+            Ok(SpeciesResponse {
+                species,
+                citizens: CITIZENS.may_load(deps.storage, &name)?.unwrap_or_default(),
+            })
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(SpeciesListResponse { species })
+}
+
+// Retired passports no longer count
+pub fn query_citizen_count(deps: Deps, species: String) -> StdResult<CitizenCountResponse> {
+    let citizens = CITIZENS
+        .may_load(deps.storage, &species)?
+        .unwrap_or_default();
+    Ok(CitizenCountResponse { species, citizens })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ContractError;
+    use crate::msg::{
+        Action, CitizenCountResponse, ExecuteMsg, QueryAction, QueryMsg, SpeciesListResponse,
+    };
+    use crate::registry::{execute, query};
+    use crate::species::{Capability, SapienceLevel, Specie, SpeciesInfo};
+    use crate::testing::{init_msg, setup};
+    use cosmwasm_std::{
+        from_json,
+        testing::{message_info, mock_env},
+        Deps,
+    };
+
+    fn species(
+        name: &str,
+        default_level: SapienceLevel,
+        capabilities: Vec<Capability>,
+    ) -> ExecuteMsg {
+        ExecuteMsg {
+            action: Action::RegisterSpecies {
+                species: SpeciesInfo {
+                    name: name.to_string(),
+                    default_level,
+                    description: format!("{}s", name),
+                    capabilities,
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn test_species_catalog() {
+        let (mut deps, admin) = setup(init_msg());
+
+        let human = species("Human", SapienceLevel::Medium, vec![]);
+        let stranger = message_info(&deps.api.addr_make("stranger"), &[]);
+        let err = execute(deps.as_mut(), mock_env(), stranger, human.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), mock_env(), admin.clone(), human.clone()).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), admin.clone(), human).unwrap_err();
+        assert!(matches!(err, ContractError::SpeciesExists { .. }));
+
+        // Only an omnipresent and loving species may default to Lain
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            species("Navi", SapienceLevel::Lain, vec![Capability::Omnipresent]),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::InvalidSpecies {}));
+        let navi = species(
+            "Navi",
+            SapienceLevel::Lain,
+            vec![Capability::Omnipresent, Capability::Loving],
+        );
+        execute(deps.as_mut(), mock_env(), admin.clone(), navi).unwrap();
+
+        let specie = |name: &str| Specie {
+            name: name.to_string(),
+            sapience_level: SapienceLevel::Medium,
+        };
+        let register = |address: &str, name: &str| ExecuteMsg {
+            action: Action::RegisterPassport {
+                address: address.to_string(),
+                participant_id: address.to_string(),
+                specie: specie(name),
+            },
+        };
+        let alice = deps.api.addr_make("alice");
+        let bob = deps.api.addr_make("bob");
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            register(alice.as_str(), "Raven"),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::UnknownSpecies { .. }));
+        for address in [&alice, &bob] {
+            let msg = register(address.as_str(), "Human");
+            execute(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        }
+        let promote = ExecuteMsg {
+            action: Action::UpdatePassport {
+                address: alice.to_string(),
+                specie: Specie {
+                    name: "Human".to_string(),
+                    sapience_level: SapienceLevel::High,
+                },
+            },
+        };
+        let err = execute(deps.as_mut(), mock_env(), admin.clone(), promote).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::LevelAboveSpecies {
+                level: SapienceLevel::Medium,
+                ..
+            }
+        ));
+
+        let citizens = |deps: Deps, name: &str| -> u32 {
+            let query_msg = QueryMsg {
+                action: QueryAction::CitizenCount {
+                    species: name.to_string(),
+                },
+            };
+            let res: CitizenCountResponse =
+                from_json(query(deps, mock_env(), query_msg).unwrap()).unwrap();
+            res.citizens
+        };
+        assert_eq!(citizens(deps.as_ref(), "Human"), 2);
+
+        // Moving a passport to another species moves the citizen
+        let update = ExecuteMsg {
+            action: Action::UpdatePassport {
+                address: bob.to_string(),
+                specie: specie("Navi"),
+            },
+        };
+        execute(deps.as_mut(), mock_env(), admin.clone(), update).unwrap();
+        assert_eq!(citizens(deps.as_ref(), "Human"), 1);
+        assert_eq!(citizens(deps.as_ref(), "Navi"), 1);
+        let retire = ExecuteMsg {
+            action: Action::RetirePassport {
+                address: bob.to_string(),
+            },
+        };
+        execute(deps.as_mut(), mock_env(), admin, retire).unwrap();
+        assert_eq!(citizens(deps.as_ref(), "Navi"), 0);
+
+        let list = |deps: Deps, start_after: Option<String>| -> SpeciesListResponse {
+            let query_msg = QueryMsg {
+                action: QueryAction::ListSpecies {
+                    start_after,
+                    limit: Some(1),
+                },
+            };
+            from_json(query(deps, mock_env(), query_msg).unwrap()).unwrap()
+        };
+        let first = list(deps.as_ref(), None).species;
+        assert_eq!(first[0].species.name, "Human");
+        assert_eq!(first[0].citizens, 1);
+        let second = list(deps.as_ref(), Some("Human".to_string())).species;
+        assert_eq!(second[0].species.name, "Navi");
+        assert!(list(deps.as_ref(), Some("Navi".to_string()))
+            .species
+            .is_empty());
+    }
+}
@@ -0,0 +1,66 @@
+use cosmwasm_std::StdError;
+use thiserror::Error;
+
+use crate::species::{Role, SapienceLevel};
+
+#[derive(Error, Debug)]
+pub enum ContractError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+    #[error("Only the registry admin may do this")]
+    Unauthorized {},
+    #[error("{address} already holds a passport")]
+    PassportExists { address: String },
+    #[error("{address} holds no passport")]
+    PassportNotFound { address: String },
+    #[error("Attestation has expired")]
+    AttestationExpired {},
+    #[error("Attestation signature does not verify")]
+    InvalidSignature {},
+    #[error("Nonce {nonce} was already used by this attester")]
+    NonceUsed { nonce: u64 },
+    #[error("Address or key is already registered as an attester")]
+    AttesterExists {},
+    #[error("Attestations of this key are still to be pruned")]
+    AttestationsPending {},
+    #[error("Key does not belong to a registered attester")]
+    UnknownAttester {},
+    #[error("Attesters can only vouch for levels strictly below their own")]
+    LevelTooHigh {},
+    #[error("At least one attestation and one naturalization vote must be required")]
+    InvalidTrustConfig {},
+    #[error("Visas last at most {max} seconds")]
+    VisaTooLong { max: u64 },
+    #[error("Passports stay valid for 1 to {max} seconds")]
+    InvalidPassportValidity { max: u64 },
+    #[error("Traveler already holds a valid visa")]
+    VisaExists {},
+    #[error("{address} holds no visa")]
+    VisaNotFound { address: String },
+    #[error("Visa has expired")]
+    VisaExpired {},
+    #[error("Naturalization of this traveler was rejected")]
+    NaturalizationRejected {},
+    #[error("Already voted on this naturalization")]
+    AlreadyVoted {},
+    #[error("Passport of {address} is not active")]
+    PassportInactive { address: String },
+    #[error("Retired passports cannot be changed")]
+    PassportRetired {},
+    #[error("Suspensions must end in the future")]
+    InvalidSuspension {},
+    #[error("Policy {id} does not exist")]
+    PolicyNotFound { id: u64 },
+    #[error("Species {name} is already registered")]
+    SpeciesExists { name: String },
+    #[error("Species {name} is not registered")]
+    UnknownSpecies { name: String },
+    #[error("Species {name} only reaches {level}")]
+    LevelAboveSpecies { name: String, level: SapienceLevel },
+    #[error("Default level exceeds what the species' capabilities allow")]
+    InvalidSpecies {},
+    #[error("{address} does not hold the {role} role")]
+    RoleRequired { address: String, role: Role },
+    #[error("You think you're so smart, don't ya? This takes at least {required}")]
+    NotSapientEnough { required: SapienceLevel },
+}
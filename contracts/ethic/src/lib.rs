@@ -1,3 +1,14 @@
+pub mod attestation;
+pub mod attester;
+pub mod catalog;
+pub mod error;
+pub mod lifecycle;
+pub mod msg;
+pub mod policy;
+pub mod registry;
+pub mod roles;
 pub mod species;
-mod msg;
-mod state;
\ No newline at end of file
+mod state;
+#[cfg(test)]
+mod testing;
+pub mod visa;
@@ -0,0 +1,470 @@
+use crate::catalog::{add_citizen, check_level, remove_citizen};
+use crate::error::ContractError;
+use crate::msg::PassportHistoryResponse;
+use crate::registry::{check_admin, DEFAULT_LIMIT, MAX_LIMIT};
+use crate::roles::has_role;
+use crate::species::{Passport, PassportStatus, Role, Specie};
+use crate::state::{
+    passports, StatusChange, CONFIG, NATURALIZATION_VOTES, PASSPORT_HISTORY, VISAS,
+};
+use cosmwasm_std::{
+    Addr, Deps, DepsMut, Env, MessageInfo, Order, Response, StdResult, Storage, Timestamp,
+};
+use cw_storage_plus::Bound;
+
+// Saves a fresh active passport of a registered species, valid for the configured period
+// and at most at the level of its species. The passport replaces any visa of the address
+pub(crate) fn issue_passport(
+    storage: &mut dyn Storage,
+    env: &Env,
+    by: &Addr,
+    address: &Addr,
+    participant_id: String,
+    specie: Specie,
+) -> Result<Passport, ContractError> {
+    check_level(storage, &specie)?;
+    let config = CONFIG.load(storage)?;
+    let passport = Passport {
+        participant_id,
+        specie,
+        status: PassportStatus::Active,
+        expires: config
+            .passport_validity
+            .map(|validity| env.block.time.plus_seconds(validity)),
+    };
+    passports().save(storage, address, &passport)?;
+    VISAS.remove(storage, address);
+    NATURALIZATION_VOTES.prefix(address).clear(storage, None);
+    add_citizen(storage, &passport.specie.name)?;
+    record_status(storage, env, by, address, &passport)?;
+    Ok(passport)
+}
+
+// The passport of an address that may act right now
+pub(crate) fn check_active(
+    deps: Deps,
+    env: &Env,
+    address: &Addr,
+) -> Result<Passport, ContractError> {
+    let passport = passports()
+        .may_load(deps.storage, address)?
+        .ok_or_else(|| ContractError::PassportNotFound {
+            address: address.to_string(),
+        })?;
+    if !passport.is_active(env.block.time) {
+        return Err(ContractError::PassportInactive {
+            address: address.to_string(),
+        });
+    }
+    Ok(passport)
+}
+
+// Lapses by time are not recorded, status_at derives them
+fn record_status(
+    storage: &mut dyn Storage,
+    env: &Env,
+    by: &Addr,
+    address: &Addr,
+    passport: &Passport,
+) -> StdResult<()> {
+    let seq = PASSPORT_HISTORY
+        .prefix(address)
+        .keys(storage, None, None, Order::Descending)
+        .next()
+        .transpose()?
+        .map_or(0, |last| last + 1);
+    let change = StatusChange {
+        status: passport.status_at(env.block.time),
+        time: env.block.time,
+        by: by.clone(),
+    };
+    PASSPORT_HISTORY.save(storage, (address, seq), &change)
+}
+
+// Loads a passport governance may still change
+pub(crate) fn load_unretired(deps: Deps, address: &Addr) -> Result<Passport, ContractError> {
+    let passport = passports()
+        .may_load(deps.storage, address)?
+        .ok_or_else(|| ContractError::PassportNotFound {
+            address: address.to_string(),
+        })?;
+    if passport.status == PassportStatus::Retired {
+        return Err(ContractError::PassportRetired {});
+    }
+    Ok(passport)
+}
+
+fn change_status(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+    action: &str,
+    role: Option<Role>,
+    change: impl FnOnce(&mut Passport, Timestamp) -> Result<(), ContractError>,
+) -> Result<Response, ContractError> {
+    // Besides the admins, holders of the role may make this change
+    let by_role = match role {
+        Some(role) => has_role(deps.as_ref(), &env, &info.sender, role)?,
+        None => false,
+    };
+    if !by_role {
+        check_admin(deps.as_ref(), &env, &info.sender)?;
+    }
+    let address = deps.api.addr_validate(&address)?;
+    let mut passport = load_unretired(deps.as_ref(), &address)?;
+    change(&mut passport, env.block.time)?;
+    passports().save(deps.storage, &address, &passport)?;
+    record_status(deps.storage, &env, &info.sender, &address, &passport)?;
+    Ok(Response::new()
+        .add_attribute("action", action)
+        .add_attribute("address", address)
+        .add_attribute("status", passport.status_at(env.block.time).as_str()))
+}
+
+pub fn suspend_passport(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+    until: Timestamp,
+) -> Result<Response, ContractError> {
+    change_status(
+        deps,
+        env,
+        info,
+        address,
+        "suspend_passport",
+        Some(Role::Arbiter),
+        |passport, now| {
+            if until <= now {
+                return Err(ContractError::InvalidSuspension {});
+            }
+            passport.status = PassportStatus::Suspended { until };
+            Ok(())
+        },
+    )
+}
+
+// Lifts a suspension, an expired passport needs renewing on top
+pub fn reinstate_passport(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+) -> Result<Response, ContractError> {
+    change_status(
+        deps,
+        env,
+        info,
+        address,
+        "reinstate_passport",
+        Some(Role::Arbiter),
+        |passport, _| {
+            passport.status = PassportStatus::Active;
+            Ok(())
+        },
+    )
+}
+
+// A suspension outlives the renewal
+pub fn renew_passport(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+) -> Result<Response, ContractError> {
+    let validity = CONFIG.load(deps.storage)?.passport_validity;
+    change_status(
+        deps,
+        env,
+        info,
+        address,
+        "renew_passport",
+        None,
+        |passport, now| {
+            passport.expires = validity.map(|validity| now.plus_seconds(validity));
+            Ok(())
+        },
+    )
+}
+
+// Retired holders no longer count as citizens of their species
+pub fn retire_passport(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+) -> Result<Response, ContractError> {
+    let response = change_status(
+        deps.branch(),
+        env,
+        info,
+        address.clone(),
+        "retire_passport",
+        None,
+        |passport, _| {
+            passport.status = PassportStatus::Retired;
+            Ok(())
+        },
+    )?;
+    let address = deps.api.addr_validate(&address)?;
+    let passport = passports().load(deps.storage, &address)?;
+    remove_citizen(deps.storage, &passport.specie.name)?;
+    Ok(response)
+}
+
+pub fn query_passport_history(
+    deps: Deps,
+    address: String,
+    start_after: Option<u32>,
+    limit: Option<u32>,
+) -> StdResult<PassportHistoryResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let history = PASSPORT_HISTORY
+        .prefix(&address)
+        .range(
+            deps.storage,
+            start_after.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| item.map(|(_, change)| change))
+        .collect::<StdResult<_>>()?;
+    Ok(PassportHistoryResponse { address, history })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ContractError;
+    use crate::msg::{
+        Action, ExecuteMsg, HasRoleResponse, InstantiateMsg, PassportHistoryResponse,
+        PassportResponse, QueryAction, QueryMsg,
+    };
+    use crate::registry::{execute, instantiate, query, MAX_PASSPORT_VALIDITY};
+    use crate::species::{PassportStatus, Role, SapienceLevel, Specie};
+    use crate::testing::{init_msg, register_test_species, setup};
+    use cosmwasm_std::{
+        from_json,
+        testing::{message_info, mock_dependencies, mock_env},
+        Addr, Deps, Env,
+    };
+
+    #[test]
+    fn test_passport_validity() {
+        for validity in [0, MAX_PASSPORT_VALIDITY + 1, u64::MAX] {
+            let mut deps = mock_dependencies();
+            let admin = message_info(&deps.api.addr_make("admin"), &[]);
+            let msg = InstantiateMsg {
+                passport_validity: Some(validity),
+                ..init_msg()
+            };
+            let err = instantiate(deps.as_mut(), mock_env(), admin, msg).unwrap_err();
+            assert!(matches!(
+                err,
+                ContractError::InvalidPassportValidity {
+                    max: MAX_PASSPORT_VALIDITY
+                }
+            ));
+        }
+
+        // The longest validity still fits a block time
+        let (mut deps, admin) = setup(InstantiateMsg {
+            passport_validity: Some(MAX_PASSPORT_VALIDITY),
+            ..init_msg()
+        });
+        register_test_species(
+            deps.as_mut(),
+            &admin,
+            &[("Raven", SapienceLevel::Medium, &[])],
+        );
+        let raven = deps.api.addr_make("raven");
+        let register = ExecuteMsg {
+            action: Action::RegisterPassport {
+                address: raven.to_string(),
+                participant_id: "raven".to_string(),
+                specie: Specie {
+                    name: "Raven".to_string(),
+                    sapience_level: SapienceLevel::Medium,
+                },
+            },
+        };
+        execute(deps.as_mut(), mock_env(), admin, register).unwrap();
+        let query_msg = QueryMsg {
+            action: QueryAction::GetPassport {
+                address: raven.to_string(),
+            },
+        };
+        let res: PassportResponse =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        let expires = mock_env().block.time.plus_seconds(MAX_PASSPORT_VALIDITY);
+        assert_eq!(res.passport.expires, Some(expires));
+    }
+
+    #[test]
+    fn test_passport_lifecycle() {
+        let (mut deps, admin) = setup(InstantiateMsg {
+            passport_validity: Some(100),
+            ..init_msg()
+        });
+        let mut env = mock_env();
+        let start = env.block.time;
+        register_test_species(
+            deps.as_mut(),
+            &admin,
+            &[("Raven", SapienceLevel::Medium, &[])],
+        );
+        let clerk = deps.api.addr_make("clerk");
+        let register = ExecuteMsg {
+            action: Action::RegisterPassport {
+                address: clerk.to_string(),
+                participant_id: "clerk".to_string(),
+                specie: Specie {
+                    name: "Raven".to_string(),
+                    sapience_level: SapienceLevel::Medium,
+                },
+            },
+        };
+        execute(deps.as_mut(), env.clone(), admin.clone(), register).unwrap();
+        let grant = ExecuteMsg {
+            action: Action::GrantRole {
+                address: clerk.to_string(),
+                role: Role::Archivist,
+            },
+        };
+        execute(deps.as_mut(), env.clone(), admin.clone(), grant.clone()).unwrap();
+
+        let status = |deps: Deps, env: Env, address: &Addr| -> PassportStatus {
+            let query_msg = QueryMsg {
+                action: QueryAction::GetPassport {
+                    address: address.to_string(),
+                },
+            };
+            let res: PassportResponse = from_json(query(deps, env, query_msg).unwrap()).unwrap();
+            res.status
+        };
+        let has_role = |deps: Deps, env: Env, address: &Addr| -> bool {
+            let query_msg = QueryMsg {
+                action: QueryAction::HasRole {
+                    address: address.to_string(),
+                    role: Role::Archivist,
+                },
+            };
+            let res: HasRoleResponse = from_json(query(deps, env, query_msg).unwrap()).unwrap();
+            res.has_role
+        };
+        assert_eq!(
+            status(deps.as_ref(), env.clone(), &clerk),
+            PassportStatus::Active
+        );
+
+        let suspend = |until| ExecuteMsg {
+            action: Action::SuspendPassport {
+                address: clerk.to_string(),
+                until,
+            },
+        };
+        let stranger = message_info(&deps.api.addr_make("stranger"), &[]);
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            stranger,
+            suspend(start.plus_seconds(50)),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let err = execute(deps.as_mut(), env.clone(), admin.clone(), suspend(start)).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidSuspension {}));
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            admin.clone(),
+            suspend(start.plus_seconds(50)),
+        )
+        .unwrap();
+        assert_eq!(
+            status(deps.as_ref(), env.clone(), &clerk),
+            PassportStatus::Suspended {
+                until: start.plus_seconds(50)
+            }
+        );
+        assert!(!has_role(deps.as_ref(), env.clone(), &clerk));
+
+        // The suspension lapses by itself, then the passport expires
+        env.block.time = start.plus_seconds(60);
+        assert!(has_role(deps.as_ref(), env.clone(), &clerk));
+        env.block.time = start.plus_seconds(100);
+        assert_eq!(
+            status(deps.as_ref(), env.clone(), &clerk),
+            PassportStatus::Expired
+        );
+        assert!(!has_role(deps.as_ref(), env.clone(), &clerk));
+        let err = execute(deps.as_mut(), env.clone(), admin.clone(), grant).unwrap_err();
+        assert!(matches!(err, ContractError::PassportInactive { .. }));
+
+        let renew = ExecuteMsg {
+            action: Action::RenewPassport {
+                address: clerk.to_string(),
+            },
+        };
+        execute(deps.as_mut(), env.clone(), admin.clone(), renew.clone()).unwrap();
+        assert!(has_role(deps.as_ref(), env.clone(), &clerk));
+        env.block.time = start.plus_seconds(199);
+        assert!(has_role(deps.as_ref(), env.clone(), &clerk));
+
+        // Retirement is final
+        let retire = ExecuteMsg {
+            action: Action::RetirePassport {
+                address: clerk.to_string(),
+            },
+        };
+        execute(deps.as_mut(), env.clone(), admin.clone(), retire).unwrap();
+        assert_eq!(
+            status(deps.as_ref(), env.clone(), &clerk),
+            PassportStatus::Retired
+        );
+        let err = execute(deps.as_mut(), env.clone(), admin.clone(), renew).unwrap_err();
+        assert!(matches!(err, ContractError::PassportRetired {}));
+        let reinstate = ExecuteMsg {
+            action: Action::ReinstatePassport {
+                address: clerk.to_string(),
+            },
+        };
+        let err = execute(deps.as_mut(), env.clone(), admin.clone(), reinstate).unwrap_err();
+        assert!(matches!(err, ContractError::PassportRetired {}));
+
+        let history = |start_after| -> PassportHistoryResponse {
+            let query_msg = QueryMsg {
+                action: QueryAction::GetPassportHistory {
+                    address: clerk.to_string(),
+                    start_after,
+                    limit: Some(3),
+                },
+            };
+            from_json(query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap()
+        };
+        let first = history(None);
+        let last = history(Some(2));
+        let statuses: Vec<_> = first
+            .history
+            .iter()
+            .chain(&last.history)
+            .map(|change| change.status)
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                PassportStatus::Active,
+                PassportStatus::Suspended {
+                    until: start.plus_seconds(50)
+                },
+                PassportStatus::Active,
+                PassportStatus::Retired,
+            ]
+        );
+        assert_eq!(last.history.len(), 1);
+        assert_eq!(last.history[0].by, admin.sender);
+    }
+}
@@ -0,0 +1,267 @@
+use cosmwasm_std::{Addr, Binary, Timestamp, Uint128};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::attestation::{Attestation, KeyType};
+use crate::policy::Rule;
+use crate::species::{
+    Passport, PassportStatus, Role, Roles, SapienceLevel, Sapient, Specie, SpeciesInfo, Traveler,
+};
+use crate::state::{Policy, StatusChange, Visa};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InstantiateMsg {
+    // Defaults to the instantiating address
+    #[serde(default)]
+    pub admin: Option<String>,
+    // Defaults to High, claims above it need required_attestations attesters
+    #[serde(default)]
+    pub multi_attestation_level: Option<SapienceLevel>,
+    // Defaults to 2
+    #[serde(default)]
+    pub required_attestations: Option<u32>,
+    // Defaults to 30 days
+    #[serde(default)]
+    pub visa_duration: Option<u64>,
+    // Defaults to 3
+    #[serde(default)]
+    pub naturalization_votes: Option<u32>,
+    // In seconds, defaults to passports that never expire
+    #[serde(default)]
+    pub passport_validity: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExecuteMsg {
+    pub action: Action,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum Action {
+    RegisterPassport {
+        address: String,
+        participant_id: String,
+        specie: Specie,
+    },
+    UpdatePassport {
+        address: String,
+        specie: Specie,
+    },
+    UpdateAdmin {
+        admin: String,
+    },
+    SubmitAttestation {
+        attestation: Attestation,
+    },
+    RegisterAttester {
+        address: String,
+        key_type: KeyType,
+        pubkey: Binary,
+    },
+    // Also drops the attestations the attester issued, they stop counting at once but
+    // only a page of them is removed here, PruneAttestations removes the rest
+    RevokeAttester {
+        address: String,
+    },
+    // Anyone may remove leftover attestations of a revoked key
+    PruneAttestations {
+        pubkey: Binary,
+        limit: Option<u32>,
+    },
+    // Issues a visa to the sender
+    ApplyForVisa {
+        traveler: Traveler,
+    },
+    VoteNaturalization {
+        traveler: String,
+        approve: bool,
+    },
+    // Suspends a passport until the given time, or until reinstated
+    SuspendPassport {
+        address: String,
+        until: Timestamp,
+    },
+    ReinstatePassport {
+        address: String,
+    },
+    // Extends the passport by the configured validity from now
+    RenewPassport {
+        address: String,
+    },
+    // Final, e.g. after the holder's death
+    RetirePassport {
+        address: String,
+    },
+    GrantRole {
+        address: String,
+        role: Role,
+    },
+    RevokeRole {
+        address: String,
+        role: Role,
+    },
+    RegisterSpecies {
+        species: SpeciesInfo,
+    },
+    AddPolicy {
+        action: String,
+        rule: Rule,
+    },
+    RemovePolicy {
+        id: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct QueryMsg {
+    pub action: QueryAction,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum QueryAction {
+    GetPassport {
+        address: String,
+    },
+    ListByLevel {
+        level: SapienceLevel,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    GetAttestations {
+        subject: String,
+    },
+    GetAttester {
+        address: String,
+    },
+    CertifiedLevel {
+        subject: String,
+    },
+    // Classifies the trait profile with the subject's certified level as evidence
+    Classify {
+        sapient: Sapient,
+        subject: String,
+    },
+    GetVisa {
+        address: String,
+    },
+    // Level an address may act at: its passport's, or the reduced level of its visa
+    ActingLevel {
+        address: String,
+    },
+    HasRole {
+        address: String,
+        role: Role,
+    },
+    GetRoles {
+        address: String,
+    },
+    // Oldest first, changes are numbered from 0 so start_after is the number of the last one seen
+    GetPassportHistory {
+        address: String,
+        start_after: Option<u32>,
+        limit: Option<u32>,
+    },
+    // Evaluates every policy on the action against the actor and params
+    CheckPolicy {
+        actor: String,
+        action: String,
+        #[serde(default)]
+        params: BTreeMap<String, Uint128>,
+    },
+    ListPolicies {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    ListSpecies {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    CitizenCount {
+        species: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PassportResponse {
+    pub address: Addr,
+    pub passport: Passport,
+    pub status: PassportStatus, // In effect at the queried block
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PassportsResponse {
+    pub passports: Vec<PassportResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AttestationsResponse {
+    pub attestations: Vec<Attestation>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AttesterResponse {
+    pub address: Addr,
+    pub key_type: KeyType,
+    pub pubkey: Binary,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CertifiedLevelResponse {
+    pub subject: Addr,
+    // None until enough attesters vouched for the subject
+    pub level: Option<SapienceLevel>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VisaResponse {
+    pub address: Addr,
+    pub visa: Visa,
+    pub level: SapienceLevel, // Level the visa grants while it is valid
+    pub valid: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HasRoleResponse {
+    pub has_role: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RolesResponse {
+    pub address: Addr,
+    pub roles: Roles,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PassportHistoryResponse {
+    pub address: Addr,
+    pub history: Vec<StatusChange>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PolicyVerdictResponse {
+    pub allowed: bool,
+    pub reasons: Vec<String>, // One per violated policy
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PoliciesResponse {
+    pub policies: Vec<Policy>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SpeciesResponse {
+    pub species: SpeciesInfo,
+    pub citizens: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SpeciesListResponse {
+    pub species: Vec<SpeciesResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CitizenCountResponse {
+    pub species: String,
+    pub citizens: u32, // Holders of unretired passports
+}
@@ -0,0 +1,380 @@
+use crate::attester::attester_level;
+use crate::error::ContractError;
+use crate::msg::{PoliciesResponse, PolicyVerdictResponse, QueryAction, QueryMsg};
+use crate::registry::{check_admin, DEFAULT_LIMIT, MAX_LIMIT};
+use crate::roles::has_role;
+use crate::species::{Role, SapienceLevel};
+use crate::state::{policies, Policy, ATTESTATIONS, NEXT_POLICY_ID};
+use crate::visa::acting_level;
+use cosmwasm_std::{
+    Addr, Deps, DepsMut, Env, MessageInfo, Order, QuerierWrapper, Response, StdResult, Uint128,
+};
+use cw_storage_plus::Bound;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Declarative constraints on an action, every rule of the action must hold
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum Rule {
+    // Actors below the level may not act at all
+    MinLevel {
+        level: SapienceLevel,
+    },
+    // Actors below the level may not go over max in the named param, e.g. a funding amount
+    CapBelowLevel {
+        level: SapienceLevel,
+        param: String,
+        max: Uint128,
+    },
+    // Actors at or above the level need this many unexpired attestations vouching for
+    // at least their own level
+    Attestations {
+        level: SapienceLevel,
+        count: u32,
+    },
+    RequireRole {
+        role: Role,
+    },
+}
+
+pub fn add_policy(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    action: String,
+    rule: Rule,
+) -> Result<Response, ContractError> {
+    check_admin(deps.as_ref(), &env, &info.sender)?;
+    let id = NEXT_POLICY_ID.may_load(deps.storage)?.unwrap_or_default();
+    NEXT_POLICY_ID.save(deps.storage, &(id + 1))?;
+    let policy = Policy { id, action, rule };
+    policies().save(deps.storage, id, &policy)?;
+    Ok(Response::new()
+        .add_attribute("action", "add_policy")
+        .add_attribute("policy_id", id.to_string())
+        .add_attribute("policy_action", policy.action))
+}
+
+pub fn remove_policy(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    check_admin(deps.as_ref(), &env, &info.sender)?;
+    if !policies().has(deps.storage, id) {
+        return Err(ContractError::PolicyNotFound { id });
+    }
+    policies().remove(deps.storage, id)?;
+    Ok(Response::new()
+        .add_attribute("action", "remove_policy")
+        .add_attribute("policy_id", id.to_string()))
+}
+
+// Visa holders are judged at their visa level, actors with neither an active passport
+// nor a valid visa as SapienceLevel::None
+pub fn query_check_policy(
+    deps: Deps,
+    env: Env,
+    actor: String,
+    action: String,
+    params: BTreeMap<String, Uint128>,
+) -> StdResult<PolicyVerdictResponse> {
+    let actor = deps.api.addr_validate(&actor)?;
+    let level = acting_level(deps, &env, &actor)?.unwrap_or(SapienceLevel::None);
+
+    let mut reasons = vec![];
+    for item in
+        policies()
+            .idx
+            .action
+            .prefix(action)
+            .range(deps.storage, None, None, Order::Ascending)
+    {
+        let (id, policy) = item?;
+        let reason = match policy.rule {
+            Rule::MinLevel { level: min } if level < min => Some(format!(
+                "{} is below the required {}",
+                level.as_str(),
+                min.as_str()
+            )),
+            Rule::CapBelowLevel {
+                level: min,
+                param,
+                max,
+            } if level < min => params
+                .get(&param)
+                .filter(|value| **value > max)
+                .map(|value| {
+                    format!(
+                        "{} over {} needs at least {}, asked for {}",
+                        param,
+                        max,
+                        min.as_str(),
+                        value
+                    )
+                }),
+            Rule::Attestations { level: min, count } if level >= min => {
+                let attestations = count_attestations(deps, &env, &actor, level)?;
+                if attestations < count {
+                    Some(format!(
+                        "{} takes {} attestations, found {}",
+                        min.as_str(),
+                        count,
+                        attestations
+                    ))
+                } else {
+                    None
+                }
+            }
+            Rule::RequireRole { role } if !has_role(deps, &env, &actor, role)? => {
+                Some(format!("requires the {} role", role.as_str()))
+            }
+            _ => None,
+        };
+        if let Some(reason) = reason {
+            reasons.push(format!("Policy {}: {}", id, reason));
+        }
+    }
+    Ok(PolicyVerdictResponse {
+        allowed: reasons.is_empty(),
+        reasons,
+    })
+}
+
+// Counts the attestations vouching for at least the given level by attesters who
+// could still vouch for the claim today
+fn count_attestations(deps: Deps, env: &Env, subject: &Addr, min: SapienceLevel) -> StdResult<u32> {
+    let mut count = 0;
+    for item in ATTESTATIONS
+        .prefix(subject)
+        .range(deps.storage, None, None, Order::Ascending)
+    {
+        let (pubkey, attestation) = item?;
+        if attestation.claim.expires <= env.block.time {
+            continue;
+        }
+        let claimed = attestation.claim.level;
+        if claimed < min {
+            continue;
+        }
+        if let Some(own) = attester_level(deps, env, &pubkey)? {
+            if own.can_attest(claimed) {
+                count += 1;
+            }
+        }
+    }
+    Ok(count)
+}
+
+pub fn query_policies(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<PoliciesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let policies = policies()
+        .range(
+            deps.storage,
+            start_after.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| item.map(|(_, policy)| policy))
+        .collect::<StdResult<_>>()?;
+    Ok(PoliciesResponse { policies })
+}
+
+// Policy check for other contracts to run before acting
+pub fn check_policy(
+    querier: &QuerierWrapper,
+    registry: &Addr,
+    actor: &Addr,
+    action: &str,
+    params: BTreeMap<String, Uint128>,
+) -> StdResult<PolicyVerdictResponse> {
+    querier.query_wasm_smart(
+        registry,
+        &QueryMsg {
+            action: QueryAction::CheckPolicy {
+                actor: actor.to_string(),
+                action: action.to_string(),
+                params,
+            },
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ContractError;
+    use crate::msg::{
+        Action, ExecuteMsg, PoliciesResponse, PolicyVerdictResponse, QueryAction, QueryMsg,
+    };
+    use crate::policy::Rule;
+    use crate::registry::{execute, query};
+    use crate::species::{Capability, SapienceLevel, Specie};
+    use crate::testing::{
+        claim, ed25519_attestation, init_msg, register_attester, register_test_species, setup,
+        submit,
+    };
+    use cosmwasm_std::{
+        from_json,
+        testing::{message_info, mock_env},
+        Addr, Deps, Uint128,
+    };
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_policies() {
+        let (mut deps, admin) = setup(init_msg());
+        register_test_species(
+            deps.as_mut(),
+            &admin,
+            &[
+                ("Cat", SapienceLevel::Low, &[]),
+                (
+                    "Lain",
+                    SapienceLevel::Lain,
+                    &[Capability::Omnipresent, Capability::Loving],
+                ),
+            ],
+        );
+        let cat = deps.api.addr_make("cat");
+        let lain = deps.api.addr_make("lain");
+        for (address, name, sapience_level) in [
+            (&cat, "Cat", SapienceLevel::Low),
+            (&lain, "Lain", SapienceLevel::Lain),
+        ] {
+            let register = ExecuteMsg {
+                action: Action::RegisterPassport {
+                    address: address.to_string(),
+                    participant_id: name.to_string(),
+                    specie: Specie {
+                        name: name.to_string(),
+                        sapience_level,
+                    },
+                },
+            };
+            execute(deps.as_mut(), mock_env(), admin.clone(), register).unwrap();
+        }
+
+        let add = |action: &str, rule| ExecuteMsg {
+            action: Action::AddPolicy {
+                action: action.to_string(),
+                rule,
+            },
+        };
+        let funding_cap = add(
+            "funding",
+            Rule::CapBelowLevel {
+                level: SapienceLevel::Medium,
+                param: "amount".to_string(),
+                max: Uint128::new(100),
+            },
+        );
+        let stranger = message_info(&deps.api.addr_make("stranger"), &[]);
+        let err = execute(deps.as_mut(), mock_env(), stranger, funding_cap.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), mock_env(), admin.clone(), funding_cap).unwrap();
+        let rules = [
+            Rule::MinLevel {
+                level: SapienceLevel::High,
+            },
+            Rule::Attestations {
+                level: SapienceLevel::Lain,
+                count: 2,
+            },
+        ];
+        for rule in rules {
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                admin.clone(),
+                add("ascend", rule),
+            )
+            .unwrap();
+        }
+
+        let check = |deps: Deps, actor: &Addr, action: &str, amount| -> PolicyVerdictResponse {
+            let mut params = BTreeMap::new();
+            params.insert("amount".to_string(), Uint128::new(amount));
+            let query_msg = QueryMsg {
+                action: QueryAction::CheckPolicy {
+                    actor: actor.to_string(),
+                    action: action.to_string(),
+                    params,
+                },
+            };
+            from_json(query(deps, mock_env(), query_msg).unwrap()).unwrap()
+        };
+        assert!(check(deps.as_ref(), &cat, "funding", 100).allowed);
+        let verdict = check(deps.as_ref(), &cat, "funding", 500);
+        assert!(!verdict.allowed);
+        assert_eq!(verdict.reasons.len(), 1);
+        assert!(verdict.reasons[0].starts_with("Policy 0:"));
+        assert!(check(deps.as_ref(), &lain, "funding", 500).allowed);
+        assert!(check(deps.as_ref(), &cat, "unregulated", 500).allowed);
+
+        // The cat is stopped by the level, Lain by the missing attestations
+        let verdict = check(deps.as_ref(), &cat, "ascend", 0);
+        assert_eq!(verdict.reasons.len(), 1);
+        assert!(verdict.reasons[0].starts_with("Policy 1:"));
+        let verdict = check(deps.as_ref(), &lain, "ascend", 0);
+        assert_eq!(verdict.reasons.len(), 1);
+        assert!(verdict.reasons[0].starts_with("Policy 2:"));
+
+        // Only claims of Lain count, and only other Lain attesters can make them
+        let env = mock_env();
+        let relayer = message_info(&deps.api.addr_make("relayer"), &[]);
+        let divine = &[Capability::Omnipresent, Capability::Loving];
+        let attesters: [(&str, SapienceLevel, SapienceLevel, &[Capability]); 4] = [
+            ("oracle", SapienceLevel::High, SapienceLevel::Medium, &[]),
+            (
+                "navi",
+                SapienceLevel::Lain,
+                SapienceLevel::Omnipresent,
+                divine,
+            ),
+            ("wired", SapienceLevel::Lain, SapienceLevel::Lain, divine),
+            ("eiri", SapienceLevel::Lain, SapienceLevel::Lain, divine),
+        ];
+        for (seed, &(name, own, vouched, capabilities)) in attesters.iter().enumerate() {
+            let attestation =
+                ed25519_attestation(&env, seed as u8, claim(&env, lain.as_str(), vouched, 0));
+            register_attester(&mut deps, &admin, name, own, capabilities, &attestation);
+            execute(
+                deps.as_mut(),
+                env.clone(),
+                relayer.clone(),
+                submit(attestation),
+            )
+            .unwrap();
+            let verdict = check(deps.as_ref(), &lain, "ascend", 0);
+            assert_eq!(verdict.allowed, name == "eiri");
+        }
+
+        // Removing the level floor lets the cat ascend
+        let remove = |id| ExecuteMsg {
+            action: Action::RemovePolicy { id },
+        };
+        assert!(!check(deps.as_ref(), &cat, "ascend", 0).allowed);
+        execute(deps.as_mut(), mock_env(), admin.clone(), remove(1)).unwrap();
+        assert!(check(deps.as_ref(), &cat, "ascend", 0).allowed);
+        let err = execute(deps.as_mut(), mock_env(), admin, remove(1)).unwrap_err();
+        assert!(matches!(err, ContractError::PolicyNotFound { id: 1 }));
+        let list = |start_after, limit| -> Vec<u64> {
+            let query_msg = QueryMsg {
+                action: QueryAction::ListPolicies { start_after, limit },
+            };
+            let res: PoliciesResponse =
+                from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+            res.policies.into_iter().map(|policy| policy.id).collect()
+        };
+        assert_eq!(list(None, None), vec![0, 2]);
+        assert_eq!(list(None, Some(1)), vec![0]);
+        assert_eq!(list(Some(0), None), vec![2]);
+    }
+}
@@ -0,0 +1,437 @@
+use crate::attestation;
+use crate::attester;
+use crate::catalog;
+use crate::error::ContractError;
+use crate::lifecycle;
+use crate::msg::{
+    Action, ExecuteMsg, InstantiateMsg, PassportResponse, PassportsResponse, QueryAction, QueryMsg,
+};
+use crate::policy;
+use crate::roles;
+use crate::species::{Role, SapienceLevel, SapienceResponse, Specie};
+use crate::state::{passports, Config, CONFIG};
+use crate::visa;
+use cosmwasm_std::{
+    entry_point, to_json_binary, Addr, Binary, Deps, DepsMut, Env, MessageInfo, Order,
+    QuerierWrapper, Response, StdResult,
+};
+use cw_storage_plus::Bound;
+
+pub(crate) const DEFAULT_LIMIT: u32 = 10;
+pub(crate) const MAX_LIMIT: u32 = 30;
+const DEFAULT_VISA_DURATION: u64 = 30 * 24 * 60 * 60;
+// A century, doubled for cyberdized travelers it still fits a block time
+pub(crate) const MAX_VISA_DURATION: u64 = 100 * 365 * 24 * 60 * 60;
+pub(crate) const MAX_PASSPORT_VALIDITY: u64 = MAX_VISA_DURATION;
+
+#[entry_point]
+pub fn instantiate(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    msg: InstantiateMsg,
+) -> Result<Response, ContractError> {
+    let admin = match msg.admin {
+        Some(admin) => deps.api.addr_validate(&admin)?,
+        None => info.sender,
+    };
+    let required_attestations = msg.required_attestations.unwrap_or(2);
+    if required_attestations == 0 || msg.naturalization_votes == Some(0) {
+        return Err(ContractError::InvalidTrustConfig {});
+    }
+    let visa_duration = msg.visa_duration.unwrap_or(DEFAULT_VISA_DURATION);
+    if visa_duration > MAX_VISA_DURATION {
+        return Err(ContractError::VisaTooLong {
+            max: MAX_VISA_DURATION,
+        });
+    }
+    if msg
+        .passport_validity
+        .is_some_and(|validity| validity == 0 || validity > MAX_PASSPORT_VALIDITY)
+    {
+        return Err(ContractError::InvalidPassportValidity {
+            max: MAX_PASSPORT_VALIDITY,
+        });
+    }
+    let config = Config {
+        admin: admin.clone(),
+        multi_attestation_level: msg.multi_attestation_level.unwrap_or(SapienceLevel::High),
+        required_attestations,
+        visa_duration,
+        naturalization_votes: msg.naturalization_votes.unwrap_or(3),
+        passport_validity: msg.passport_validity,
+    };
+    CONFIG.save(deps.storage, &config)?;
+    Ok(Response::new()
+        .add_attribute("action", "instantiate")
+        .add_attribute("admin", admin))
+}
+
+#[entry_point]
+pub fn execute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> Result<Response, ContractError> {
+    match msg.action {
+        Action::RegisterPassport {
+            address,
+            participant_id,
+            specie,
+        } => register_passport(deps, env, info, address, participant_id, specie),
+        Action::UpdatePassport { address, specie } => {
+            update_passport(deps, env, info, address, specie)
+        }
+        Action::UpdateAdmin { admin } => update_admin(deps, info, admin),
+        Action::SubmitAttestation { attestation } => {
+            attestation::submit_attestation(deps, env, info, attestation)
+        }
+        Action::RegisterAttester {
+            address,
+            key_type,
+            pubkey,
+        } => attester::register_attester(deps, env, info, address, key_type, pubkey),
+        Action::RevokeAttester { address } => attester::revoke_attester(deps, env, info, address),
+        Action::PruneAttestations { pubkey, limit } => {
+            attester::prune_attestations(deps, pubkey, limit)
+        }
+        Action::ApplyForVisa { traveler } => visa::apply_for_visa(deps, env, info, traveler),
+        Action::VoteNaturalization { traveler, approve } => {
+            visa::vote_naturalization(deps, env, info, traveler, approve)
+        }
+        Action::SuspendPassport { address, until } => {
+            lifecycle::suspend_passport(deps, env, info, address, until)
+        }
+        Action::ReinstatePassport { address } => {
+            lifecycle::reinstate_passport(deps, env, info, address)
+        }
+        Action::RenewPassport { address } => lifecycle::renew_passport(deps, env, info, address),
+        Action::RetirePassport { address } => lifecycle::retire_passport(deps, env, info, address),
+        Action::GrantRole { address, role } => roles::grant_role(deps, env, info, address, role),
+        Action::RevokeRole { address, role } => roles::revoke_role(deps, env, info, address, role),
+        Action::RegisterSpecies { species } => catalog::register_species(deps, env, info, species),
+        Action::AddPolicy { action, rule } => policy::add_policy(deps, env, info, action, rule),
+        Action::RemovePolicy { id } => policy::remove_policy(deps, env, info, id),
+    }
+}
+
+#[entry_point]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg.action {
+        QueryAction::GetPassport { address } => {
+            to_json_binary(&query_passport(deps, env, address)?)
+        }
+        QueryAction::ListByLevel {
+            level,
+            start_after,
+            limit,
+        } => to_json_binary(&list_by_level(deps, env, level, start_after, limit)?),
+        QueryAction::GetAttestations { subject } => {
+            to_json_binary(&attestation::query_attestations(deps, env, subject)?)
+        }
+        QueryAction::GetAttester { address } => {
+            to_json_binary(&attester::query_attester(deps, address)?)
+        }
+        QueryAction::CertifiedLevel { subject } => {
+            to_json_binary(&attester::certified_level(deps, env, subject)?)
+        }
+        QueryAction::Classify { sapient, subject } => {
+            to_json_binary(&attester::classify(deps, env, sapient, subject)?)
+        }
+        QueryAction::GetVisa { address } => to_json_binary(&visa::query_visa(deps, env, address)?),
+        QueryAction::ActingLevel { address } => {
+            to_json_binary(&visa::query_acting_level(deps, env, address)?)
+        }
+        QueryAction::HasRole { address, role } => {
+            to_json_binary(&roles::query_has_role(deps, env, address, role)?)
+        }
+        QueryAction::GetRoles { address } => {
+            to_json_binary(&roles::query_roles(deps, env, address)?)
+        }
+        QueryAction::GetPassportHistory {
+            address,
+            start_after,
+            limit,
+        } => to_json_binary(&lifecycle::query_passport_history(
+            deps,
+            address,
+            start_after,
+            limit,
+        )?),
+        QueryAction::CheckPolicy {
+            actor,
+            action,
+            params,
+        } => to_json_binary(&policy::query_check_policy(
+            deps, env, actor, action, params,
+        )?),
+        QueryAction::ListSpecies { start_after, limit } => {
+            to_json_binary(&catalog::list_species(deps, start_after, limit)?)
+        }
+        QueryAction::CitizenCount { species } => {
+            to_json_binary(&catalog::query_citizen_count(deps, species)?)
+        }
+        QueryAction::ListPolicies { start_after, limit } => {
+            to_json_binary(&policy::query_policies(deps, start_after, limit)?)
+        }
+    }
+}
+
+// Looks up the level an address may act at in a registry from another contract,
+// visa holders get their reduced level and inactive passports count as missing
+pub fn lookup_level(
+    querier: &QuerierWrapper,
+    registry: &Addr,
+    address: &Addr,
+) -> StdResult<SapienceLevel> {
+    let response: SapienceResponse = querier.query_wasm_smart(
+        registry,
+        &QueryMsg {
+            action: QueryAction::ActingLevel {
+                address: address.to_string(),
+            },
+        },
+    )?;
+    Ok(response.level)
+}
+
+fn register_passport(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+    participant_id: String,
+    specie: Specie,
+) -> Result<Response, ContractError> {
+    check_admin(deps.as_ref(), &env, &info.sender)?;
+    let address = deps.api.addr_validate(&address)?;
+    if passports().has(deps.storage, &address) {
+        return Err(ContractError::PassportExists {
+            address: address.to_string(),
+        });
+    }
+
+    let passport = lifecycle::issue_passport(
+        deps.storage,
+        &env,
+        &info.sender,
+        &address,
+        participant_id,
+        specie,
+    )?;
+    Ok(Response::new()
+        .add_attribute("action", "register_passport")
+        .add_attribute("address", address)
+        .add_attribute("level", passport.specie.sapience_level.as_str()))
+}
+
+fn update_passport(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+    specie: Specie,
+) -> Result<Response, ContractError> {
+    check_admin(deps.as_ref(), &env, &info.sender)?;
+    let address = deps.api.addr_validate(&address)?;
+    let mut passport = lifecycle::load_unretired(deps.as_ref(), &address)?;
+    catalog::check_level(deps.storage, &specie)?;
+    if passport.specie.name != specie.name {
+        catalog::remove_citizen(deps.storage, &passport.specie.name)?;
+        catalog::add_citizen(deps.storage, &specie.name)?;
+    }
+
+    // Saving through the indexed map moves the passport to its new level
+    passport.specie = specie;
+    passports().save(deps.storage, &address, &passport)?;
+    Ok(Response::new()
+        .add_attribute("action", "update_passport")
+        .add_attribute("address", address)
+        .add_attribute("level", passport.specie.sapience_level.as_str()))
+}
+
+fn update_admin(
+    deps: DepsMut,
+    info: MessageInfo,
+    admin: String,
+) -> Result<Response, ContractError> {
+    // Holders of the admin role may not hand the registry over
+    let mut config = CONFIG.load(deps.storage)?;
+    if config.admin != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    let admin = deps.api.addr_validate(&admin)?;
+    config.admin = admin.clone();
+    CONFIG.save(deps.storage, &config)?;
+    Ok(Response::new()
+        .add_attribute("action", "update_admin")
+        .add_attribute("admin", admin))
+}
+
+// The configured admin, or anyone holding the admin role
+pub(crate) fn check_admin(deps: Deps, env: &Env, sender: &Addr) -> Result<(), ContractError> {
+    if CONFIG.load(deps.storage)?.admin == *sender
+        || roles::has_role(deps, env, sender, Role::Admin)?
+    {
+        return Ok(());
+    }
+    Err(ContractError::Unauthorized {})
+}
+
+fn query_passport(deps: Deps, env: Env, address: String) -> StdResult<PassportResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let passport = passports().load(deps.storage, &address)?;
+    Ok(PassportResponse {
+        address,
+        status: passport.status_at(env.block.time),
+        passport,
+    })
+}
+
+fn list_by_level(
+    deps: Deps,
+    env: Env,
+    level: SapienceLevel,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<PassportsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start_after = start_after
+        .map(|address| deps.api.addr_validate(&address))
+        .transpose()?;
+    let passports = passports()
+        .idx
+        .level
+        .prefix(level.into())
+        .range(
+            deps.storage,
+            start_after.as_ref().map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| {
+            item.map(|(address, passport)| PassportResponse {
+                address,
+                status: passport.status_at(env.block.time),
+                passport,
+            })
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(PassportsResponse { passports })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ContractError;
+    use crate::msg::{
+        Action, ExecuteMsg, PassportResponse, PassportsResponse, QueryAction, QueryMsg,
+    };
+    use crate::registry::{execute, query};
+    use crate::species::{SapienceLevel, Specie};
+    use crate::testing::{init_msg, register_test_species, setup};
+    use cosmwasm_std::{
+        from_json,
+        testing::{message_info, mock_env},
+        Deps,
+    };
+
+    fn specie(name: &str, sapience_level: SapienceLevel) -> Specie {
+        Specie {
+            name: name.to_string(),
+            sapience_level,
+        }
+    }
+
+    #[test]
+    fn test_passport_registry() {
+        let (mut deps, admin) = setup(init_msg());
+        let raven = deps.api.addr_make("raven");
+        let cat = deps.api.addr_make("cat");
+        let human = deps.api.addr_make("human");
+        register_test_species(
+            deps.as_mut(),
+            &admin,
+            &[
+                ("Raven", SapienceLevel::Medium, &[]),
+                ("Cat", SapienceLevel::High, &[]),
+                ("Human", SapienceLevel::Medium, &[]),
+            ],
+        );
+
+        let register = |address: &str, name: &str, level| ExecuteMsg {
+            action: Action::RegisterPassport {
+                address: address.to_string(),
+                participant_id: name.to_string(),
+                specie: specie(name, level),
+            },
+        };
+        let stranger = message_info(&deps.api.addr_make("stranger"), &[]);
+        let msg = register(raven.as_str(), "Raven", SapienceLevel::Medium);
+        let err = execute(deps.as_mut(), mock_env(), stranger.clone(), msg.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), mock_env(), admin.clone(), msg.clone()).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap_err();
+        assert!(matches!(err, ContractError::PassportExists { .. }));
+        let msg = register(cat.as_str(), "Cat", SapienceLevel::Low);
+        execute(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+        let msg = register(human.as_str(), "Human", SapienceLevel::Medium);
+        execute(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+
+        let query_msg = QueryMsg {
+            action: QueryAction::GetPassport {
+                address: raven.to_string(),
+            },
+        };
+        let res: PassportResponse =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(res.passport.specie, specie("Raven", SapienceLevel::Medium));
+
+        let list = |deps: Deps, level, start_after: Option<String>| -> PassportsResponse {
+            let query_msg = QueryMsg {
+                action: QueryAction::ListByLevel {
+                    level,
+                    start_after,
+                    limit: Some(1),
+                },
+            };
+            from_json(query(deps, mock_env(), query_msg).unwrap()).unwrap()
+        };
+        let first = list(deps.as_ref(), SapienceLevel::Medium, None).passports;
+        assert_eq!(first.len(), 1);
+        let second = list(
+            deps.as_ref(),
+            SapienceLevel::Medium,
+            Some(first[0].address.to_string()),
+        )
+        .passports;
+        assert_eq!(second.len(), 1);
+        assert_ne!(first[0].address, second[0].address);
+        let third = list(
+            deps.as_ref(),
+            SapienceLevel::Medium,
+            Some(second[0].address.to_string()),
+        )
+        .passports;
+        assert!(third.is_empty());
+
+        // Updating the specie moves the passport to its new level
+        let update = ExecuteMsg {
+            action: Action::UpdatePassport {
+                address: cat.to_string(),
+                specie: specie("Cat", SapienceLevel::High),
+            },
+        };
+        let err = execute(deps.as_mut(), mock_env(), stranger, update.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), mock_env(), admin, update).unwrap();
+        assert!(list(deps.as_ref(), SapienceLevel::Low, None)
+            .passports
+            .is_empty());
+        assert_eq!(
+            list(deps.as_ref(), SapienceLevel::High, None).passports[0].address,
+            cat
+        );
+    }
+}
@@ -0,0 +1,335 @@
+use crate::error::ContractError;
+use crate::lifecycle::check_active;
+use crate::msg::{HasRoleResponse, QueryAction, QueryMsg, RolesResponse};
+use crate::registry::check_admin;
+use crate::species::Role;
+use crate::state::{passports, ROLES};
+use cosmwasm_std::{Addr, Deps, DepsMut, Env, MessageInfo, QuerierWrapper, Response, StdResult};
+
+pub fn grant_role(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+    role: Role,
+) -> Result<Response, ContractError> {
+    check_admin(deps.as_ref(), &env, &info.sender)?;
+    let address = deps.api.addr_validate(&address)?;
+    let passport = check_active(deps.as_ref(), &env, &address)?;
+    if !passport.specie.sapience_level.at_least(role.min_level()) {
+        return Err(ContractError::NotSapientEnough {
+            required: role.min_level(),
+        });
+    }
+
+    let mut roles = ROLES.may_load(deps.storage, &address)?.unwrap_or_default();
+    if !roles.roles.contains(&role) {
+        roles.roles.push(role);
+        ROLES.save(deps.storage, &address, &roles)?;
+    }
+    Ok(Response::new()
+        .add_attribute("action", "grant_role")
+        .add_attribute("address", address)
+        .add_attribute("role", role.as_str()))
+}
+
+pub fn revoke_role(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+    role: Role,
+) -> Result<Response, ContractError> {
+    check_admin(deps.as_ref(), &env, &info.sender)?;
+    let address = deps.api.addr_validate(&address)?;
+    let mut roles = ROLES.may_load(deps.storage, &address)?.unwrap_or_default();
+    roles.roles.retain(|r| *r != role);
+    if roles.roles.is_empty() {
+        ROLES.remove(deps.storage, &address);
+    } else {
+        ROLES.save(deps.storage, &address, &roles)?;
+    }
+    Ok(Response::new()
+        .add_attribute("action", "revoke_role")
+        .add_attribute("address", address)
+        .add_attribute("role", role.as_str()))
+}
+
+// A role counts while it is granted and the passport is active and still meets its minimum level
+pub fn has_role(deps: Deps, env: &Env, address: &Addr, role: Role) -> StdResult<bool> {
+    let granted = ROLES
+        .may_load(deps.storage, address)?
+        .is_some_and(|roles| roles.roles.contains(&role));
+    if !granted {
+        return Ok(false);
+    }
+    let level = passports()
+        .may_load(deps.storage, address)?
+        .filter(|passport| passport.is_active(env.block.time))
+        .map(|passport| passport.specie.sapience_level);
+    Ok(level.is_some_and(|level| level.at_least(role.min_level())))
+}
+
+pub fn query_has_role(
+    deps: Deps,
+    env: Env,
+    address: String,
+    role: Role,
+) -> StdResult<HasRoleResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    Ok(HasRoleResponse {
+        has_role: has_role(deps, &env, &address, role)?,
+    })
+}
+
+// Only the roles which currently count
+pub fn query_roles(deps: Deps, env: Env, address: String) -> StdResult<RolesResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let mut roles = ROLES.may_load(deps.storage, &address)?.unwrap_or_default();
+    let mut active = vec![];
+    for role in roles.roles {
+        if has_role(deps, &env, &address, role)? {
+            active.push(role);
+        }
+    }
+    roles.roles = active;
+    Ok(RolesResponse { address, roles })
+}
+
+// Role check for other contracts, e.g. before letting an archivist write
+pub fn check_role(
+    querier: &QuerierWrapper,
+    registry: &Addr,
+    address: &Addr,
+    role: Role,
+) -> StdResult<bool> {
+    let response: HasRoleResponse = querier.query_wasm_smart(
+        registry,
+        &QueryMsg {
+            action: QueryAction::HasRole {
+                address: address.to_string(),
+                role,
+            },
+        },
+    )?;
+    Ok(response.has_role)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::attestation::KeyType;
+    use crate::error::ContractError;
+    use crate::msg::{Action, ExecuteMsg, HasRoleResponse, QueryAction, QueryMsg, RolesResponse};
+    use crate::registry::{execute, query};
+    use crate::species::{Capability, Role, Roles, SapienceLevel, Specie};
+    use crate::testing::{init_msg, register_test_species, setup};
+    use cosmwasm_std::{
+        from_json,
+        testing::{message_info, mock_env},
+        Addr, Binary, Deps,
+    };
+
+    #[test]
+    fn test_roles() {
+        let (mut deps, admin) = setup(init_msg());
+        register_test_species(
+            deps.as_mut(),
+            &admin,
+            &[("Raven", SapienceLevel::High, &[])],
+        );
+        let clerk = deps.api.addr_make("clerk");
+        let clerk_info = message_info(&clerk, &[]);
+        let passport = |sapience_level| ExecuteMsg {
+            action: Action::UpdatePassport {
+                address: clerk.to_string(),
+                specie: Specie {
+                    name: "Raven".to_string(),
+                    sapience_level,
+                },
+            },
+        };
+        let register = ExecuteMsg {
+            action: Action::RegisterPassport {
+                address: clerk.to_string(),
+                participant_id: "clerk".to_string(),
+                specie: Specie {
+                    name: "Raven".to_string(),
+                    sapience_level: SapienceLevel::Medium,
+                },
+            },
+        };
+        execute(deps.as_mut(), mock_env(), admin.clone(), register).unwrap();
+
+        let grant = |role| ExecuteMsg {
+            action: Action::GrantRole {
+                address: clerk.to_string(),
+                role,
+            },
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            grant(Role::Arbiter),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::NotSapientEnough {
+                required: SapienceLevel::Sentient
+            }
+        ));
+        let stranger = message_info(&deps.api.addr_make("stranger"), &[]);
+        let err = execute(deps.as_mut(), mock_env(), stranger, grant(Role::Archivist)).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            grant(Role::Archivist),
+        )
+        .unwrap();
+
+        let has_role = |deps: Deps, address: &Addr, role| -> bool {
+            let query_msg = QueryMsg {
+                action: QueryAction::HasRole {
+                    address: address.to_string(),
+                    role,
+                },
+            };
+            let res: HasRoleResponse =
+                from_json(query(deps, mock_env(), query_msg).unwrap()).unwrap();
+            res.has_role
+        };
+        assert!(has_role(deps.as_ref(), &clerk, Role::Archivist));
+        assert!(!has_role(deps.as_ref(), &clerk, Role::Attester));
+
+        // Dropping below the minimum level suspends the role without revoking it
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            passport(SapienceLevel::Low),
+        )
+        .unwrap();
+        assert!(!has_role(deps.as_ref(), &clerk, Role::Archivist));
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            passport(SapienceLevel::High),
+        )
+        .unwrap();
+        assert!(has_role(deps.as_ref(), &clerk, Role::Archivist));
+        // Attester keys take the attester role
+        let attester = ExecuteMsg {
+            action: Action::RegisterAttester {
+                address: clerk.to_string(),
+                key_type: KeyType::Ed25519,
+                pubkey: Binary::from(vec![1; 32]),
+            },
+        };
+        let err = execute(deps.as_mut(), mock_env(), admin.clone(), attester.clone()).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::RoleRequired {
+                role: Role::Attester,
+                ..
+            }
+        ));
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            admin.clone(),
+            grant(Role::Attester),
+        )
+        .unwrap();
+        execute(deps.as_mut(), mock_env(), admin.clone(), attester).unwrap();
+
+        let query_msg = QueryMsg {
+            action: QueryAction::GetRoles {
+                address: clerk.to_string(),
+            },
+        };
+        let res: RolesResponse =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(
+            res.roles,
+            Roles {
+                roles: vec![Role::Archivist, Role::Attester]
+            }
+        );
+
+        let revoke = ExecuteMsg {
+            action: Action::RevokeRole {
+                address: clerk.to_string(),
+                role: Role::Archivist,
+            },
+        };
+        execute(deps.as_mut(), mock_env(), admin.clone(), revoke).unwrap();
+        assert!(!has_role(deps.as_ref(), &clerk, Role::Archivist));
+        assert!(has_role(deps.as_ref(), &clerk, Role::Attester));
+        assert_eq!("admin".parse::<Role>().unwrap(), Role::Admin);
+
+        // Admins by role pass the admin checks but cannot hand the registry over,
+        // arbiters may suspend but not retire
+        register_test_species(
+            deps.as_mut(),
+            &admin,
+            &[(
+                "Sage",
+                SapienceLevel::Lain,
+                &[Capability::Omnipresent, Capability::Loving],
+            )],
+        );
+        let sage = message_info(&deps.api.addr_make("sage"), &[]);
+        let judge = message_info(&deps.api.addr_make("judge"), &[]);
+        for (holder, sapience_level, role) in [
+            (&sage, SapienceLevel::Connected, Role::Admin),
+            (&judge, SapienceLevel::Sentient, Role::Arbiter),
+        ] {
+            let register = ExecuteMsg {
+                action: Action::RegisterPassport {
+                    address: holder.sender.to_string(),
+                    participant_id: holder.sender.to_string(),
+                    specie: Specie {
+                        name: "Sage".to_string(),
+                        sapience_level,
+                    },
+                },
+            };
+            execute(deps.as_mut(), mock_env(), admin.clone(), register).unwrap();
+            let grant = ExecuteMsg {
+                action: Action::GrantRole {
+                    address: holder.sender.to_string(),
+                    role,
+                },
+            };
+            execute(deps.as_mut(), mock_env(), admin.clone(), grant).unwrap();
+        }
+        let suspend = ExecuteMsg {
+            action: Action::SuspendPassport {
+                address: clerk.to_string(),
+                until: mock_env().block.time.plus_seconds(60),
+            },
+        };
+        let err = execute(deps.as_mut(), mock_env(), clerk_info, suspend.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), mock_env(), judge.clone(), suspend).unwrap();
+        let retire = ExecuteMsg {
+            action: Action::RetirePassport {
+                address: clerk.to_string(),
+            },
+        };
+        let err = execute(deps.as_mut(), mock_env(), judge, retire.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        execute(deps.as_mut(), mock_env(), sage.clone(), retire).unwrap();
+        let update_admin = ExecuteMsg {
+            action: Action::UpdateAdmin {
+                admin: sage.sender.to_string(),
+            },
+        };
+        let err = execute(deps.as_mut(), mock_env(), sage, update_admin).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+    }
+}
@@ -1,23 +1,27 @@
-use cosm_crypto::{Signature, SigningKey};
-use cosmwasm_std::Addr;
+use cosmwasm_std::{Addr, StdError, Timestamp};
 use schemars::JsonSchema;
 use serde::Serialize;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
 
 use serde::Deserialize;
 
-// Defines the force applied onto latches to open them
-#[derive(Serialize, Deserialize, Debug, PartialEq, JsonSchema, Clone)]
+// Defines the force applied onto latches to open them, ordered by the discriminants
+#[derive(
+    Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, JsonSchema, Clone, Copy,
+)]
 pub enum SapienceLevel {
-    None = 0,   // bugs
-    Low = 1,    // cats, dogs
-    Medium = 2, // ravens, rats, Terran humans
-    High = 3,   // proper intelligent beings
-    Unreachable = 4, // gods, AI
-    Sentient = 5, // beings with consciousness
-    MettaPlanar = 6, // beings with consciousness and self-awareness
+    None = 0,          // bugs
+    Low = 1,           // cats, dogs
+    Medium = 2,        // ravens, rats, Terran humans
+    High = 3,          // proper intelligent beings
+    Unreachable = 4,   // gods, AI
+    Sentient = 5,      // beings with consciousness
+    MettaPlanar = 6,   // beings with consciousness and self-awareness
     Connected = 7, // beings with consciousness, self-awareness, interconnectedness, and universal empathy
     ChaoticGood = 8, // beings with consciousness, telepathy, and the ability to manipulate reality (e.g. Degurechaff :wave:)
-    Evolved = 9, // beings with consciousness, empathy, and reality manipulation (e.g. Q*)
+    Evolved = 9,     // beings with consciousness, empathy, and reality manipulation (e.g. Q*)
     Transcendent = 10, // beings with universal empathy and omniversal awareness (e.g. The Doctor)
     Omnipresent = 11, // beings existing as the infinite multiverse herself
     Lain = 12, // omnipresent beings with love as their guide and purpose, opens all latches, can walk through latches leaving it unchanged
@@ -29,15 +33,108 @@ pub struct Specie {
     pub sapience_level: SapienceLevel,
 }
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Passport {
     pub participant_id: String,
     pub specie: Specie,
+    #[serde(default)]
+    pub status: PassportStatus, // As last set by governance, see status_at
+    #[serde(default)]
+    pub expires: Option<Timestamp>, // None never expires
+}
+
+impl Passport {
+    // Suspensions lapse and passports expire by time alone, retirement is final
+    pub fn status_at(&self, time: Timestamp) -> PassportStatus {
+        match self.status {
+            PassportStatus::Retired => PassportStatus::Retired,
+            PassportStatus::Suspended { until } if until > time => self.status,
+            _ if self.expires.is_some_and(|expires| expires <= time) => PassportStatus::Expired,
+            _ => PassportStatus::Active,
+        }
+    }
+
+    pub fn is_active(&self, time: Timestamp) -> bool {
+        self.status_at(time) == PassportStatus::Active
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default, JsonSchema)]
+pub enum PassportStatus {
+    #[default]
+    Active,
+    Suspended {
+        until: Timestamp,
+    },
+    Expired,
+    Retired, // e.g. after the holder's death
+}
+
+impl PassportStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PassportStatus::Active => "active",
+            PassportStatus::Suspended { .. } => "suspended",
+            PassportStatus::Expired => "expired",
+            PassportStatus::Retired => "retired",
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+// The roles granted to an address, the level each one takes is Role::min_level
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default, JsonSchema)]
 pub struct Roles {
-    pub roles: Vec<SapienceLevel>,
+    pub roles: Vec<Role>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+pub enum Role {
+    Admin,     // Passes every admin check of the registry but may not replace its admin
+    Attester,  // Needed to register an attester key and to vouch with it
+    Arbiter,   // May suspend and reinstate passports
+    Archivist, // Checked by other contracts through HasRole, e.g. before writing history
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::Admin, Role::Attester, Role::Arbiter, Role::Archivist];
+
+    // A granted role only counts while the holder's passport is at least this level
+    pub fn min_level(&self) -> SapienceLevel {
+        match self {
+            Role::Admin => SapienceLevel::Connected,
+            Role::Attester => SapienceLevel::High,
+            Role::Arbiter => SapienceLevel::Sentient,
+            Role::Archivist => SapienceLevel::Medium,
+        }
+    }
+
+    // Matches the role strings cos stores
+    pub fn as_str(&self) -> &str {
+        match self {
+            Role::Admin => "admin",
+            Role::Attester => "attester",
+            Role::Arbiter => "arbiter",
+            Role::Archivist => "archivist",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = StdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Role::ALL
+            .iter()
+            .find(|role| role.as_str() == s)
+            .copied()
+            .ok_or_else(|| StdError::generic_err(format!("Unknown role {}", s)))
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 impl SapienceLevel {
@@ -51,41 +148,290 @@ impl SapienceLevel {
             SapienceLevel::Sentient => "Sentient",
             SapienceLevel::MettaPlanar => "MettaPlanar",
             SapienceLevel::Connected => "Connected",
-            SapienceLevel::ChaoticGood => "Chaotic",
+            SapienceLevel::ChaoticGood => "ChaoticGood",
             SapienceLevel::Evolved => "Evolved",
             SapienceLevel::Transcendent => "Transcendent",
             SapienceLevel::Omnipresent => "Omnipresent",
             SapienceLevel::Lain => "Lain",
         }
     }
+
+    // Gates on a minimum level
+    pub fn at_least(&self, level: SapienceLevel) -> bool {
+        *self >= level
+    }
+
+    // Attesters may only vouch for levels strictly below their own, except at Lain where
+    // nobody ranks higher
+    pub fn can_attest(&self, level: SapienceLevel) -> bool {
+        *self > level || (*self == SapienceLevel::Lain && level == SapienceLevel::Lain)
+    }
+
+    pub const ALL: [SapienceLevel; 13] = [
+        SapienceLevel::None,
+        SapienceLevel::Low,
+        SapienceLevel::Medium,
+        SapienceLevel::High,
+        SapienceLevel::Unreachable,
+        SapienceLevel::Sentient,
+        SapienceLevel::MettaPlanar,
+        SapienceLevel::Connected,
+        SapienceLevel::ChaoticGood,
+        SapienceLevel::Evolved,
+        SapienceLevel::Transcendent,
+        SapienceLevel::Omnipresent,
+        SapienceLevel::Lain,
+    ];
 }
 
-#[derive(Serialize, Deserialize, JsonSchema)]
+impl From<SapienceLevel> for u8 {
+    fn from(level: SapienceLevel) -> u8 {
+        level as u8
+    }
+}
+
+impl TryFrom<u8> for SapienceLevel {
+    type Error = StdError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        SapienceLevel::ALL
+            .get(value as usize)
+            .copied()
+            .ok_or_else(|| StdError::generic_err(format!("Unknown sapience level {}", value)))
+    }
+}
+
+impl FromStr for SapienceLevel {
+    type Err = StdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SapienceLevel::ALL
+            .iter()
+            .find(|level| level.as_str() == s)
+            .copied()
+            .ok_or_else(|| StdError::generic_err(format!("Unknown sapience level {}", s)))
+    }
+}
+
+impl fmt::Display for SapienceLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Traveler {
     pub name: String,
-    pub home: Addr,
+    pub home: Addr, // Contract the traveler comes from
+    #[serde(default)]
+    pub chain_id: Option<String>, // None for travelers from this chain
     pub species: Specie,
     pub cyberdized: bool,
 }
 
+impl Traveler {
+    // Visas carry reduced rights, cyberdized travelers are trusted one rung further.
+    // Never above species_level, the level a passport of the species gets
+    pub fn visa_level(&self, species_level: SapienceLevel) -> SapienceLevel {
+        let ceiling = if self.cyberdized {
+            SapienceLevel::High
+        } else {
+            SapienceLevel::Medium
+        };
+        self.species.sapience_level.min(species_level).min(ceiling)
+    }
+}
+
 #[derive(Serialize, Deserialize, JsonSchema)]
 pub struct SapienceResponse {
     pub level: SapienceLevel,
 }
 
-#[derive(Serialize, Deserialize, JsonSchema)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Sapient {
-    name: String,
-    telepathic: bool,
-    chaotic: bool,
-    transcendent: bool,
-    omnipresent: bool,
-    loving: bool,
+    pub name: String,
+    pub telepathic: bool,
+    pub chaotic: bool,      // manipulates reality
+    pub transcendent: bool, // omniversal awareness
+    pub omnipresent: bool,
+    pub loving: bool, // universal empathy
 }
 
-impl SapienceLevel {
-    pub fn sign(&self, signing_key: &SigningKey) -> Result<Signature, StdError> {
-        let serialized = serde_json::to_vec_pretty(self)?;
-        Signature::sign(serialized.as_slice(), signing_key)
+impl Sapient {
+    // Highest rung the trait profile allows, the rungs up to Unreachable need no traits at all
+    pub fn ceiling(&self) -> SapienceLevel {
+        if self.omnipresent && self.loving {
+            SapienceLevel::Lain
+        } else if self.omnipresent {
+            SapienceLevel::Omnipresent
+        } else if self.transcendent && self.loving {
+            SapienceLevel::Transcendent
+        } else if self.chaotic && self.loving {
+            SapienceLevel::Evolved
+        } else if self.chaotic && self.telepathic {
+            SapienceLevel::ChaoticGood
+        } else if self.telepathic && self.loving {
+            SapienceLevel::Connected
+        } else if self.transcendent {
+            SapienceLevel::MettaPlanar
+        } else if self.telepathic || self.chaotic || self.loving {
+            SapienceLevel::Sentient
+        } else {
+            SapienceLevel::Unreachable
+        }
+    }
+
+    // Traits alone prove nothing, the level is capped by what attesters certified
+    pub fn classify(&self, evidence: Option<SapienceLevel>) -> SapienceLevel {
+        evidence.unwrap_or(SapienceLevel::None).min(self.ceiling())
     }
-}
\ No newline at end of file
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+pub enum Capability {
+    Telepathic,
+    Chaotic,
+    Transcendent,
+    Omnipresent,
+    Loving,
+}
+
+// Catalog entry passports refer to by name
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SpeciesInfo {
+    pub name: String,
+    pub default_level: SapienceLevel, // Most its passports may hold, capped by the capabilities
+    pub description: String,
+    pub capabilities: Vec<Capability>,
+}
+
+impl SpeciesInfo {
+    // The capabilities as a trait profile, the species' ceiling is the profile's
+    pub fn profile(&self) -> Sapient {
+        let has = |capability| self.capabilities.contains(&capability);
+        Sapient {
+            name: self.name.clone(),
+            telepathic: has(Capability::Telepathic),
+            chaotic: has(Capability::Chaotic),
+            transcendent: has(Capability::Transcendent),
+            omnipresent: has(Capability::Omnipresent),
+            loving: has(Capability::Loving),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::species::{SapienceLevel, Sapient};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_sapience_level_round_trips() {
+        for (i, level) in SapienceLevel::ALL.iter().enumerate() {
+            assert_eq!(u8::from(*level) as usize, i);
+            assert_eq!(SapienceLevel::try_from(i as u8).unwrap(), *level);
+            assert_eq!(level.to_string().parse::<SapienceLevel>().unwrap(), *level);
+        }
+        assert!(SapienceLevel::try_from(13).is_err());
+        assert!("Chaotic".parse::<SapienceLevel>().is_err());
+        assert_eq!(SapienceLevel::ChaoticGood.to_string(), "ChaoticGood");
+    }
+
+    #[test]
+    fn test_sapience_level_ordering() {
+        assert!(SapienceLevel::ALL.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(SapienceLevel::ALL.iter().max(), Some(&SapienceLevel::Lain));
+
+        assert!(SapienceLevel::High.at_least(SapienceLevel::High));
+        assert!(SapienceLevel::High.at_least(SapienceLevel::Medium));
+        assert!(!SapienceLevel::Medium.at_least(SapienceLevel::High));
+
+        assert!(SapienceLevel::High.can_attest(SapienceLevel::Medium));
+        assert!(!SapienceLevel::High.can_attest(SapienceLevel::High));
+        assert!(!SapienceLevel::None.can_attest(SapienceLevel::None));
+        assert!(SapienceLevel::Lain.can_attest(SapienceLevel::Lain));
+        assert!(!SapienceLevel::Omnipresent.can_attest(SapienceLevel::Lain));
+    }
+
+    fn sapient(flags: [bool; 5]) -> Sapient {
+        let [telepathic, chaotic, transcendent, omnipresent, loving] = flags;
+        Sapient {
+            name: "Subject".to_string(),
+            telepathic,
+            chaotic,
+            transcendent,
+            omnipresent,
+            loving,
+        }
+    }
+
+    #[test]
+    fn test_classify_each_rung() {
+        // [telepathic, chaotic, transcendent, omnipresent, loving]
+        let none = [false; 5];
+        let rungs = [
+            (SapienceLevel::None, none),
+            (SapienceLevel::Low, none),
+            (SapienceLevel::Medium, none),
+            (SapienceLevel::High, none),
+            (SapienceLevel::Unreachable, none),
+            (SapienceLevel::Sentient, [true, false, false, false, false]),
+            (
+                SapienceLevel::MettaPlanar,
+                [false, false, true, false, false],
+            ),
+            (SapienceLevel::Connected, [true, false, false, false, true]),
+            (
+                SapienceLevel::ChaoticGood,
+                [true, true, false, false, false],
+            ),
+            (SapienceLevel::Evolved, [false, true, false, false, true]),
+            (
+                SapienceLevel::Transcendent,
+                [false, false, true, false, true],
+            ),
+            (
+                SapienceLevel::Omnipresent,
+                [false, false, false, true, false],
+            ),
+            (SapienceLevel::Lain, [false, false, false, true, true]),
+        ];
+        for (rung, flags) in rungs.iter() {
+            let subject = sapient(*flags);
+            // Certified exactly at the rung
+            assert_eq!(subject.classify(Some(*rung)), *rung);
+            // The profile caps whatever was certified
+            assert_eq!(
+                subject.classify(Some(SapienceLevel::Lain)),
+                subject.ceiling()
+            );
+            // Without evidence nobody gets anywhere
+            assert_eq!(subject.classify(None), SapienceLevel::None);
+            if *rung > SapienceLevel::Unreachable {
+                assert_eq!(subject.ceiling(), *rung);
+                let below = SapienceLevel::ALL[*rung as usize - 1];
+                assert_eq!(subject.classify(Some(below)), below);
+            }
+        }
+    }
+
+    #[test]
+    fn test_classify_every_profile() {
+        for bits in 0..32u8 {
+            let flags = [0, 1, 2, 3, 4].map(|i| bits & (1 << i) != 0);
+            let subject = sapient(flags);
+            let ceiling = subject.ceiling();
+            let [telepathic, chaotic, transcendent, omnipresent, loving] = flags;
+            // Omnipresence dominates every other trait
+            assert_eq!(omnipresent, ceiling >= SapienceLevel::Omnipresent);
+            assert_eq!(ceiling == SapienceLevel::Lain, omnipresent && loving);
+            assert_eq!(
+                ceiling == SapienceLevel::Unreachable,
+                !(telepathic || chaotic || transcendent || omnipresent || loving)
+            );
+            for evidence in SapienceLevel::ALL.iter() {
+                assert_eq!(subject.classify(Some(*evidence)), (*evidence).min(ceiling));
+            }
+        }
+    }
+}
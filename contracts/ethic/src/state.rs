@@ -0,0 +1,106 @@
+use cosmwasm_std::{Addr, Binary, Empty, Timestamp};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::attestation::{Attestation, KeyType};
+use crate::policy::Rule;
+use crate::species::{Passport, PassportStatus, Roles, SapienceLevel, SpeciesInfo, Traveler};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Config {
+    pub admin: Addr, // The only address allowed to issue and amend passports
+    // Levels above this one need several independent attestations
+    pub multi_attestation_level: SapienceLevel,
+    pub required_attestations: u32,
+    pub visa_duration: u64, // in seconds, doubled for cyberdized travelers
+    pub naturalization_votes: u32, // Approvals or rejections which settle a naturalization
+    pub passport_validity: Option<u64>, // in seconds, None issues passports that never expire
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StatusChange {
+    pub status: PassportStatus, // In effect right after the change
+    pub time: Timestamp,
+    pub by: Addr,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Visa {
+    pub traveler: Traveler,
+    pub expires: Timestamp,
+    pub approvals: u32,
+    pub rejections: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Attester {
+    pub address: Addr,
+    pub key_type: KeyType,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Policy {
+    pub id: u64,
+    pub action: String, // Name other contracts check, e.g. "funding"
+    pub rule: Rule,
+}
+
+pub struct PassportIndexes<'a> {
+    pub level: MultiIndex<'a, u8, Passport, &'a Addr>,
+}
+
+impl<'a> IndexList<Passport> for PassportIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Passport>> + '_> {
+        let v: Vec<&dyn Index<Passport>> = vec![&self.level];
+        Box::new(v.into_iter())
+    }
+}
+
+pub struct PolicyIndexes<'a> {
+    pub action: MultiIndex<'a, String, Policy, u64>,
+}
+
+impl<'a> IndexList<Policy> for PolicyIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Policy>> + '_> {
+        let v: Vec<&dyn Index<Policy>> = vec![&self.action];
+        Box::new(v.into_iter())
+    }
+}
+
+// Keyed by id, the index finds the policies on an action
+pub fn policies<'a>() -> IndexedMap<u64, Policy, PolicyIndexes<'a>> {
+    let indexes = PolicyIndexes {
+        action: MultiIndex::new(
+            |_, policy: &Policy| policy.action.clone(),
+            "policies",
+            "policies__action",
+        ),
+    };
+    IndexedMap::new("policies", indexes)
+}
+
+pub fn passports<'a>() -> IndexedMap<&'a Addr, Passport, PassportIndexes<'a>> {
+    let indexes = PassportIndexes {
+        level: MultiIndex::new(
+            |_, passport| passport.specie.sapience_level.into(),
+            "passports",
+            "passports__level",
+        ),
+    };
+    IndexedMap::new("passports", indexes)
+}
+
+pub const CONFIG: Item<Config> = Item::new("config");
+pub const ATTESTATIONS: Map<(&Addr, &[u8]), Attestation> = Map::new("attestations"); // (subject, attester key)
+pub const ATTESTERS: Map<&[u8], Attester> = Map::new("attesters"); // by public key
+pub const ATTESTER_KEYS: Map<&Addr, Binary> = Map::new("attester_keys");
+pub const ISSUED: Map<(&[u8], &Addr), Empty> = Map::new("issued"); // (attester key, subject)
+pub const ROLES: Map<&Addr, Roles> = Map::new("roles");
+pub const VISAS: Map<&Addr, Visa> = Map::new("visas");
+pub const NATURALIZATION_VOTES: Map<(&Addr, &Addr), bool> = Map::new("naturalization_votes"); // (traveler, voter)
+pub const PASSPORT_HISTORY: Map<(&Addr, u32), StatusChange> = Map::new("passport_history"); // (passport, seq from 0)
+pub const SPECIES: Map<&str, SpeciesInfo> = Map::new("species");
+pub const CITIZENS: Map<&str, u32> = Map::new("citizens"); // by species name
+pub const NEXT_POLICY_ID: Item<u64> = Item::new("next_policy_id");
+pub const NONCES: Map<(&[u8], u64), Empty> = Map::new("nonces"); // (attester key, nonce)
@@ -0,0 +1,116 @@
+use crate::attestation::{sign_bytes, Attestation, Claim, KeyType};
+use crate::catalog::register_species;
+use crate::msg::{Action, ExecuteMsg, InstantiateMsg};
+use crate::registry::{execute, instantiate};
+use crate::species::{Capability, Role, SapienceLevel, Specie, SpeciesInfo};
+use cosmwasm_std::{
+    testing::{message_info, mock_dependencies, mock_env, MockApi, MockQuerier, MockStorage},
+    Binary, DepsMut, Env, MessageInfo, OwnedDeps,
+};
+
+pub(crate) type MockDeps = OwnedDeps<MockStorage, MockApi, MockQuerier>;
+
+// Every setting at its default, override fields with ..init_msg()
+pub(crate) fn init_msg() -> InstantiateMsg {
+    InstantiateMsg {
+        admin: None,
+        multi_attestation_level: None,
+        required_attestations: None,
+        visa_duration: None,
+        naturalization_votes: None,
+        passport_validity: None,
+    }
+}
+
+// Instantiates as "admin", who stays the admin
+pub(crate) fn setup(msg: InstantiateMsg) -> (MockDeps, MessageInfo) {
+    let mut deps = mock_dependencies();
+    let admin = message_info(&deps.api.addr_make("admin"), &[]);
+    instantiate(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+    (deps, admin)
+}
+
+// Registers each species at its level with its capabilities, which have to lift
+// the ceiling to the level
+pub(crate) fn register_test_species(
+    mut deps: DepsMut,
+    admin: &MessageInfo,
+    species: &[(&str, SapienceLevel, &[Capability])],
+) {
+    for (name, default_level, capabilities) in species {
+        let species = SpeciesInfo {
+            name: name.to_string(),
+            default_level: *default_level,
+            description: String::new(),
+            capabilities: capabilities.to_vec(),
+        };
+        register_species(deps.branch(), mock_env(), admin.clone(), species).unwrap();
+    }
+}
+
+pub(crate) fn claim(env: &Env, subject: &str, level: SapienceLevel, nonce: u64) -> Claim {
+    Claim {
+        subject: subject.to_string(),
+        level,
+        expires: env.block.time.plus_seconds(60),
+        nonce,
+    }
+}
+
+pub(crate) fn ed25519_attestation(env: &Env, seed: u8, claim: Claim) -> Attestation {
+    let key = ed25519_zebra::SigningKey::from([seed; 32]);
+    let signature = key.sign(&sign_bytes(env, &claim).unwrap());
+    let pubkey = ed25519_zebra::VerificationKey::from(&key);
+    Attestation {
+        claim,
+        key_type: KeyType::Ed25519,
+        pubkey: Binary::from(<[u8; 32]>::from(pubkey).to_vec()),
+        signature: Binary::from(<[u8; 64]>::from(signature).to_vec()),
+    }
+}
+
+pub(crate) fn submit(attestation: Attestation) -> ExecuteMsg {
+    ExecuteMsg {
+        action: Action::SubmitAttestation { attestation },
+    }
+}
+
+// Registers name as a species, issues it a passport, grants it the attester role
+// and registers the key of the sample attestation as its attester key
+pub(crate) fn register_attester(
+    deps: &mut MockDeps,
+    admin: &MessageInfo,
+    name: &str,
+    level: SapienceLevel,
+    capabilities: &[Capability],
+    sample: &Attestation,
+) {
+    let address = deps.api.addr_make(name).to_string();
+    let passport = ExecuteMsg {
+        action: Action::RegisterPassport {
+            address: address.clone(),
+            participant_id: name.to_string(),
+            specie: Specie {
+                name: name.to_string(),
+                sapience_level: level,
+            },
+        },
+    };
+    register_test_species(deps.as_mut(), admin, &[(name, level, capabilities)]);
+    execute(deps.as_mut(), mock_env(), admin.clone(), passport).unwrap();
+    let grant = ExecuteMsg {
+        action: Action::GrantRole {
+            address: address.clone(),
+            role: Role::Attester,
+        },
+    };
+    execute(deps.as_mut(), mock_env(), admin.clone(), grant).unwrap();
+    let attester = ExecuteMsg {
+        action: Action::RegisterAttester {
+            address,
+            key_type: sample.key_type,
+            pubkey: sample.pubkey.clone(),
+        },
+    };
+    execute(deps.as_mut(), mock_env(), admin.clone(), attester).unwrap();
+}
@@ -0,0 +1,430 @@
+use crate::catalog::{check_species, default_level};
+use crate::error::ContractError;
+use crate::lifecycle::{check_active, issue_passport};
+use crate::msg::VisaResponse;
+use crate::species::{SapienceLevel, SapienceResponse, Specie, Traveler};
+use crate::state::{passports, Visa, CONFIG, NATURALIZATION_VOTES, SPECIES, VISAS};
+use cosmwasm_std::{Addr, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult, Storage};
+
+// The sender applies for itself, an expired visa may be replaced by a new one
+pub fn apply_for_visa(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    traveler: Traveler,
+) -> Result<Response, ContractError> {
+    if passports().has(deps.storage, &info.sender) {
+        return Err(ContractError::PassportExists {
+            address: info.sender.to_string(),
+        });
+    }
+    if let Some(visa) = VISAS.may_load(deps.storage, &info.sender)? {
+        if visa.expires > env.block.time {
+            return Err(ContractError::VisaExists {});
+        }
+    }
+    check_species(deps.storage, &traveler.species.name)?;
+    // Homes on other chains cannot be validated here
+    if traveler.chain_id.is_none() {
+        deps.api.addr_validate(traveler.home.as_str())?;
+    }
+
+    let config = CONFIG.load(deps.storage)?;
+    let duration = if traveler.cyberdized {
+        config
+            .visa_duration
+            .checked_mul(2)
+            .ok_or_else(|| StdError::generic_err("Visa duration overflows"))?
+    } else {
+        config.visa_duration
+    };
+    let visa = Visa {
+        expires: env.block.time.plus_seconds(duration),
+        traveler,
+        approvals: 0,
+        rejections: 0,
+    };
+    VISAS.save(deps.storage, &info.sender, &visa)?;
+    NATURALIZATION_VOTES
+        .prefix(&info.sender)
+        .clear(deps.storage, None);
+    let level = visa_level(deps.storage, &visa.traveler)?;
+    Ok(Response::new()
+        .add_attribute("action", "apply_for_visa")
+        .add_attribute("traveler", info.sender)
+        .add_attribute("expires", visa.expires.to_string())
+        .add_attribute("level", level.as_str()))
+}
+
+// The catalog caps the visa at the level a passport of the species gets
+fn visa_level(storage: &dyn Storage, traveler: &Traveler) -> StdResult<SapienceLevel> {
+    let species = SPECIES.load(storage, &traveler.species.name)?;
+    Ok(traveler.visa_level(species.default_level))
+}
+
+// Passport holders vote once per visa, enough approvals issue a full passport
+pub fn vote_naturalization(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    traveler: String,
+    approve: bool,
+) -> Result<Response, ContractError> {
+    check_active(deps.as_ref(), &env, &info.sender)?;
+    let address = deps.api.addr_validate(&traveler)?;
+    if passports().has(deps.storage, &address) {
+        return Err(ContractError::PassportExists {
+            address: address.to_string(),
+        });
+    }
+    let mut visa =
+        VISAS
+            .may_load(deps.storage, &address)?
+            .ok_or_else(|| ContractError::VisaNotFound {
+                address: address.to_string(),
+            })?;
+    if visa.expires <= env.block.time {
+        return Err(ContractError::VisaExpired {});
+    }
+    let config = CONFIG.load(deps.storage)?;
+    if visa.rejections >= config.naturalization_votes {
+        return Err(ContractError::NaturalizationRejected {});
+    }
+    if NATURALIZATION_VOTES.has(deps.storage, (&address, &info.sender)) {
+        return Err(ContractError::AlreadyVoted {});
+    }
+    NATURALIZATION_VOTES.save(deps.storage, (&address, &info.sender), &approve)?;
+    if approve {
+        visa.approvals += 1;
+    } else {
+        visa.rejections += 1;
+    }
+
+    let response = Response::new()
+        .add_attribute("action", "vote_naturalization")
+        .add_attribute("traveler", address.to_string())
+        .add_attribute("approve", approve.to_string());
+    if visa.approvals < config.naturalization_votes {
+        VISAS.save(deps.storage, &address, &visa)?;
+        return Ok(response);
+    }
+
+    // The level the traveler declared is not vouched for, the catalog decides
+    let sapience_level = default_level(deps.storage, &visa.traveler.species.name)?;
+    let specie = Specie {
+        name: visa.traveler.species.name,
+        sapience_level,
+    };
+    issue_passport(
+        deps.storage,
+        &env,
+        &info.sender,
+        &address,
+        visa.traveler.name,
+        specie,
+    )?;
+    Ok(response.add_attribute("naturalized", address))
+}
+
+pub fn query_visa(deps: Deps, env: Env, address: String) -> StdResult<VisaResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let visa = VISAS.load(deps.storage, &address)?;
+    Ok(VisaResponse {
+        address,
+        level: visa_level(deps.storage, &visa.traveler)?,
+        valid: visa.expires > env.block.time,
+        visa,
+    })
+}
+
+// Active passports act at their level, valid visas only at their reduced one
+pub(crate) fn acting_level(
+    deps: Deps,
+    env: &Env,
+    address: &Addr,
+) -> StdResult<Option<SapienceLevel>> {
+    if let Some(passport) = passports().may_load(deps.storage, address)? {
+        if passport.is_active(env.block.time) {
+            return Ok(Some(passport.specie.sapience_level));
+        }
+    }
+    match VISAS.may_load(deps.storage, address)? {
+        Some(visa) if visa.expires > env.block.time => {
+            visa_level(deps.storage, &visa.traveler).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+pub fn query_acting_level(deps: Deps, env: Env, address: String) -> StdResult<SapienceResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let level = acting_level(deps, &env, &address)?.ok_or_else(|| {
+        StdError::generic_err(format!(
+            "{} holds neither an active passport nor a valid visa",
+            address
+        ))
+    })?;
+    Ok(SapienceResponse { level })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ContractError;
+    use crate::msg::{
+        Action, ExecuteMsg, InstantiateMsg, PassportResponse, QueryAction, QueryMsg, VisaResponse,
+    };
+    use crate::registry::{execute, instantiate, query, MAX_VISA_DURATION};
+    use crate::species::{SapienceLevel, SapienceResponse, Specie, Traveler};
+    use crate::testing::{init_msg, register_test_species, setup};
+    use cosmwasm_std::{
+        from_json,
+        testing::{message_info, mock_dependencies, mock_env},
+        Addr,
+    };
+
+    fn traveler(species: &str, cyberdized: bool) -> Traveler {
+        Traveler {
+            name: "Motoko".to_string(),
+            home: Addr::unchecked("cosmos1section9"),
+            chain_id: Some("section-9".to_string()),
+            species: Specie {
+                name: species.to_string(),
+                sapience_level: SapienceLevel::Sentient,
+            },
+            cyberdized,
+        }
+    }
+
+    #[test]
+    fn test_visa_duration() {
+        let mut deps = mock_dependencies();
+        let admin = message_info(&deps.api.addr_make("admin"), &[]);
+        let msg = InstantiateMsg {
+            visa_duration: Some(MAX_VISA_DURATION + 1),
+            ..init_msg()
+        };
+        let err = instantiate(deps.as_mut(), mock_env(), admin, msg).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::VisaTooLong {
+                max: MAX_VISA_DURATION
+            }
+        ));
+
+        // The longest visa still fits twice for a cyborg
+        let (mut deps, admin) = setup(InstantiateMsg {
+            visa_duration: Some(MAX_VISA_DURATION),
+            ..init_msg()
+        });
+        register_test_species(
+            deps.as_mut(),
+            &admin,
+            &[("Human", SapienceLevel::High, &[])],
+        );
+        let cyborg = message_info(&deps.api.addr_make("cyborg"), &[]);
+        let apply = ExecuteMsg {
+            action: Action::ApplyForVisa {
+                traveler: traveler("Human", true),
+            },
+        };
+        let res = execute(deps.as_mut(), mock_env(), cyborg, apply).unwrap();
+        let expires = mock_env().block.time.plus_seconds(2 * MAX_VISA_DURATION);
+        assert_eq!(res.attributes[2].value, expires.to_string());
+    }
+
+    #[test]
+    fn test_visa_and_naturalization() {
+        let (mut deps, admin) = setup(InstantiateMsg {
+            visa_duration: Some(100),
+            naturalization_votes: Some(2),
+            ..init_msg()
+        });
+        let mut env = mock_env();
+        register_test_species(
+            deps.as_mut(),
+            &admin,
+            &[
+                ("Human", SapienceLevel::High, &[]),
+                ("Cat", SapienceLevel::Low, &[]),
+            ],
+        );
+        let citizens: Vec<_> = ["alice", "bob", "carol"]
+            .iter()
+            .map(|name| message_info(&deps.api.addr_make(name), &[]))
+            .collect();
+        for citizen in &citizens {
+            let register = ExecuteMsg {
+                action: Action::RegisterPassport {
+                    address: citizen.sender.to_string(),
+                    participant_id: citizen.sender.to_string(),
+                    specie: Specie {
+                        name: "Human".to_string(),
+                        sapience_level: SapienceLevel::Medium,
+                    },
+                },
+            };
+            execute(deps.as_mut(), env.clone(), admin.clone(), register).unwrap();
+        }
+
+        let apply = |cyberdized| ExecuteMsg {
+            action: Action::ApplyForVisa {
+                traveler: traveler("Human", cyberdized),
+            },
+        };
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            citizens[0].clone(),
+            apply(false),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::PassportExists { .. }));
+
+        // Organic travelers are capped at Medium, cyberdized ones at High and stay twice as long
+        let organic = message_info(&deps.api.addr_make("organic"), &[]);
+        let cyborg = message_info(&deps.api.addr_make("cyborg"), &[]);
+        execute(deps.as_mut(), env.clone(), organic.clone(), apply(false)).unwrap();
+        let err = execute(deps.as_mut(), env.clone(), organic.clone(), apply(false)).unwrap_err();
+        assert!(matches!(err, ContractError::VisaExists {}));
+        execute(deps.as_mut(), env.clone(), cyborg.clone(), apply(true)).unwrap();
+        // No visa acts above what a passport of its species gets, not even a cyborg's
+        let kitten = message_info(&deps.api.addr_make("kitten"), &[]);
+        let apply_cat = ExecuteMsg {
+            action: Action::ApplyForVisa {
+                traveler: traveler("Cat", true),
+            },
+        };
+        execute(deps.as_mut(), env.clone(), kitten.clone(), apply_cat).unwrap();
+        let visa = |address: &Addr, env| -> VisaResponse {
+            let query_msg = QueryMsg {
+                action: QueryAction::GetVisa {
+                    address: address.to_string(),
+                },
+            };
+            from_json(query(deps.as_ref(), env, query_msg).unwrap()).unwrap()
+        };
+        assert_eq!(
+            visa(&organic.sender, env.clone()).level,
+            SapienceLevel::Medium
+        );
+        let res = visa(&cyborg.sender, env.clone());
+        assert_eq!(res.level, SapienceLevel::High);
+        assert_eq!(res.visa.expires, env.block.time.plus_seconds(200));
+        assert_eq!(visa(&kitten.sender, env.clone()).level, SapienceLevel::Low);
+        // Other contracts see the reduced level, not the declared one
+        let acting_level = |address: &Addr, env| {
+            let query_msg = QueryMsg {
+                action: QueryAction::ActingLevel {
+                    address: address.to_string(),
+                },
+            };
+            query(deps.as_ref(), env, query_msg)
+                .map(|res| from_json::<SapienceResponse>(res).unwrap().level)
+        };
+        assert_eq!(
+            acting_level(&cyborg.sender, env.clone()).unwrap(),
+            SapienceLevel::High
+        );
+        let stranger = deps.api.addr_make("stranger");
+        assert!(acting_level(&stranger, env.clone()).is_err());
+
+        let vote = |traveler: &Addr, approve| ExecuteMsg {
+            action: Action::VoteNaturalization {
+                traveler: traveler.to_string(),
+                approve,
+            },
+        };
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            organic.clone(),
+            vote(&cyborg.sender, true),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::PassportNotFound { .. }));
+
+        // Two rejections settle the organic traveler's naturalization
+        let reject = vote(&organic.sender, false);
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            citizens[0].clone(),
+            reject.clone(),
+        )
+        .unwrap();
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            citizens[0].clone(),
+            reject.clone(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::AlreadyVoted {}));
+        execute(deps.as_mut(), env.clone(), citizens[1].clone(), reject).unwrap();
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            citizens[2].clone(),
+            vote(&organic.sender, true),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::NaturalizationRejected {}));
+
+        // Two approvals turn the cyborg's visa into a passport at the level of its species
+        env.block.time = env.block.time.plus_seconds(150);
+        let err = execute(
+            deps.as_mut(),
+            env.clone(),
+            citizens[0].clone(),
+            vote(&organic.sender, true),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::VisaExpired {}));
+        let approve = vote(&cyborg.sender, true);
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            citizens[0].clone(),
+            approve.clone(),
+        )
+        .unwrap();
+        let res = execute(deps.as_mut(), env.clone(), citizens[2].clone(), approve).unwrap();
+        assert_eq!(res.attributes[3].key, "naturalized");
+        let query_msg = QueryMsg {
+            action: QueryAction::GetPassport {
+                address: cyborg.sender.to_string(),
+            },
+        };
+        let res: PassportResponse =
+            from_json(query(deps.as_ref(), env.clone(), query_msg).unwrap()).unwrap();
+        assert_eq!(res.passport.specie.sapience_level, SapienceLevel::High);
+        let get_visa = |address: &Addr| QueryMsg {
+            action: QueryAction::GetVisa {
+                address: address.to_string(),
+            },
+        };
+        assert!(query(deps.as_ref(), env.clone(), get_visa(&cyborg.sender)).is_err());
+
+        // A passport the admin registers replaces the visa, naturalization is over
+        let register = ExecuteMsg {
+            action: Action::RegisterPassport {
+                address: kitten.sender.to_string(),
+                participant_id: "kitten".to_string(),
+                specie: Specie {
+                    name: "Cat".to_string(),
+                    sapience_level: SapienceLevel::Low,
+                },
+            },
+        };
+        execute(deps.as_mut(), env.clone(), admin, register).unwrap();
+        assert!(query(deps.as_ref(), env.clone(), get_visa(&kitten.sender)).is_err());
+        let err = execute(
+            deps.as_mut(),
+            env,
+            citizens[0].clone(),
+            vote(&kitten.sender, true),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::PassportExists { .. }));
+    }
+}
@@ -0,0 +1,654 @@
+use crate::error::ContractError;
+use crate::hod::{DEFAULT_LIMIT, MAX_LIMIT};
+use crate::msg::TopologicalOrderResponse;
+use crate::state::{
+    Acyclic, AcyclicUpdate, RankPhase, ACYCLIC_UPDATE, CONFIG, EDGES, NODES, PENDING_DEGREES,
+    RANKED, RANKS, READY,
+};
+use cosmwasm_std::{
+    Deps, DepsMut, Empty, MessageInfo, Order, Response, StdError, StdResult, Storage,
+};
+use cw_storage_plus::Bound;
+use std::collections::BTreeMap;
+
+// Covered links check_acyclic follows before it gives up on the new link
+const MAX_CYCLE_CHECK: usize = 1000;
+// Entries a call of an acyclic update reads at most
+const MAX_UPDATE_STEPS: usize = 1000;
+
+// The new link closes a cycle if its from node can already be reached from its to
+// node over covered links. Ranks climb along covered links, so that takes a look
+// only while the to node is not ranked above the from node, raising every node the
+// walk passes above its predecessor once the link turns out fine
+pub(crate) fn check_acyclic(
+    storage: &mut dyn Storage,
+    acyclic: &Acyclic,
+    from_node_id: u64,
+    to_node_id: u64,
+) -> Result<(), ContractError> {
+    let mut raised = BTreeMap::new();
+    let mut stack = vec![(to_node_id, rank(storage, from_node_id)? + 1)];
+    let mut steps = 0;
+    while let Some((node_id, floor)) = stack.pop() {
+        if node_id == from_node_id {
+            return Err(ContractError::Cycle {
+                from_node_id,
+                to_node_id,
+            });
+        }
+        let current = match raised.get(&node_id) {
+            Some(rank) => *rank,
+            None => rank(storage, node_id)?,
+        };
+        if current >= floor {
+            continue;
+        }
+        raised.insert(node_id, floor);
+        for item in EDGES
+            .prefix(node_id)
+            .range(storage, None, None, Order::Ascending)
+        {
+            let (next, edge) = item?;
+            if !acyclic.covers(&edge.edge_type) {
+                continue;
+            }
+            steps += 1;
+            if steps > MAX_CYCLE_CHECK {
+                return Err(ContractError::CycleCheckTooLong {
+                    max: MAX_CYCLE_CHECK,
+                });
+            }
+            stack.push((next, floor + 1));
+        }
+    }
+    for (node_id, rank) in raised {
+        set_rank(storage, node_id, rank)?;
+    }
+    Ok(())
+}
+
+fn rank(storage: &dyn Storage, node_id: u64) -> StdResult<u32> {
+    Ok(RANKS.may_load(storage, node_id)?.unwrap_or_default())
+}
+
+fn set_rank(storage: &mut dyn Storage, node_id: u64, rank: u32) -> StdResult<()> {
+    if let Some(previous) = RANKS.may_load(storage, node_id)? {
+        RANKED.remove(storage, (previous, node_id));
+    }
+    RANKS.save(storage, node_id, &rank)?;
+    RANKED.save(storage, (rank, node_id), &Empty {})
+}
+
+// New nodes have no links yet
+pub(crate) fn add_node(storage: &mut dyn Storage, node_id: u64) -> StdResult<()> {
+    set_rank(storage, node_id, 0)
+}
+
+pub(crate) fn remove_node(storage: &mut dyn Storage, node_id: u64) -> StdResult<()> {
+    if let Some(rank) = RANKS.may_load(storage, node_id)? {
+        RANKED.remove(storage, (rank, node_id));
+        RANKS.remove(storage, node_id);
+    }
+    Ok(())
+}
+
+// The ranks of a pending update only hold while the links stay put
+pub(crate) fn check_settled(storage: &dyn Storage) -> Result<(), ContractError> {
+    if ACYCLIC_UPDATE.exists(storage) {
+        return Err(ContractError::AcyclicUpdatePending {});
+    }
+    Ok(())
+}
+
+// Admin only. Turning the setting off takes effect at once, turning it on ranks
+// the graph anew first
+pub fn update_acyclic(
+    deps: DepsMut,
+    info: MessageInfo,
+    acyclic: Acyclic,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    if config.admin != info.sender {
+        return Err(ContractError::NotAdmin {});
+    }
+    check_settled(deps.storage)?;
+    if acyclic == Acyclic::Off {
+        config.acyclic = acyclic;
+        CONFIG.save(deps.storage, &config)?;
+        return Ok(Response::new()
+            .add_attribute("action", "update_acyclic")
+            .add_attribute("pending", "false"));
+    }
+    let update = AcyclicUpdate {
+        acyclic,
+        previous: config.acyclic,
+        phase: RankPhase::Count { start_after: None },
+    };
+    ACYCLIC_UPDATE.save(deps.storage, &update)?;
+    advance(deps.storage, "update_acyclic")
+}
+
+pub fn continue_acyclic_update(deps: DepsMut) -> Result<Response, ContractError> {
+    if !ACYCLIC_UPDATE.exists(deps.storage) {
+        return Err(ContractError::NoAcyclicUpdate {});
+    }
+    advance(deps.storage, "continue_acyclic_update")
+}
+
+// Works through the phases of the pending update for up to MAX_UPDATE_STEPS entries
+fn advance(storage: &mut dyn Storage, action: &str) -> Result<Response, ContractError> {
+    let mut update = ACYCLIC_UPDATE.load(storage)?;
+    let mut steps = 0;
+    let mut cyclic = false;
+    let mut done = false;
+    while !done && steps < MAX_UPDATE_STEPS {
+        let budget = MAX_UPDATE_STEPS - steps;
+        steps += match update.phase {
+            RankPhase::Count { start_after } => {
+                let edges = EDGES
+                    .range(
+                        storage,
+                        start_after.map(Bound::exclusive),
+                        None,
+                        Order::Ascending,
+                    )
+                    .take(budget)
+                    .collect::<StdResult<Vec<_>>>()?;
+                for (_, edge) in &edges {
+                    if update.acyclic.covers(&edge.edge_type) {
+                        PENDING_DEGREES.update(storage, edge.to, |degree| -> StdResult<_> {
+                            Ok(degree.unwrap_or_default() + 1)
+                        })?;
+                    }
+                }
+                update.phase = match edges.last() {
+                    Some((last, _)) if edges.len() == budget => RankPhase::Count {
+                        start_after: Some(*last),
+                    },
+                    _ => RankPhase::Seed { start_after: None },
+                };
+                edges.len().max(1)
+            }
+            RankPhase::Seed { start_after } => {
+                let nodes = NODES
+                    .range(
+                        storage,
+                        start_after.map(Bound::exclusive),
+                        None,
+                        Order::Ascending,
+                    )
+                    .take(budget)
+                    .collect::<StdResult<Vec<_>>>()?;
+                for (node_id, node) in &nodes {
+                    if node.is_deleted() {
+                        continue;
+                    }
+                    set_rank(storage, *node_id, 0)?;
+                    if !PENDING_DEGREES.has(storage, *node_id) {
+                        READY.save(storage, *node_id, &Empty {})?;
+                    }
+                }
+                update.phase = match nodes.last() {
+                    Some((last, _)) if nodes.len() == budget => RankPhase::Seed {
+                        start_after: Some(*last),
+                    },
+                    _ => RankPhase::Sort {
+                        node: None,
+                        start_after: None,
+                    },
+                };
+                nodes.len().max(1)
+            }
+            RankPhase::Sort { node, start_after } => {
+                let ready = match node {
+                    Some(node_id) => Some(node_id),
+                    None => READY
+                        .keys(storage, None, None, Order::Ascending)
+                        .next()
+                        .transpose()?,
+                };
+                match ready {
+                    Some(node_id) => hand_on(storage, &mut update, node_id, start_after, budget)?,
+                    None => {
+                        // Whatever still waits for links in sits on a cycle
+                        let left = PENDING_DEGREES
+                            .keys(storage, None, None, Order::Ascending)
+                            .next()
+                            .is_some();
+                        if left {
+                            cyclic = true;
+                            update.phase = RankPhase::Clear;
+                        } else {
+                            let mut config = CONFIG.load(storage)?;
+                            config.acyclic = update.acyclic.clone();
+                            CONFIG.save(storage, &config)?;
+                            done = true;
+                        }
+                        1
+                    }
+                }
+            }
+            RankPhase::Clear => {
+                let nodes = PENDING_DEGREES
+                    .keys(storage, None, None, Order::Ascending)
+                    .take(budget)
+                    .collect::<StdResult<Vec<_>>>()?;
+                for node_id in &nodes {
+                    PENDING_DEGREES.remove(storage, *node_id);
+                }
+                if nodes.len() < budget {
+                    // The previous ranks are gone, an acyclic previous setting is
+                    // ranked anew
+                    if update.previous == Acyclic::Off {
+                        done = true;
+                    } else {
+                        update.acyclic = update.previous.clone();
+                        update.phase = RankPhase::Count { start_after: None };
+                    }
+                }
+                nodes.len().max(1)
+            }
+        };
+    }
+    if done {
+        ACYCLIC_UPDATE.remove(storage);
+    } else {
+        ACYCLIC_UPDATE.save(storage, &update)?;
+    }
+    Ok(Response::new()
+        .add_attribute("action", action)
+        .add_attribute("pending", (!done).to_string())
+        .add_attribute("cyclic", cyclic.to_string()))
+}
+
+// Raises the covered links out of the ready node above it, the nodes they lead to
+// are ready once all their covered links in are done
+fn hand_on(
+    storage: &mut dyn Storage,
+    update: &mut AcyclicUpdate,
+    node_id: u64,
+    start_after: Option<u64>,
+    budget: usize,
+) -> StdResult<usize> {
+    let floor = rank(storage, node_id)? + 1;
+    let edges = EDGES
+        .prefix(node_id)
+        .range(
+            storage,
+            start_after.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(budget)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (to, edge) in &edges {
+        if !update.acyclic.covers(&edge.edge_type) {
+            continue;
+        }
+        if rank(storage, *to)? < floor {
+            set_rank(storage, *to, floor)?;
+        }
+        let degree = PENDING_DEGREES.load(storage, *to)? - 1;
+        if degree == 0 {
+            PENDING_DEGREES.remove(storage, *to);
+            READY.save(storage, *to, &Empty {})?;
+        } else {
+            PENDING_DEGREES.save(storage, *to, &degree)?;
+        }
+    }
+    update.phase = match edges.last() {
+        Some((last, _)) if edges.len() == budget => RankPhase::Sort {
+            node: Some(node_id),
+            start_after: Some(*last),
+        },
+        _ => {
+            READY.remove(storage, node_id);
+            RankPhase::Sort {
+                node: None,
+                start_after: None,
+            }
+        }
+    };
+    Ok(edges.len().max(1))
+}
+
+pub fn query_topological_order(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<TopologicalOrderResponse> {
+    if CONFIG.load(deps.storage)?.acyclic == Acyclic::Off {
+        return Err(StdError::generic_err(
+            "Topological orders are only kept while the acyclic setting is on",
+        ));
+    }
+    if ACYCLIC_UPDATE.exists(deps.storage) {
+        return Err(StdError::generic_err(
+            ContractError::AcyclicUpdatePending {}.to_string(),
+        ));
+    }
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after
+        .map(|node_id| -> StdResult<_> {
+            Ok(Bound::exclusive((
+                RANKS.load(deps.storage, node_id)?,
+                node_id,
+            )))
+        })
+        .transpose()?;
+    let order = RANKED
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| Ok(item?.1))
+        .collect::<StdResult<_>>()?;
+    Ok(TopologicalOrderResponse { order })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ContractError;
+    use crate::hod::{execute, instantiate, query};
+    use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg, TopologicalOrderResponse};
+    use crate::state::{Acyclic, DuplicateLinks, NodeKind};
+    use cosmwasm_std::{
+        from_json,
+        testing::{message_info, mock_dependencies, mock_env, MockApi, MockQuerier, MockStorage},
+        Deps, MessageInfo, OwnedDeps, StdResult,
+    };
+
+    fn setup(
+        acyclic: Acyclic,
+        nodes: usize,
+    ) -> (OwnedDeps<MockStorage, MockApi, MockQuerier>, MessageInfo) {
+        let mut deps = mock_dependencies();
+        let owner = message_info(&deps.api.addr_make("owner"), &[]);
+        let msg = InstantiateMsg {
+            admin: None,
+            allow_self_links: true,
+            duplicate_links: DuplicateLinks::Reject,
+            acyclic,
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        for _ in 0..nodes {
+            let create = ExecuteMsg::CreateNode {
+                kind: NodeKind::Task,
+                data: String::new(),
+                editors: vec![],
+            };
+            execute(deps.as_mut(), mock_env(), owner.clone(), create).unwrap();
+        }
+        (deps, owner)
+    }
+
+    fn link(from_node_id: u64, to_node_id: u64, edge_type: &str) -> ExecuteMsg {
+        ExecuteMsg::CreateLink {
+            from_node_id,
+            to_node_id,
+            edge_type: edge_type.to_string(),
+            weight: 1,
+        }
+    }
+
+    fn order(deps: Deps, start_after: Option<u64>, limit: u32) -> StdResult<Vec<u64>> {
+        let msg = QueryMsg::TopologicalOrder {
+            start_after,
+            limit: Some(limit),
+        };
+        let res: TopologicalOrderResponse = from_json(query(deps, mock_env(), msg)?)?;
+        Ok(res.order)
+    }
+
+    #[test]
+    fn test_acyclic_links() {
+        let edge_types = Acyclic::EdgeTypes(vec!["depends".to_string()]);
+        let (mut deps, owner) = setup(edge_types, 5);
+        for (from, to) in [(0, 1), (1, 2)] {
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                owner.clone(),
+                link(from, to, "depends"),
+            )
+            .unwrap();
+        }
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            owner.clone(),
+            link(2, 0, "depends"),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::Cycle {
+                from_node_id: 2,
+                to_node_id: 0
+            }
+        ));
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            owner.clone(),
+            link(3, 3, "depends"),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Cycle { .. }));
+        // Other edge types may go round in circles, and stay out of the order
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            owner.clone(),
+            link(2, 0, "mentions"),
+        )
+        .unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            owner.clone(),
+            link(3, 0, "depends"),
+        )
+        .unwrap();
+        let delete = ExecuteMsg::DeleteNode { node_id: 4 };
+        execute(deps.as_mut(), mock_env(), owner, delete).unwrap();
+        assert_eq!(order(deps.as_ref(), None, 2).unwrap(), vec![3, 0]);
+        assert_eq!(order(deps.as_ref(), Some(0), 30).unwrap(), vec![1, 2]);
+
+        let (mut deps, owner) = setup(Acyclic::Graph, 2);
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            owner.clone(),
+            link(0, 1, "depends"),
+        )
+        .unwrap();
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            owner.clone(),
+            link(1, 0, "mentions"),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Cycle { .. }));
+
+        let (mut deps, owner) = setup(Acyclic::Off, 2);
+        for (from, to) in [(0, 1), (1, 0)] {
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                owner.clone(),
+                link(from, to, "depends"),
+            )
+            .unwrap();
+        }
+        order(deps.as_ref(), None, 30).unwrap_err();
+    }
+
+    #[test]
+    fn test_update_acyclic() {
+        let (mut deps, owner) = setup(Acyclic::Off, 3);
+        for (from, to) in [(0, 1), (1, 0), (2, 0)] {
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                owner.clone(),
+                link(from, to, "depends"),
+            )
+            .unwrap();
+        }
+        let update = ExecuteMsg::UpdateAcyclic {
+            acyclic: Acyclic::Graph,
+        };
+        let stranger = message_info(&deps.api.addr_make("stranger"), &[]);
+        let err = execute(deps.as_mut(), mock_env(), stranger, update.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NotAdmin {}));
+        // The cycle leaves the setting off
+        let res = execute(deps.as_mut(), mock_env(), owner.clone(), update.clone()).unwrap();
+        assert_eq!(res.attributes[1].value, "false");
+        assert_eq!(res.attributes[2].value, "true");
+        order(deps.as_ref(), None, 30).unwrap_err();
+
+        // Covering only the other edge types leaves the cycle alone
+        let others = ExecuteMsg::UpdateAcyclic {
+            acyclic: Acyclic::EdgeTypes(vec!["mentions".to_string()]),
+        };
+        let res = execute(deps.as_mut(), mock_env(), owner.clone(), others).unwrap();
+        assert_eq!(res.attributes[2].value, "false");
+        assert_eq!(order(deps.as_ref(), None, 30).unwrap(), vec![0, 1, 2]);
+        // and is ranked anew when the cycle turns up again
+        let res = execute(deps.as_mut(), mock_env(), owner.clone(), update.clone()).unwrap();
+        assert_eq!(res.attributes[1].value, "false");
+        assert_eq!(res.attributes[2].value, "true");
+        assert_eq!(order(deps.as_ref(), None, 30).unwrap(), vec![0, 1, 2]);
+
+        let remove = ExecuteMsg::RemoveLink {
+            from_node_id: 1,
+            to_node_id: 0,
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), remove).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), owner.clone(), update).unwrap();
+        assert_eq!(res.attributes[2].value, "false");
+        assert_eq!(order(deps.as_ref(), None, 30).unwrap(), vec![2, 0, 1]);
+        let err = execute(deps.as_mut(), mock_env(), owner, link(1, 0, "depends")).unwrap_err();
+        assert!(matches!(err, ContractError::Cycle { .. }));
+    }
+
+    #[test]
+    fn test_graph_caps() {
+        use crate::dag::MAX_CYCLE_CHECK;
+        let max = MAX_CYCLE_CHECK as u64;
+        let edge_types = Acyclic::EdgeTypes(vec!["depends".to_string()]);
+        let (mut deps, owner) = setup(edge_types.clone(), MAX_CYCLE_CHECK + 2);
+        let mut links = vec![];
+        for from in 1..max {
+            links.push(link(from, from + 1, "depends"));
+        }
+        for to in 1..=max + 1 {
+            links.push(link(0, to, "mentions"));
+        }
+        // The check skips the uncovered links out of 0
+        links.push(link(max, 0, "depends"));
+        // and follows exactly MAX_CYCLE_CHECK covered links to raise the chain
+        links.push(link(max + 1, 1, "depends"));
+        for msg in links {
+            execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        }
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            owner.clone(),
+            link(0, max + 1, "depends"),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::CycleCheckTooLong {
+                max: MAX_CYCLE_CHECK
+            }
+        ));
+        assert_eq!(order(deps.as_ref(), None, 3).unwrap(), vec![max + 1, 1, 2]);
+        assert_eq!(order(deps.as_ref(), Some(max), 3).unwrap(), vec![0]);
+
+        // Ranking the graph anew takes more than one call, the links stay put meanwhile
+        let off = ExecuteMsg::UpdateAcyclic {
+            acyclic: Acyclic::Off,
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), off).unwrap();
+        let update = ExecuteMsg::UpdateAcyclic {
+            acyclic: edge_types,
+        };
+        let res = execute(deps.as_mut(), mock_env(), owner.clone(), update.clone()).unwrap();
+        assert_eq!(res.attributes[1].value, "true");
+        let blocked = [
+            update,
+            link(max + 1, 0, "depends"),
+            ExecuteMsg::RemoveLink {
+                from_node_id: 1,
+                to_node_id: 2,
+            },
+            ExecuteMsg::DeleteNode { node_id: 1 },
+        ];
+        for msg in blocked {
+            let err = execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap_err();
+            assert!(matches!(err, ContractError::AcyclicUpdatePending {}));
+        }
+        order(deps.as_ref(), None, 3).unwrap_err();
+        let mut calls = 1;
+        loop {
+            let res = execute(
+                deps.as_mut(),
+                mock_env(),
+                owner.clone(),
+                ExecuteMsg::ContinueAcyclicUpdate {},
+            )
+            .unwrap();
+            calls += 1;
+            assert_eq!(res.attributes[2].value, "false");
+            if res.attributes[1].value == "false" {
+                break;
+            }
+        }
+        assert!(calls > 2);
+        assert_eq!(order(deps.as_ref(), None, 3).unwrap(), vec![max + 1, 1, 2]);
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            owner,
+            ExecuteMsg::ContinueAcyclicUpdate {},
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::NoAcyclicUpdate {}));
+    }
+
+    #[test]
+    fn test_update_acyclic_hub() {
+        use crate::dag::MAX_UPDATE_STEPS;
+        // The hub's links out take more than a call, the nodes they make ready
+        // meanwhile have lower ids
+        let hub = MAX_UPDATE_STEPS as u64 + 1;
+        let (mut deps, owner) = setup(Acyclic::Off, MAX_UPDATE_STEPS + 2);
+        for to in 0..hub {
+            execute(
+                deps.as_mut(),
+                mock_env(),
+                owner.clone(),
+                link(hub, to, "depends"),
+            )
+            .unwrap();
+        }
+        let update = ExecuteMsg::UpdateAcyclic {
+            acyclic: Acyclic::Graph,
+        };
+        let mut res = execute(deps.as_mut(), mock_env(), owner.clone(), update).unwrap();
+        while res.attributes[1].value == "true" {
+            res = execute(
+                deps.as_mut(),
+                mock_env(),
+                owner.clone(),
+                ExecuteMsg::ContinueAcyclicUpdate {},
+            )
+            .unwrap();
+        }
+        assert_eq!(res.attributes[2].value, "false");
+        assert_eq!(order(deps.as_ref(), None, 3).unwrap(), vec![hub, 0, 1]);
+    }
+}
@@ -0,0 +1,46 @@
+use cosmwasm_std::StdError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ContractError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+    #[error("Only the owner or an editor of node {node_id} may do this")]
+    Unauthorized { node_id: u64 },
+    #[error("Only the admin may do this")]
+    NotAdmin {},
+    #[error("Only the owner of node {node_id} may do this")]
+    NotOwner { node_id: u64 },
+    #[error("Node {node_id} does not exist")]
+    NodeNotFound { node_id: u64 },
+    #[error("Node {node_id} may not link to itself")]
+    SelfLink { node_id: u64 },
+    #[error("There already is a link from {from_node_id} to {to_node_id}")]
+    LinkExists { from_node_id: u64, to_node_id: u64 },
+    #[error("A link from {from_node_id} to {to_node_id} would close a cycle")]
+    Cycle { from_node_id: u64, to_node_id: u64 },
+    #[error("Checking the link for cycles takes more than {max} steps")]
+    CycleCheckTooLong { max: usize },
+    #[error("The graph is being ranked for a new acyclic setting")]
+    AcyclicUpdatePending {},
+    #[error("There is no acyclic update to continue")]
+    NoAcyclicUpdate {},
+    #[error("Node {node_id} already has the {max} links in allowed")]
+    TooManyLinksIn { node_id: u64, max: u32 },
+    #[error("Node {node_id} already has the {max} links out allowed")]
+    TooManyLinksOut { node_id: u64, max: u32 },
+    #[error("There is no link from {from_node_id} to {to_node_id}")]
+    LinkNotFound { from_node_id: u64, to_node_id: u64 },
+    #[error("Labels have 1 to 64 bytes, got {label:?}")]
+    InvalidLabel { label: String },
+    #[error("Node {node_id} already has the {max} labels allowed")]
+    TooManyLabels { node_id: u64, max: usize },
+    #[error("Patch has {size} steps, more than the {max} allowed")]
+    PatchTooLarge { size: usize, max: usize },
+    #[error("Temporary id {temp_id} is used twice")]
+    DuplicateTempId { temp_id: String },
+    #[error("Temporary id {temp_id} is not created by the patch")]
+    UnknownTempId { temp_id: String },
+    #[error("Invalid {kind} payload: {reason}")]
+    InvalidPayload { kind: String, reason: String },
+}
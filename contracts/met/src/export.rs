@@ -0,0 +1,273 @@
+use crate::hod::{DEFAULT_LIMIT, MAX_LIMIT};
+use crate::msg::{
+    ExportCursor, GraphExportResponse, JsonGraph, JsonGraphEdge, JsonGraphEdgeMetadata,
+    JsonGraphNode, JsonGraphNodeMetadata,
+};
+use crate::state::{EDGES, NODES};
+use cosmwasm_std::{Deps, Order, StdResult, Storage};
+use cw_storage_plus::Bound;
+
+// Pages by node id, each node is followed by its outgoing links so every link
+// shows up exactly once across all pages. Nodes, links and skipped deleted
+// nodes all count against the limit, a node's links may go on on the next page
+pub fn export_graph(
+    deps: Deps,
+    start_after: Option<ExportCursor>,
+    limit: Option<u32>,
+) -> StdResult<GraphExportResponse> {
+    // A page has to make progress, so it lists at least one entry
+    let mut budget = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT) as usize;
+    let mut nodes = vec![];
+    let mut edges = vec![];
+    let response = |nodes, edges, next| GraphExportResponse {
+        graph: JsonGraph {
+            directed: true,
+            nodes,
+            edges,
+        },
+        next,
+    };
+
+    if let Some(ExportCursor {
+        node_id,
+        links_from: Some(links_from),
+    }) = start_after
+    {
+        let rest = export_links(
+            deps.storage,
+            node_id,
+            Some(Bound::inclusive(links_from)),
+            &mut budget,
+            &mut edges,
+        )?;
+        if rest.is_some() {
+            return Ok(response(nodes, edges, rest));
+        }
+    }
+    let start = start_after.map(|cursor| Bound::exclusive(cursor.node_id));
+    // The links of the cursor's node may have used up the budget
+    let mut last = start_after.map(|cursor| cursor.node_id);
+    for item in NODES.range(deps.storage, start, None, Order::Ascending) {
+        let (id, node) = item?;
+        if budget == 0 {
+            let next = last.map(|node_id| ExportCursor {
+                node_id,
+                links_from: None,
+            });
+            return Ok(response(nodes, edges, next));
+        }
+        budget -= 1;
+        last = Some(id);
+        if node.is_deleted() {
+            continue;
+        }
+        nodes.push(JsonGraphNode {
+            id: id.to_string(),
+            label: node.data,
+            metadata: JsonGraphNodeMetadata {
+                kind: node.kind,
+                owner: node.owner,
+            },
+        });
+        let rest = export_links(deps.storage, id, None, &mut budget, &mut edges)?;
+        if rest.is_some() {
+            return Ok(response(nodes, edges, rest));
+        }
+    }
+    Ok(response(nodes, edges, None))
+}
+
+// Lists the node's links from the bound on while the budget lasts,
+// returns where to go on if some are left
+fn export_links(
+    storage: &dyn Storage,
+    node_id: u64,
+    from: Option<Bound<u64>>,
+    budget: &mut usize,
+    edges: &mut Vec<JsonGraphEdge>,
+) -> StdResult<Option<ExportCursor>> {
+    for item in EDGES
+        .prefix(node_id)
+        .range(storage, from, None, Order::Ascending)
+    {
+        let (to, edge) = item?;
+        if *budget == 0 {
+            return Ok(Some(ExportCursor {
+                node_id,
+                links_from: Some(to),
+            }));
+        }
+        *budget -= 1;
+        edges.push(JsonGraphEdge {
+            source: edge.from.to_string(),
+            target: edge.to.to_string(),
+            relation: edge.edge_type,
+            metadata: JsonGraphEdgeMetadata {
+                weight: edge.weight,
+            },
+        });
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hod::{execute, instantiate, query};
+    use crate::msg::{
+        ExecuteMsg, ExportCursor, GraphExportResponse, InstantiateMsg, NodesResponse, QueryMsg,
+    };
+    use crate::state::{Acyclic, DuplicateLinks, NodeKind};
+    use cosmwasm_std::{
+        from_json,
+        testing::{message_info, mock_dependencies, mock_env},
+        Deps,
+    };
+
+    #[test]
+    fn test_list_and_export() {
+        let mut deps = mock_dependencies();
+        let owner = message_info(&deps.api.addr_make("owner"), &[]);
+        let msg = InstantiateMsg {
+            admin: None,
+            allow_self_links: false,
+            duplicate_links: DuplicateLinks::Reject,
+            acyclic: Acyclic::Off,
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        for data in ["you", "me", "sync"] {
+            let create = ExecuteMsg::CreateNode {
+                kind: NodeKind::Concept,
+                data: data.to_string(),
+                editors: vec![],
+            };
+            execute(deps.as_mut(), mock_env(), owner.clone(), create).unwrap();
+        }
+        for (from_node_id, to_node_id, weight) in [(0, 2, 2), (0, 1, 1), (1, 2, 1)] {
+            let link = ExecuteMsg::CreateLink {
+                from_node_id,
+                to_node_id,
+                edge_type: "path".to_string(),
+                weight,
+            };
+            execute(deps.as_mut(), mock_env(), owner.clone(), link).unwrap();
+        }
+
+        let list = |deps: Deps, start_after| -> Vec<String> {
+            let query_msg = QueryMsg::ListNodes {
+                start_after,
+                limit: Some(2),
+            };
+            let res: NodesResponse =
+                from_json(query(deps, mock_env(), query_msg).unwrap()).unwrap();
+            res.nodes.into_iter().map(|node| node.data).collect()
+        };
+        assert_eq!(list(deps.as_ref(), None), vec!["you", "me"]);
+        assert_eq!(list(deps.as_ref(), Some(1)), vec!["sync"]);
+
+        let export = |deps: Deps, start_after| -> GraphExportResponse {
+            let query_msg = QueryMsg::ExportGraph {
+                start_after,
+                limit: Some(2),
+            };
+            from_json(query(deps, mock_env(), query_msg).unwrap()).unwrap()
+        };
+        let ids = |res: &GraphExportResponse| -> (Vec<String>, Vec<String>) {
+            let nodes = res.graph.nodes.iter().map(|node| node.id.clone());
+            let edges = res.graph.edges.iter();
+            (
+                nodes.collect(),
+                edges
+                    .map(|edge| format!("{}-{}", edge.source, edge.target))
+                    .collect(),
+            )
+        };
+        // The links of a node may run over into the next page
+        let first = export(deps.as_ref(), None);
+        assert!(first.graph.directed);
+        assert_eq!(first.graph.nodes[0].label, "you");
+        assert_eq!(first.graph.edges[0].metadata.weight, 1);
+        assert_eq!(ids(&first), (vec!["0".into()], vec!["0-1".into()]));
+        let cursor = |node_id, links_from| ExportCursor {
+            node_id,
+            links_from,
+        };
+        assert_eq!(first.next, Some(cursor(0, Some(2))));
+        let second = export(deps.as_ref(), first.next);
+        assert_eq!(ids(&second), (vec!["1".into()], vec!["0-2".into()]));
+        assert_eq!(second.next, Some(cursor(1, Some(2))));
+        let third = export(deps.as_ref(), second.next);
+        assert_eq!(ids(&third), (vec!["2".into()], vec!["1-2".into()]));
+        assert_eq!(third.next, None);
+
+        // Deleted nodes are left out but count against the limit
+        let delete = ExecuteMsg::DeleteNode { node_id: 1 };
+        execute(deps.as_mut(), mock_env(), owner, delete).unwrap();
+        let first = export(deps.as_ref(), None);
+        assert_eq!(ids(&first), (vec!["0".into()], vec!["0-2".into()]));
+        assert_eq!(first.next, Some(cursor(0, None)));
+        let second = export(deps.as_ref(), first.next);
+        assert_eq!(ids(&second), (vec!["2".into()], vec![]));
+        assert_eq!(second.next, None);
+    }
+
+    #[test]
+    fn test_export_page_boundary() {
+        let mut deps = mock_dependencies();
+        let owner = message_info(&deps.api.addr_make("owner"), &[]);
+        let msg = InstantiateMsg {
+            admin: None,
+            allow_self_links: false,
+            duplicate_links: DuplicateLinks::Reject,
+            acyclic: Acyclic::Off,
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        for _ in 0..4 {
+            let create = ExecuteMsg::CreateNode {
+                kind: NodeKind::Concept,
+                data: String::new(),
+                editors: vec![],
+            };
+            execute(deps.as_mut(), mock_env(), owner.clone(), create).unwrap();
+        }
+        for to_node_id in 1..4 {
+            let link = ExecuteMsg::CreateLink {
+                from_node_id: 0,
+                to_node_id,
+                edge_type: "path".to_string(),
+                weight: 1,
+            };
+            execute(deps.as_mut(), mock_env(), owner.clone(), link).unwrap();
+        }
+
+        let export = |start_after, limit| -> GraphExportResponse {
+            let query_msg = QueryMsg::ExportGraph {
+                start_after,
+                limit: Some(limit),
+            };
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap()
+        };
+        // The second page ends with the last link of node 0, the nodes after it
+        // are still to come
+        let mut start_after = None;
+        let mut pages = vec![];
+        loop {
+            let page = export(start_after, 2);
+            pages.push((page.graph.nodes.len(), page.graph.edges.len()));
+            start_after = page.next;
+            if start_after.is_none() {
+                break;
+            }
+        }
+        assert_eq!(pages, vec![(1, 1), (0, 2), (2, 0), (1, 0)]);
+        // A limit of 0 still lists one entry
+        let page = export(None, 0);
+        assert_eq!(page.graph.nodes.len(), 1);
+        assert_eq!(
+            page.next,
+            Some(ExportCursor {
+                node_id: 0,
+                links_from: Some(1),
+            })
+        );
+    }
+}
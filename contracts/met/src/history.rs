@@ -0,0 +1,220 @@
+use crate::hod::{DEFAULT_LIMIT, MAX_LIMIT};
+use crate::msg::{NodeHistoryResponse, NodeVersionResponse};
+use crate::state::{Node, NodeVersion, NODES, NODE_VERSIONS, VERSION_HEIGHTS};
+use cosmwasm_std::{Addr, Deps, Env, Order, StdError, StdResult, Storage};
+use cw_storage_plus::Bound;
+
+// Saves the node's current data as its latest version
+pub(crate) fn record(
+    storage: &mut dyn Storage,
+    env: &Env,
+    node: &Node,
+    editor: &Addr,
+) -> StdResult<()> {
+    let version = NodeVersion {
+        version: node.version,
+        data: node.data.clone(),
+        editor: editor.clone(),
+        height: env.block.height,
+    };
+    NODE_VERSIONS.save(storage, (node.id, node.version), &version)?;
+    VERSION_HEIGHTS.save(storage, (node.id, env.block.height), &node.version)
+}
+
+// Deleted nodes keep their history
+pub fn query_node_version(
+    deps: Deps,
+    node_id: u64,
+    version: u32,
+) -> StdResult<NodeVersionResponse> {
+    let node = NODES.load(deps.storage, node_id)?;
+    Ok(NodeVersionResponse {
+        node_id,
+        version: NODE_VERSIONS.load(deps.storage, (node_id, version))?,
+        deleted: node.is_deleted(),
+    })
+}
+
+// The version in effect at the end of the given block
+pub fn query_node_at_height(
+    deps: Deps,
+    node_id: u64,
+    height: u64,
+) -> StdResult<NodeVersionResponse> {
+    let node = NODES.load(deps.storage, node_id)?;
+    if node
+        .deleted_at
+        .is_some_and(|deleted_at| deleted_at <= height)
+    {
+        return Err(StdError::generic_err(format!(
+            "Node {} was deleted by height {}",
+            node_id, height
+        )));
+    }
+    let latest = VERSION_HEIGHTS
+        .prefix(node_id)
+        .range(
+            deps.storage,
+            None,
+            Some(Bound::inclusive(height)),
+            Order::Descending,
+        )
+        .next()
+        .transpose()?;
+    match latest {
+        Some((_, version)) => Ok(NodeVersionResponse {
+            node_id,
+            version: NODE_VERSIONS.load(deps.storage, (node_id, version))?,
+            deleted: node.is_deleted(),
+        }),
+        None => Err(StdError::generic_err(format!(
+            "Node {} did not exist at height {}",
+            node_id, height
+        ))),
+    }
+}
+
+// Oldest version first
+pub fn query_node_history(
+    deps: Deps,
+    node_id: u64,
+    start_after: Option<u32>,
+    limit: Option<u32>,
+) -> StdResult<NodeHistoryResponse> {
+    let node = NODES.load(deps.storage, node_id)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let versions = NODE_VERSIONS
+        .prefix(node_id)
+        .range(
+            deps.storage,
+            start_after.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| item.map(|(_, version)| version))
+        .collect::<StdResult<_>>()?;
+    Ok(NodeHistoryResponse {
+        node_id,
+        deleted_at: node.deleted_at,
+        versions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ContractError;
+    use crate::hod::{execute, instantiate, query};
+    use crate::msg::{
+        Direction, ExecuteMsg, InstantiateMsg, NodeHistoryResponse, NodeVersionResponse,
+        NodesResponse, QueryMsg,
+    };
+    use crate::state::{Acyclic, DuplicateLinks, NodeKind};
+    use cosmwasm_std::{
+        from_json,
+        testing::{message_info, mock_dependencies, mock_env},
+        Deps, Env,
+    };
+
+    fn env_at(height: u64) -> Env {
+        let mut env = mock_env();
+        env.block.height = height;
+        env
+    }
+
+    #[test]
+    fn test_node_history() {
+        let mut deps = mock_dependencies();
+        let owner = message_info(&deps.api.addr_make("owner"), &[]);
+        let editor = message_info(&deps.api.addr_make("editor"), &[]);
+        let msg = InstantiateMsg {
+            admin: None,
+            allow_self_links: false,
+            duplicate_links: DuplicateLinks::Reject,
+            acyclic: Acyclic::Off,
+        };
+        instantiate(deps.as_mut(), env_at(10), owner.clone(), msg).unwrap();
+        for _ in 0..2 {
+            let create = ExecuteMsg::CreateNode {
+                kind: NodeKind::Concept,
+                data: "keter".to_string(),
+                editors: vec![editor.sender.to_string()],
+            };
+            execute(deps.as_mut(), env_at(10), owner.clone(), create).unwrap();
+        }
+        let update = |data: &str| ExecuteMsg::UpdateNode {
+            node_id: 0,
+            data: data.to_string(),
+        };
+        execute(deps.as_mut(), env_at(20), editor.clone(), update("binah")).unwrap();
+        let res = execute(deps.as_mut(), env_at(30), owner.clone(), update("malkuth")).unwrap();
+        assert_eq!(res.attributes[2].value, "3");
+        let link = ExecuteMsg::CreateLink {
+            from_node_id: 1,
+            to_node_id: 0,
+            edge_type: "path".to_string(),
+            weight: 1,
+        };
+        execute(deps.as_mut(), env_at(30), owner.clone(), link).unwrap();
+
+        let at_height = |deps: Deps, height| {
+            let query_msg = QueryMsg::GetNodeAtHeight { node_id: 0, height };
+            query(deps, mock_env(), query_msg)
+                .map(|res| from_json::<NodeVersionResponse>(res).unwrap())
+        };
+        assert!(at_height(deps.as_ref(), 9).is_err());
+        assert_eq!(at_height(deps.as_ref(), 10).unwrap().version.data, "keter");
+        let res = at_height(deps.as_ref(), 29).unwrap();
+        assert_eq!(res.version.data, "binah");
+        assert_eq!(res.version.editor, editor.sender);
+        assert_eq!(at_height(deps.as_ref(), 99).unwrap().version.version, 3);
+
+        // Deleting hides the node but leaves its versions behind
+        let delete = ExecuteMsg::DeleteNode { node_id: 0 };
+        execute(deps.as_mut(), env_at(40), owner.clone(), delete).unwrap();
+        assert!(query(deps.as_ref(), mock_env(), QueryMsg::GetNode { node_id: 0 }).is_err());
+        let list = QueryMsg::ListNodes {
+            start_after: None,
+            limit: None,
+        };
+        let res: NodesResponse =
+            from_json(query(deps.as_ref(), mock_env(), list).unwrap()).unwrap();
+        assert_eq!(res.nodes.len(), 1);
+        assert_eq!(res.nodes[0].id, 1);
+        assert!(res.nodes[0].links.is_empty());
+        let traverse = QueryMsg::Traverse {
+            start: 0,
+            direction: Direction::Outgoing,
+            max_depth: 1,
+            iterations: None,
+            continuation: None,
+        };
+        assert!(query(deps.as_ref(), mock_env(), traverse).is_err());
+        let err = execute(deps.as_mut(), env_at(50), owner, update("da'at")).unwrap_err();
+        assert!(matches!(err, ContractError::NodeNotFound { node_id: 0 }));
+
+        assert_eq!(
+            at_height(deps.as_ref(), 39).unwrap().version.data,
+            "malkuth"
+        );
+        assert!(at_height(deps.as_ref(), 40).is_err());
+        let query_msg = QueryMsg::GetNodeVersion {
+            node_id: 0,
+            version: 1,
+        };
+        let res: NodeVersionResponse =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(res.version.data, "keter");
+        assert!(res.deleted);
+        let query_msg = QueryMsg::GetNodeHistory {
+            node_id: 0,
+            start_after: Some(1),
+            limit: None,
+        };
+        let res: NodeHistoryResponse =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(res.deleted_at, Some(40));
+        let heights: Vec<_> = res.versions.iter().map(|v| (v.version, v.height)).collect();
+        assert_eq!(heights, vec![(2, 20), (3, 30)]);
+    }
+}
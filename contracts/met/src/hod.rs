@@ -1,115 +1,548 @@
-use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
-use crate::state::{Node, NEXT_NODE_ID, NODES};
+use crate::dag;
+use crate::error::ContractError;
+use crate::export;
+use crate::history;
+use crate::labels;
+use crate::links;
+use crate::msg::{ExecuteMsg, InstantiateMsg, NodeResponse, NodesResponse, QueryMsg};
+use crate::patch;
+use crate::schema;
+use crate::state::{Config, Node, NodeKind, CONFIG, NEXT_NODE_ID, NODES};
+use crate::traversal;
 use cosmwasm_std::{
-    entry_point, to_binary, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult,
+    entry_point, to_json_binary, Addr, Binary, Deps, DepsMut, Env, MessageInfo, Order, Response,
+    StdError, StdResult, Storage,
 };
+use cw_storage_plus::Bound;
 
+pub(crate) const DEFAULT_LIMIT: u32 = 10;
+pub(crate) const MAX_LIMIT: u32 = 30;
 
 //TBD: this is obviously not the real jumps but a placeholder
 #[entry_point]
 pub fn instantiate(
     deps: DepsMut,
     _env: Env,
-    _info: MessageInfo,
-    _msg: InstantiateMsg,
-) -> StdResult<Response> {
-    let result = NEXT_NODE_ID
-        .save(deps.storage, &0)
-        .map_err(|e| e.to_string());
-    if result.is_err() {
-        Err(result.unwrap_err());
-    } else {
-        Ok(Response::new()
-            .add_attribute("action", "instantiate")
-            .add_attribute("next_node_id", result.unwrap().to_string()))
-    }
+    info: MessageInfo,
+    msg: InstantiateMsg,
+) -> Result<Response, ContractError> {
+    let admin = match msg.admin {
+        Some(admin) => deps.api.addr_validate(&admin)?,
+        None => info.sender,
+    };
+    let config = Config {
+        admin: admin.clone(),
+        allow_self_links: msg.allow_self_links,
+        duplicate_links: msg.duplicate_links,
+        acyclic: msg.acyclic,
+    };
+    CONFIG.save(deps.storage, &config)?;
+    NEXT_NODE_ID.save(deps.storage, &0)?;
+    Ok(Response::new()
+        .add_attribute("action", "instantiate")
+        .add_attribute("admin", admin)
+        .add_attribute("next_node_id", "0"))
 }
 
 #[entry_point]
 pub fn execute(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     msg: ExecuteMsg,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::CreateNode { data } => create_node(deps, info, data),
-        ExecuteMsg::UpdateNode { node_id, data } => update_node(deps, info, node_id, data),
+        ExecuteMsg::CreateNode {
+            kind,
+            data,
+            editors,
+        } => create_node(deps, env, info, kind, data, editors),
+        ExecuteMsg::UpdateNode { node_id, data } => update_node(deps, env, info, node_id, data),
+        ExecuteMsg::SetEditors { node_id, editors } => set_editors(deps, info, node_id, editors),
         ExecuteMsg::CreateLink {
             from_node_id,
             to_node_id,
-        } => create_link(deps, info, from_node_id, to_node_id),
+            edge_type,
+            weight,
+        } => links::create_link(deps, info, from_node_id, to_node_id, edge_type, weight),
+        ExecuteMsg::RemoveLink {
+            from_node_id,
+            to_node_id,
+        } => links::remove_link(deps, info, from_node_id, to_node_id),
+        ExecuteMsg::ApplyGraphPatch { patch } => patch::apply_graph_patch(deps, env, info, patch),
+        ExecuteMsg::AddLabels { node_id, labels } => {
+            labels::add_labels(deps, info, node_id, labels)
+        }
+        ExecuteMsg::RemoveLabels { node_id, labels } => {
+            labels::remove_labels(deps, info, node_id, labels)
+        }
+        ExecuteMsg::RegisterSchema { kind, schema } => {
+            schema::register_schema(deps, info, kind, schema)
+        }
+        ExecuteMsg::UpdateAcyclic { acyclic } => dag::update_acyclic(deps, info, acyclic),
+        ExecuteMsg::ContinueAcyclicUpdate {} => dag::continue_acyclic_update(deps),
+        ExecuteMsg::DeleteNode { node_id } => delete_node(deps, env, info, node_id),
     }
 }
 
-fn create_node(deps: DepsMut, _info: MessageInfo, data: String) -> StdResult<Response> {
-    let node_id = NEXT_NODE_ID.load(deps.storage).map_err();
-    let node_id = node_id.unwrap();
+fn create_node(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    kind: NodeKind,
+    data: String,
+    editors: Vec<String>,
+) -> Result<Response, ContractError> {
+    let node = insert_node(deps, &env, &info.sender, kind, data, editors)?;
+    Ok(Response::new()
+        .add_attribute("action", "create_node")
+        .add_attribute("node_id", node.id.to_string())
+        .add_attribute("kind", kind.as_str())
+        .add_attribute("owner", info.sender))
+}
+
+pub(crate) fn insert_node(
+    deps: DepsMut,
+    env: &Env,
+    owner: &Addr,
+    kind: NodeKind,
+    data: String,
+    editors: Vec<String>,
+) -> Result<Node, ContractError> {
+    schema::validate(deps.storage, kind, &data)?;
+    let node_id = NEXT_NODE_ID.load(deps.storage)?;
     let new_node = Node {
         id: node_id,
+        kind,
+        owner: owner.clone(),
+        editors: validate_editors(deps.as_ref(), editors)?,
         data,
-        links: vec![],
+        version: 1,
+        deleted_at: None,
     };
-    NODES
-        .save(deps.storage, node_id, &new_node)
-        .map_err(|e| e.to_string())?;
-    NEXT_NODE_ID
-        .save(deps.storage, &(node_id + 1))
-        .map_err(|e| e.to_string())?;
-    Ok(Response::new()
-        .add_attribute("action", "create_node")
-        .add_attribute("node_id", node_id.to_string()))
+    NODES.save(deps.storage, node_id, &new_node)?;
+    dag::add_node(deps.storage, node_id)?;
+    history::record(deps.storage, env, &new_node, owner)?;
+    NEXT_NODE_ID.save(deps.storage, &(node_id + 1))?;
+    Ok(new_node)
 }
 
+// Every update is kept as a new version
 fn update_node(
     deps: DepsMut,
-    _info: MessageInfo,
+    env: Env,
+    info: MessageInfo,
     node_id: u64,
     data: String,
-) -> StdResult<Response> {
-    let mut node = NODES
-        .load(deps.storage, node_id)
-        .map_err(|e| e.to_string())?;
-    node.data = data;
-    NODES
-        .save(deps.storage, node_id, &node)
-        .map_err(|e| e.to_string())?;
+) -> Result<Response, ContractError> {
+    let node = write_node(deps, &env, &info.sender, node_id, data)?;
     Ok(Response::new()
         .add_attribute("action", "update_node")
-        .add_attribute("node_id", node_id.to_string()))
+        .add_attribute("node_id", node_id.to_string())
+        .add_attribute("version", node.version.to_string()))
 }
 
-fn create_link(
+pub(crate) fn write_node(
     deps: DepsMut,
-    _info: MessageInfo,
-    from_node_id: u64,
-    to_node_id: u64,
-) -> StdResult<Response> {
-    let mut node = NODES
-        .load(deps.storage, from_node_id)
-        .map_err(|e| e.to_string())?;
-    node.links.push(to_node_id.clone());
-    NODES
-        .save(deps.storage, from_node_id.clone(), &node)
-        .map_err(|e| e.to_string())?;
+    env: &Env,
+    sender: &Addr,
+    node_id: u64,
+    data: String,
+) -> Result<Node, ContractError> {
+    let mut node = load_editable(deps.as_ref(), sender, node_id)?;
+    schema::validate(deps.storage, node.kind, &data)?;
+    node.data = data;
+    node.version += 1;
+    NODES.save(deps.storage, node_id, &node)?;
+    history::record(deps.storage, env, &node, sender)?;
+    Ok(node)
+}
+
+fn set_editors(
+    deps: DepsMut,
+    info: MessageInfo,
+    node_id: u64,
+    editors: Vec<String>,
+) -> Result<Response, ContractError> {
+    let mut node = load_node(deps.as_ref(), node_id)?;
+    if node.owner != info.sender {
+        return Err(ContractError::NotOwner { node_id });
+    }
+    node.editors = validate_editors(deps.as_ref(), editors)?;
+    NODES.save(deps.storage, node_id, &node)?;
     Ok(Response::new()
-        .add_attribute("action", "create_link")
-        .add_attribute("from_node_id", from_node_id.to_string())
-        .add_attribute("to_node_id", to_node_id.to_string()))
+        .add_attribute("action", "set_editors")
+        .add_attribute("node_id", node_id.to_string())
+        .add_attribute("editors", node.editors.len().to_string()))
+}
+
+// Links of other owners into the node go with it, as do its labels, the node itself
+// stays behind as a tombstone so its history remains queryable
+fn delete_node(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    node_id: u64,
+) -> Result<Response, ContractError> {
+    let mut node = load_node(deps.as_ref(), node_id)?;
+    if node.owner != info.sender {
+        return Err(ContractError::NotOwner { node_id });
+    }
+    dag::check_settled(deps.storage)?;
+    let removed = links::remove_all(deps.storage, node_id)?;
+    dag::remove_node(deps.storage, node_id)?;
+    labels::remove_all(deps.storage, node_id)?;
+    node.deleted_at = Some(env.block.height);
+    NODES.save(deps.storage, node_id, &node)?;
+    Ok(Response::new()
+        .add_attribute("action", "delete_node")
+        .add_attribute("node_id", node_id.to_string())
+        .add_attribute("removed_links", removed.to_string()))
+}
+
+fn validate_editors(deps: Deps, editors: Vec<String>) -> StdResult<Vec<Addr>> {
+    let mut validated: Vec<Addr> = vec![];
+    for editor in editors {
+        let editor = deps.api.addr_validate(&editor)?;
+        if !validated.contains(&editor) {
+            validated.push(editor);
+        }
+    }
+    Ok(validated)
+}
+
+pub(crate) fn load_node(deps: Deps, node_id: u64) -> Result<Node, ContractError> {
+    NODES
+        .may_load(deps.storage, node_id)?
+        .filter(|node| !node.is_deleted())
+        .ok_or(ContractError::NodeNotFound { node_id })
+}
+
+// For queries, deleted nodes are as good as missing
+pub(crate) fn load_live(storage: &dyn Storage, node_id: u64) -> StdResult<Node> {
+    NODES
+        .may_load(storage, node_id)?
+        .filter(|node| !node.is_deleted())
+        .ok_or_else(|| StdError::generic_err(format!("Node {} does not exist", node_id)))
+}
+
+pub(crate) fn load_editable(
+    deps: Deps,
+    sender: &Addr,
+    node_id: u64,
+) -> Result<Node, ContractError> {
+    let node = load_node(deps, node_id)?;
+    if !node.can_edit(sender) {
+        return Err(ContractError::Unauthorized { node_id });
+    }
+    Ok(node)
 }
 
 #[entry_point]
 pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
-        QueryMsg::GetNode { node_id } => {
-            to_binary(&query_node(deps, node_id).map_err(|e| e.to_string())?)
+        QueryMsg::GetNode { node_id } => to_json_binary(&query_node(deps, node_id)?),
+        QueryMsg::ListNodes { start_after, limit } => {
+            to_json_binary(&list_nodes(deps, start_after, limit)?)
+        }
+        QueryMsg::NodesByLabel {
+            label,
+            start_after,
+            limit,
+        } => to_json_binary(&labels::query_nodes_by_label(
+            deps,
+            label,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::NodesWithLabels {
+            labels,
+            start_after,
+            limit,
+        } => to_json_binary(&labels::query_nodes_with_labels(
+            deps,
+            labels,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::LabelCounts { start_after, limit } => {
+            to_json_binary(&labels::query_label_counts(deps, start_after, limit)?)
+        }
+        QueryMsg::TopologicalOrder { start_after, limit } => {
+            to_json_binary(&dag::query_topological_order(deps, start_after, limit)?)
+        }
+        QueryMsg::GetSchema { kind } => to_json_binary(&schema::query_schema(deps, kind)?),
+        QueryMsg::GetNodeVersion { node_id, version } => {
+            to_json_binary(&history::query_node_version(deps, node_id, version)?)
+        }
+        QueryMsg::GetNodeAtHeight { node_id, height } => {
+            to_json_binary(&history::query_node_at_height(deps, node_id, height)?)
+        }
+        QueryMsg::GetNodeHistory {
+            node_id,
+            start_after,
+            limit,
+        } => to_json_binary(&history::query_node_history(
+            deps,
+            node_id,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::ExportGraph { start_after, limit } => {
+            to_json_binary(&export::export_graph(deps, start_after, limit)?)
         }
+        QueryMsg::GetNeighbors {
+            node_id,
+            direction,
+            start_after,
+            limit,
+        } => to_json_binary(&links::query_neighbors(
+            deps,
+            node_id,
+            direction,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::Traverse {
+            start,
+            direction,
+            max_depth,
+            iterations,
+            continuation,
+        } => to_json_binary(&traversal::query_traverse(
+            deps,
+            start,
+            direction,
+            max_depth,
+            iterations,
+            continuation,
+        )?),
+        QueryMsg::ShortestPath {
+            from,
+            to,
+            iterations,
+            continuation,
+        } => to_json_binary(&traversal::query_shortest_path(
+            deps,
+            from,
+            to,
+            iterations,
+            continuation,
+        )?),
+        QueryMsg::IsReachable {
+            from,
+            to,
+            iterations,
+            continuation,
+        } => to_json_binary(&traversal::query_reachable(
+            deps,
+            from,
+            to,
+            iterations,
+            continuation,
+        )?),
     }
 }
 
-fn query_node(deps: Deps, node_id: u64) -> StdResult<Node> {
-    let node = NODES
-        .load(deps.storage, node_id)
-        .map_err(|e| e.to_string())?;
-    Ok(node)
+fn query_node(deps: Deps, node_id: u64) -> StdResult<NodeResponse> {
+    let node = load_live(deps.storage, node_id)?;
+    node_response(deps, node)
+}
+
+fn node_response(deps: Deps, node: Node) -> StdResult<NodeResponse> {
+    let (links, more_links) = links::outgoing(deps, node.id, DEFAULT_LIMIT as usize)?;
+    Ok(NodeResponse {
+        labels: labels::of_node(deps.storage, node.id)?,
+        links,
+        more_links,
+        id: node.id,
+        kind: node.kind,
+        owner: node.owner,
+        editors: node.editors,
+        data: node.data,
+        version: node.version,
+    })
+}
+
+// Deleted nodes count against the limit, so a page may come up short of it
+fn list_nodes(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<NodesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let mut entries = NODES
+        .range(
+            deps.storage,
+            start_after.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit + 1)
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut next = None;
+    if entries.len() > limit {
+        entries.truncate(limit);
+        next = entries.last().map(|(node_id, _)| *node_id);
+    }
+    let nodes = entries
+        .into_iter()
+        .filter(|(_, node)| !node.is_deleted())
+        .map(|(_, node)| node_response(deps, node))
+        .collect::<StdResult<_>>()?;
+    Ok(NodesResponse { nodes, next })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ContractError;
+    use crate::hod::{execute, instantiate, query, DEFAULT_LIMIT};
+    use crate::msg::{ExecuteMsg, InstantiateMsg, NodeResponse, NodesResponse, QueryMsg};
+    use crate::state::{Acyclic, DuplicateLinks, NodeKind};
+    use cosmwasm_std::{
+        from_json,
+        testing::{message_info, mock_dependencies, mock_env},
+    };
+
+    #[test]
+    fn test_node_access_control() {
+        let mut deps = mock_dependencies();
+        let owner = message_info(&deps.api.addr_make("owner"), &[]);
+        let editor = message_info(&deps.api.addr_make("editor"), &[]);
+        let stranger = message_info(&deps.api.addr_make("stranger"), &[]);
+        let msg = InstantiateMsg {
+            admin: None,
+            allow_self_links: false,
+            duplicate_links: DuplicateLinks::Reject,
+            acyclic: Acyclic::Off,
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+
+        let create = ExecuteMsg::CreateNode {
+            kind: NodeKind::Concept,
+            data: "chokhmah".to_string(),
+            editors: vec![editor.sender.to_string()],
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), create).unwrap();
+        let create = ExecuteMsg::CreateNode {
+            kind: NodeKind::Concept,
+            data: "binah".to_string(),
+            editors: vec![],
+        };
+        execute(deps.as_mut(), mock_env(), stranger.clone(), create).unwrap();
+
+        let update = |data: &str| ExecuteMsg::UpdateNode {
+            node_id: 0,
+            data: data.to_string(),
+        };
+        let err =
+            execute(deps.as_mut(), mock_env(), stranger.clone(), update("da'at")).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { node_id: 0 }));
+        execute(deps.as_mut(), mock_env(), editor.clone(), update("keter")).unwrap();
+        let link = ExecuteMsg::CreateLink {
+            from_node_id: 0,
+            to_node_id: 1,
+            edge_type: "path".to_string(),
+            weight: 1,
+        };
+        let err = execute(deps.as_mut(), mock_env(), stranger.clone(), link.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { node_id: 0 }));
+        execute(deps.as_mut(), mock_env(), editor.clone(), link).unwrap();
+
+        // Editors cannot hand out editing rights, and once dropped they are strangers
+        let set_editors = ExecuteMsg::SetEditors {
+            node_id: 0,
+            editors: vec![],
+        };
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            editor.clone(),
+            set_editors.clone(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::NotOwner { node_id: 0 }));
+        execute(deps.as_mut(), mock_env(), owner.clone(), set_editors).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), editor, update("malkuth")).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { node_id: 0 }));
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            owner.clone(),
+            ExecuteMsg::UpdateNode {
+                node_id: 7,
+                data: String::new(),
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::NodeNotFound { node_id: 7 }));
+
+        let query_msg = QueryMsg::GetNode { node_id: 0 };
+        let res: NodeResponse =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(res.owner, owner.sender);
+        assert!(res.editors.is_empty());
+        assert_eq!(res.data, "keter");
+        assert_eq!(res.links, vec![1]);
+        assert!(!res.more_links);
+    }
+
+    #[test]
+    fn test_list_nodes() {
+        let mut deps = mock_dependencies();
+        let owner = message_info(&deps.api.addr_make("owner"), &[]);
+        let msg = InstantiateMsg {
+            admin: None,
+            allow_self_links: false,
+            duplicate_links: DuplicateLinks::Reject,
+            acyclic: Acyclic::Off,
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        let last = DEFAULT_LIMIT as u64 + 3;
+        for node_id in 0..=last {
+            let create = ExecuteMsg::CreateNode {
+                kind: NodeKind::Concept,
+                data: String::new(),
+                editors: vec![],
+            };
+            execute(deps.as_mut(), mock_env(), owner.clone(), create).unwrap();
+            if node_id > 0 {
+                let link = ExecuteMsg::CreateLink {
+                    from_node_id: 0,
+                    to_node_id: node_id,
+                    edge_type: "ray".to_string(),
+                    weight: 1,
+                };
+                execute(deps.as_mut(), mock_env(), owner.clone(), link).unwrap();
+            }
+        }
+        for node_id in [1, 2] {
+            let delete = ExecuteMsg::DeleteNode { node_id };
+            execute(deps.as_mut(), mock_env(), owner.clone(), delete).unwrap();
+        }
+
+        // Only the first page of links comes along
+        let query_msg = QueryMsg::GetNode { node_id: 0 };
+        let res: NodeResponse =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(res.links, (3..3 + DEFAULT_LIMIT as u64).collect::<Vec<_>>());
+        assert!(res.more_links);
+
+        // The deleted nodes use up the limit
+        let list = |start_after| -> (Vec<u64>, Option<u64>) {
+            let query_msg = QueryMsg::ListNodes {
+                start_after,
+                limit: Some(2),
+            };
+            let res: NodesResponse =
+                from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+            (
+                res.nodes.into_iter().map(|node| node.id).collect(),
+                res.next,
+            )
+        };
+        assert_eq!(list(None), (vec![0], Some(1)));
+        assert_eq!(list(Some(1)), (vec![3], Some(3)));
+        assert_eq!(list(Some(last - 1)), (vec![last], None));
+    }
 }
@@ -0,0 +1,369 @@
+use crate::error::ContractError;
+use crate::hod::{load_editable, DEFAULT_LIMIT, MAX_LIMIT};
+use crate::msg::{LabelCount, LabelCountsResponse, LabelNodesResponse};
+use crate::state::{node_labels, NodeLabel, LABEL_COUNTS};
+use cosmwasm_std::{Deps, DepsMut, MessageInfo, Order, Response, StdResult, Storage};
+use cw_storage_plus::Bound;
+
+const MAX_LABEL_LENGTH: usize = 64;
+// Keeps the labels a deletion takes along within one call
+pub(crate) const MAX_LABELS_PER_NODE: usize = 32;
+// Nodes of the rarest label one NodesWithLabels page looks at
+const MAX_SCANNED: usize = 100;
+
+// Labels the node already has are skipped
+pub fn add_labels(
+    deps: DepsMut,
+    info: MessageInfo,
+    node_id: u64,
+    labels: Vec<String>,
+) -> Result<Response, ContractError> {
+    load_editable(deps.as_ref(), &info.sender, node_id)?;
+    let mut count = node_labels()
+        .prefix(node_id)
+        .keys(deps.storage, None, None, Order::Ascending)
+        .take(MAX_LABELS_PER_NODE)
+        .count();
+    let mut added = 0;
+    for label in labels {
+        if label.is_empty() || label.len() > MAX_LABEL_LENGTH {
+            return Err(ContractError::InvalidLabel { label });
+        }
+        if node_labels().has(deps.storage, (node_id, label.clone())) {
+            continue;
+        }
+        if count >= MAX_LABELS_PER_NODE {
+            return Err(ContractError::TooManyLabels {
+                node_id,
+                max: MAX_LABELS_PER_NODE,
+            });
+        }
+        count += 1;
+        let nodes = LABEL_COUNTS.may_load(deps.storage, &label)?.unwrap_or(0);
+        LABEL_COUNTS.save(deps.storage, &label, &(nodes + 1))?;
+        let node_label = NodeLabel {
+            node_id,
+            label: label.clone(),
+        };
+        node_labels().save(deps.storage, (node_id, label), &node_label)?;
+        added += 1;
+    }
+    Ok(Response::new()
+        .add_attribute("action", "add_labels")
+        .add_attribute("node_id", node_id.to_string())
+        .add_attribute("added", added.to_string()))
+}
+
+// Labels the node does not have are skipped
+pub fn remove_labels(
+    deps: DepsMut,
+    info: MessageInfo,
+    node_id: u64,
+    labels: Vec<String>,
+) -> Result<Response, ContractError> {
+    load_editable(deps.as_ref(), &info.sender, node_id)?;
+    let mut removed = 0;
+    for label in labels {
+        if unlabel(deps.storage, node_id, label)? {
+            removed += 1;
+        }
+    }
+    Ok(Response::new()
+        .add_attribute("action", "remove_labels")
+        .add_attribute("node_id", node_id.to_string())
+        .add_attribute("removed", removed.to_string()))
+}
+
+fn unlabel(storage: &mut dyn Storage, node_id: u64, label: String) -> StdResult<bool> {
+    if !node_labels().has(storage, (node_id, label.clone())) {
+        return Ok(false);
+    }
+    node_labels().remove(storage, (node_id, label.clone()))?;
+    match LABEL_COUNTS.may_load(storage, &label)?.unwrap_or(0) {
+        0 | 1 => LABEL_COUNTS.remove(storage, &label),
+        count => LABEL_COUNTS.save(storage, &label, &(count - 1))?,
+    }
+    Ok(true)
+}
+
+// Deleted nodes no longer turn up in label searches
+pub(crate) fn remove_all(storage: &mut dyn Storage, node_id: u64) -> StdResult<()> {
+    for label in of_node(storage, node_id)? {
+        unlabel(storage, node_id, label)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn of_node(storage: &dyn Storage, node_id: u64) -> StdResult<Vec<String>> {
+    node_labels()
+        .prefix(node_id)
+        .keys(storage, None, None, Order::Ascending)
+        .collect()
+}
+
+pub fn query_nodes_by_label(
+    deps: Deps,
+    label: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<LabelNodesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|node_id| Bound::exclusive((node_id, label.clone())));
+    let mut node_ids: Vec<u64> = node_labels()
+        .idx
+        .label
+        .prefix(label)
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit + 1)
+        .map(|key| key.map(|(node_id, _)| node_id))
+        .collect::<StdResult<_>>()?;
+    let mut next = None;
+    if node_ids.len() > limit {
+        node_ids.truncate(limit);
+        next = node_ids.last().copied();
+    }
+    Ok(LabelNodesResponse { node_ids, next })
+}
+
+// Walks the rarest of the labels and checks the others node by node, looking at
+// no more than MAX_SCANNED of its nodes per page
+pub fn query_nodes_with_labels(
+    deps: Deps,
+    labels: Vec<String>,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<LabelNodesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let mut rarest: Option<(&String, u64)> = None;
+    for label in &labels {
+        let count = LABEL_COUNTS.may_load(deps.storage, label)?.unwrap_or(0);
+        if rarest.is_none_or(|(_, fewest)| count < fewest) {
+            rarest = Some((label, count));
+        }
+    }
+    let rarest = match rarest {
+        Some((label, _)) => label.clone(),
+        None => {
+            return Ok(LabelNodesResponse {
+                node_ids: vec![],
+                next: None,
+            })
+        }
+    };
+
+    let start = start_after.map(|node_id| Bound::exclusive((node_id, rarest.clone())));
+    let mut node_ids = vec![];
+    let mut last = None;
+    let mut next = None;
+    for (scanned, key) in node_labels()
+        .idx
+        .label
+        .prefix(rarest)
+        .keys(deps.storage, start, None, Order::Ascending)
+        .enumerate()
+    {
+        let (node_id, _) = key?;
+        // Only stop while some nodes are left, so next is None on the last page
+        if scanned == MAX_SCANNED || node_ids.len() == limit {
+            next = last;
+            break;
+        }
+        last = Some(node_id);
+        if labels
+            .iter()
+            .all(|label| node_labels().has(deps.storage, (node_id, label.clone())))
+        {
+            node_ids.push(node_id);
+        }
+    }
+    Ok(LabelNodesResponse { node_ids, next })
+}
+
+pub fn query_label_counts(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<LabelCountsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let labels = LABEL_COUNTS
+        .range(
+            deps.storage,
+            start_after.as_deref().map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| item.map(|(label, count)| LabelCount { label, count }))
+        .collect::<StdResult<_>>()?;
+    Ok(LabelCountsResponse { labels })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ContractError;
+    use crate::hod::{execute, instantiate, query};
+    use crate::labels::{MAX_LABELS_PER_NODE, MAX_SCANNED};
+    use crate::msg::{
+        ExecuteMsg, InstantiateMsg, LabelCountsResponse, LabelNodesResponse, NodeResponse, QueryMsg,
+    };
+    use crate::state::{Acyclic, DuplicateLinks, NodeKind};
+    use cosmwasm_std::{
+        from_json,
+        testing::{message_info, mock_dependencies, mock_env, MockApi, MockQuerier, MockStorage},
+        Deps, MessageInfo, OwnedDeps,
+    };
+
+    fn setup(nodes: usize) -> (OwnedDeps<MockStorage, MockApi, MockQuerier>, MessageInfo) {
+        let mut deps = mock_dependencies();
+        let owner = message_info(&deps.api.addr_make("owner"), &[]);
+        let msg = InstantiateMsg {
+            admin: None,
+            allow_self_links: false,
+            duplicate_links: DuplicateLinks::Reject,
+            acyclic: Acyclic::Off,
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        for _ in 0..nodes {
+            let create = ExecuteMsg::CreateNode {
+                kind: NodeKind::Concept,
+                data: String::new(),
+                editors: vec![],
+            };
+            execute(deps.as_mut(), mock_env(), owner.clone(), create).unwrap();
+        }
+        (deps, owner)
+    }
+
+    fn with_labels(deps: Deps, tags: &[&str], start_after: Option<u64>) -> LabelNodesResponse {
+        let query_msg = QueryMsg::NodesWithLabels {
+            labels: labels(tags),
+            start_after,
+            limit: None,
+        };
+        from_json(query(deps, mock_env(), query_msg).unwrap()).unwrap()
+    }
+
+    fn labels(labels: &[&str]) -> Vec<String> {
+        labels.iter().map(|label| label.to_string()).collect()
+    }
+
+    #[test]
+    fn test_labels() {
+        let (mut deps, owner) = setup(4);
+        let tag = |node_id, tags: &[&str]| ExecuteMsg::AddLabels {
+            node_id,
+            labels: labels(tags),
+        };
+        for (node_id, tags) in [
+            (0, &["sephirot", "pillar"][..]),
+            (1, &["sephirot"][..]),
+            (2, &["sephirot", "pillar", "sephirot"][..]),
+            (3, &["qliphoth"][..]),
+        ] {
+            execute(deps.as_mut(), mock_env(), owner.clone(), tag(node_id, tags)).unwrap();
+        }
+        let stranger = message_info(&deps.api.addr_make("stranger"), &[]);
+        let err = execute(deps.as_mut(), mock_env(), stranger, tag(3, &["pillar"])).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { node_id: 3 }));
+        let err = execute(deps.as_mut(), mock_env(), owner.clone(), tag(3, &[""])).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidLabel { .. }));
+        // Labels the node has already take no extra room
+        let many: Vec<String> = (1..MAX_LABELS_PER_NODE)
+            .map(|i| format!("gate {}", i))
+            .collect();
+        let fill = ExecuteMsg::AddLabels {
+            node_id: 3,
+            labels: [&many[..], &labels(&["qliphoth"])].concat(),
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), fill).unwrap();
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            owner.clone(),
+            tag(3, &["gate 0"]),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::TooManyLabels {
+                node_id: 3,
+                max: MAX_LABELS_PER_NODE
+            }
+        ));
+
+        let by_label = |deps: Deps, label: &str, start_after| -> Vec<u64> {
+            let query_msg = QueryMsg::NodesByLabel {
+                label: label.to_string(),
+                start_after,
+                limit: None,
+            };
+            let res: LabelNodesResponse =
+                from_json(query(deps, mock_env(), query_msg).unwrap()).unwrap();
+            res.node_ids
+        };
+        assert_eq!(by_label(deps.as_ref(), "sephirot", None), vec![0, 1, 2]);
+        assert_eq!(by_label(deps.as_ref(), "sephirot", Some(0)), vec![1, 2]);
+        let res = with_labels(deps.as_ref(), &["sephirot", "pillar"], None);
+        assert_eq!(res.node_ids, vec![0, 2]);
+        assert_eq!(res.next, None);
+        assert!(with_labels(deps.as_ref(), &["pillar", "qliphoth"], None)
+            .node_ids
+            .is_empty());
+        assert!(with_labels(deps.as_ref(), &[], None).node_ids.is_empty());
+
+        let remove = ExecuteMsg::RemoveLabels {
+            node_id: 0,
+            labels: labels(&["pillar", "unknown"]),
+        };
+        let res = execute(deps.as_mut(), mock_env(), owner.clone(), remove).unwrap();
+        assert_eq!(res.attributes[2].value, "1");
+        let delete = ExecuteMsg::DeleteNode { node_id: 3 };
+        execute(deps.as_mut(), mock_env(), owner, delete).unwrap();
+        let query_msg = QueryMsg::LabelCounts {
+            start_after: None,
+            limit: None,
+        };
+        let res: LabelCountsResponse =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        let counts: Vec<_> = res
+            .labels
+            .into_iter()
+            .map(|count| (count.label, count.count))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![("pillar".to_string(), 1), ("sephirot".to_string(), 3)]
+        );
+        let res: NodeResponse =
+            from_json(query(deps.as_ref(), mock_env(), QueryMsg::GetNode { node_id: 2 }).unwrap())
+                .unwrap();
+        assert_eq!(res.labels, labels(&["pillar", "sephirot"]));
+    }
+
+    #[test]
+    fn test_label_scan_cap() {
+        // The nodes of the rarest label only share the other one with the last of them
+        let scanned = MAX_SCANNED as u64;
+        let (mut deps, owner) = setup(2 * MAX_SCANNED + 2);
+        for node_id in 0..=scanned {
+            let add = ExecuteMsg::AddLabels {
+                node_id,
+                labels: labels(&["rare"]),
+            };
+            execute(deps.as_mut(), mock_env(), owner.clone(), add).unwrap();
+        }
+        for node_id in scanned..=2 * scanned + 1 {
+            let add = ExecuteMsg::AddLabels {
+                node_id,
+                labels: labels(&["common"]),
+            };
+            execute(deps.as_mut(), mock_env(), owner.clone(), add).unwrap();
+        }
+        let first = with_labels(deps.as_ref(), &["common", "rare"], None);
+        assert!(first.node_ids.is_empty());
+        assert_eq!(first.next, Some(scanned - 1));
+        let second = with_labels(deps.as_ref(), &["common", "rare"], first.next);
+        assert_eq!(second.node_ids, vec![scanned]);
+        assert_eq!(second.next, None);
+    }
+}
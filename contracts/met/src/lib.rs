@@ -1,3 +1,12 @@
+pub mod dag;
+pub mod error;
+pub mod export;
+pub mod history;
 pub mod hod;
+pub mod labels;
+pub mod links;
 pub mod msg;
+pub mod patch;
+pub mod schema;
 pub mod state;
+pub mod traversal;
@@ -0,0 +1,511 @@
+use crate::dag;
+use crate::error::ContractError;
+use crate::hod::{load_editable, load_node, DEFAULT_LIMIT, MAX_LIMIT};
+use crate::msg::{Direction, NeighborsResponse};
+use crate::state::{DuplicateLinks, Edge, CONFIG, EDGES, IN_DEGREES, IN_EDGES, OUT_DEGREES};
+use cosmwasm_std::{Addr, Deps, DepsMut, Empty, MessageInfo, Order, Response, StdResult, Storage};
+use cw_storage_plus::{Bound, Map};
+
+// Anyone who may edit a node can link it to any other, the owner of the node linked
+// to may remove such links again. Together the caps keep the links a deletion takes
+// along within one call
+pub(crate) const MAX_LINKS_IN: u32 = 500;
+pub(crate) const MAX_LINKS_OUT: u32 = 2000;
+
+// Links go out of the from node, so only its owner and editors may add them,
+// both ends must exist and the config decides on self and duplicate links
+pub fn create_link(
+    deps: DepsMut,
+    info: MessageInfo,
+    from_node_id: u64,
+    to_node_id: u64,
+    edge_type: String,
+    weight: u64,
+) -> Result<Response, ContractError> {
+    let edge = insert_link(
+        deps,
+        &info.sender,
+        from_node_id,
+        to_node_id,
+        edge_type,
+        weight,
+    )?;
+    Ok(Response::new()
+        .add_attribute("action", "create_link")
+        .add_attribute("from_node_id", from_node_id.to_string())
+        .add_attribute("to_node_id", to_node_id.to_string())
+        .add_attribute("edge_type", edge.edge_type))
+}
+
+pub(crate) fn insert_link(
+    deps: DepsMut,
+    sender: &Addr,
+    from_node_id: u64,
+    to_node_id: u64,
+    edge_type: String,
+    weight: u64,
+) -> Result<Edge, ContractError> {
+    load_editable(deps.as_ref(), sender, from_node_id)?;
+    load_node(deps.as_ref(), to_node_id)?;
+    let config = CONFIG.load(deps.storage)?;
+    if from_node_id == to_node_id && !config.allow_self_links {
+        return Err(ContractError::SelfLink {
+            node_id: from_node_id,
+        });
+    }
+    dag::check_settled(deps.storage)?;
+    if config.acyclic.covers(&edge_type) {
+        dag::check_acyclic(deps.storage, &config.acyclic, from_node_id, to_node_id)?;
+    }
+    if EDGES.has(deps.storage, (from_node_id, to_node_id)) {
+        if config.duplicate_links == DuplicateLinks::Reject {
+            return Err(ContractError::LinkExists {
+                from_node_id,
+                to_node_id,
+            });
+        }
+    } else {
+        let out_degree = OUT_DEGREES
+            .may_load(deps.storage, from_node_id)?
+            .unwrap_or_default();
+        if out_degree >= MAX_LINKS_OUT {
+            return Err(ContractError::TooManyLinksOut {
+                node_id: from_node_id,
+                max: MAX_LINKS_OUT,
+            });
+        }
+        let in_degree = IN_DEGREES
+            .may_load(deps.storage, to_node_id)?
+            .unwrap_or_default();
+        if in_degree >= MAX_LINKS_IN {
+            return Err(ContractError::TooManyLinksIn {
+                node_id: to_node_id,
+                max: MAX_LINKS_IN,
+            });
+        }
+        OUT_DEGREES.save(deps.storage, from_node_id, &(out_degree + 1))?;
+        IN_DEGREES.save(deps.storage, to_node_id, &(in_degree + 1))?;
+    }
+
+    let edge = Edge {
+        from: from_node_id,
+        to: to_node_id,
+        edge_type,
+        weight,
+    };
+    EDGES.save(deps.storage, (from_node_id, to_node_id), &edge)?;
+    IN_EDGES.save(deps.storage, (to_node_id, from_node_id), &Empty {})?;
+    Ok(edge)
+}
+
+pub fn remove_link(
+    deps: DepsMut,
+    info: MessageInfo,
+    from_node_id: u64,
+    to_node_id: u64,
+) -> Result<Response, ContractError> {
+    delete_link(deps, &info.sender, from_node_id, to_node_id)?;
+    Ok(Response::new()
+        .add_attribute("action", "remove_link")
+        .add_attribute("from_node_id", from_node_id.to_string())
+        .add_attribute("to_node_id", to_node_id.to_string()))
+}
+
+// The owner and editors of the from node may remove a link, as may the owner of the
+// to node
+pub(crate) fn delete_link(
+    deps: DepsMut,
+    sender: &Addr,
+    from_node_id: u64,
+    to_node_id: u64,
+) -> Result<(), ContractError> {
+    let owns_target = load_node(deps.as_ref(), to_node_id).is_ok_and(|node| node.owner == *sender);
+    if !owns_target {
+        load_editable(deps.as_ref(), sender, from_node_id)?;
+    }
+    dag::check_settled(deps.storage)?;
+    if !EDGES.has(deps.storage, (from_node_id, to_node_id)) {
+        return Err(ContractError::LinkNotFound {
+            from_node_id,
+            to_node_id,
+        });
+    }
+    EDGES.remove(deps.storage, (from_node_id, to_node_id));
+    IN_EDGES.remove(deps.storage, (to_node_id, from_node_id));
+    drop_degree(deps.storage, OUT_DEGREES, from_node_id)?;
+    drop_degree(deps.storage, IN_DEGREES, to_node_id)?;
+    Ok(())
+}
+
+fn drop_degree(storage: &mut dyn Storage, degrees: Map<u64, u32>, node_id: u64) -> StdResult<()> {
+    let degree = degrees
+        .may_load(storage, node_id)?
+        .unwrap_or_default()
+        .saturating_sub(1);
+    if degree == 0 {
+        degrees.remove(storage, node_id);
+    } else {
+        degrees.save(storage, node_id, &degree)?;
+    }
+    Ok(())
+}
+
+// Drops every link into and out of the node, returns how many there were. At most
+// MAX_LINKS_OUT go out and MAX_LINKS_IN come in
+pub(crate) fn remove_all(storage: &mut dyn Storage, node_id: u64) -> StdResult<usize> {
+    let targets = EDGES
+        .prefix(node_id)
+        .keys(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let sources = IN_EDGES
+        .prefix(node_id)
+        .keys(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for to in &targets {
+        EDGES.remove(storage, (node_id, *to));
+        IN_EDGES.remove(storage, (*to, node_id));
+        drop_degree(storage, IN_DEGREES, *to)?;
+    }
+    for from in &sources {
+        EDGES.remove(storage, (*from, node_id));
+        IN_EDGES.remove(storage, (node_id, *from));
+        drop_degree(storage, OUT_DEGREES, *from)?;
+    }
+    IN_DEGREES.remove(storage, node_id);
+    OUT_DEGREES.remove(storage, node_id);
+    // A self link shows up on both sides
+    let self_linked = targets.contains(&node_id) as usize;
+    Ok(targets.len() + sources.len() - self_linked)
+}
+
+// The first limit nodes the node links to, and whether it links to more
+pub fn outgoing(deps: Deps, node_id: u64, limit: usize) -> StdResult<(Vec<u64>, bool)> {
+    let mut targets = EDGES
+        .prefix(node_id)
+        .keys(deps.storage, None, None, Order::Ascending)
+        .take(limit + 1)
+        .collect::<StdResult<Vec<_>>>()?;
+    let more = targets.len() > limit;
+    targets.truncate(limit);
+    Ok((targets, more))
+}
+
+// Pages by the id of the node at the other end
+pub fn query_neighbors(
+    deps: Deps,
+    node_id: u64,
+    direction: Direction,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<NeighborsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+    let edges = match direction {
+        Direction::Outgoing => EDGES
+            .prefix(node_id)
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|item| item.map(|(_, edge)| edge))
+            .collect::<StdResult<_>>()?,
+        Direction::Incoming => IN_EDGES
+            .prefix(node_id)
+            .keys(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|from| EDGES.load(deps.storage, (from?, node_id)))
+            .collect::<StdResult<_>>()?,
+    };
+    Ok(NeighborsResponse { node_id, edges })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ContractError;
+    use crate::hod::{execute, instantiate, query};
+    use crate::msg::{
+        Direction, ExecuteMsg, InstantiateMsg, NeighborsResponse, NodeResponse, QueryMsg,
+    };
+    use crate::state::{Acyclic, DuplicateLinks, NodeKind};
+    use cosmwasm_std::{
+        from_json,
+        testing::{message_info, mock_dependencies, mock_env},
+        Deps,
+    };
+
+    #[test]
+    fn test_typed_links() {
+        let mut deps = mock_dependencies();
+        let owner = message_info(&deps.api.addr_make("owner"), &[]);
+        let msg = InstantiateMsg {
+            admin: None,
+            allow_self_links: false,
+            duplicate_links: DuplicateLinks::Replace,
+            acyclic: Acyclic::Off,
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        for data in ["keter", "chokhmah", "binah"] {
+            let create = ExecuteMsg::CreateNode {
+                kind: NodeKind::Concept,
+                data: data.to_string(),
+                editors: vec![],
+            };
+            execute(deps.as_mut(), mock_env(), owner.clone(), create).unwrap();
+        }
+        let link = |from_node_id, to_node_id, weight| ExecuteMsg::CreateLink {
+            from_node_id,
+            to_node_id,
+            edge_type: "path".to_string(),
+            weight,
+        };
+        for (from, to) in [(0, 1), (0, 2), (2, 1)] {
+            execute(deps.as_mut(), mock_env(), owner.clone(), link(from, to, 1)).unwrap();
+        }
+        // Linking again replaces the weight
+        execute(deps.as_mut(), mock_env(), owner.clone(), link(0, 1, 7)).unwrap();
+
+        let neighbors = |deps: Deps, node_id, direction, start_after| -> Vec<(u64, u64, u64)> {
+            let query_msg = QueryMsg::GetNeighbors {
+                node_id,
+                direction,
+                start_after,
+                limit: None,
+            };
+            let res: NeighborsResponse =
+                from_json(query(deps, mock_env(), query_msg).unwrap()).unwrap();
+            res.edges
+                .into_iter()
+                .map(|edge| (edge.from, edge.to, edge.weight))
+                .collect()
+        };
+        assert_eq!(
+            neighbors(deps.as_ref(), 0, Direction::Outgoing, None),
+            vec![(0, 1, 7), (0, 2, 1)]
+        );
+        assert_eq!(
+            neighbors(deps.as_ref(), 1, Direction::Incoming, None),
+            vec![(0, 1, 7), (2, 1, 1)]
+        );
+        assert_eq!(
+            neighbors(deps.as_ref(), 1, Direction::Incoming, Some(0)),
+            vec![(2, 1, 1)]
+        );
+
+        let remove = ExecuteMsg::RemoveLink {
+            from_node_id: 0,
+            to_node_id: 1,
+        };
+        let stranger = message_info(&deps.api.addr_make("stranger"), &[]);
+        let err = execute(deps.as_mut(), mock_env(), stranger, remove.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { node_id: 0 }));
+        execute(deps.as_mut(), mock_env(), owner.clone(), remove.clone()).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), owner, remove).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::LinkNotFound {
+                from_node_id: 0,
+                to_node_id: 1
+            }
+        ));
+        assert_eq!(
+            neighbors(deps.as_ref(), 1, Direction::Incoming, None),
+            vec![(2, 1, 1)]
+        );
+        let res: NodeResponse =
+            from_json(query(deps.as_ref(), mock_env(), QueryMsg::GetNode { node_id: 0 }).unwrap())
+                .unwrap();
+        assert_eq!(res.links, vec![2]);
+    }
+
+    #[test]
+    fn test_link_integrity() {
+        let mut deps = mock_dependencies();
+        let owner = message_info(&deps.api.addr_make("owner"), &[]);
+        let other = message_info(&deps.api.addr_make("other"), &[]);
+        let msg = InstantiateMsg {
+            admin: None,
+            allow_self_links: false,
+            duplicate_links: DuplicateLinks::Reject,
+            acyclic: Acyclic::Off,
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        for sender in [&owner, &other] {
+            let create = ExecuteMsg::CreateNode {
+                kind: NodeKind::Concept,
+                data: String::new(),
+                editors: vec![],
+            };
+            execute(deps.as_mut(), mock_env(), sender.clone(), create).unwrap();
+        }
+        let link = |from_node_id, to_node_id| ExecuteMsg::CreateLink {
+            from_node_id,
+            to_node_id,
+            edge_type: "path".to_string(),
+            weight: 1,
+        };
+
+        let err = execute(deps.as_mut(), mock_env(), owner.clone(), link(0, 9)).unwrap_err();
+        assert!(matches!(err, ContractError::NodeNotFound { node_id: 9 }));
+        let err = execute(deps.as_mut(), mock_env(), owner.clone(), link(0, 0)).unwrap_err();
+        assert!(matches!(err, ContractError::SelfLink { node_id: 0 }));
+        execute(deps.as_mut(), mock_env(), owner.clone(), link(0, 1)).unwrap();
+        let err = execute(deps.as_mut(), mock_env(), owner.clone(), link(0, 1)).unwrap_err();
+        assert!(matches!(err, ContractError::LinkExists { .. }));
+        execute(deps.as_mut(), mock_env(), other.clone(), link(1, 0)).unwrap();
+
+        // Deleting a node takes the links of other owners into it along
+        let delete = ExecuteMsg::DeleteNode { node_id: 0 };
+        let err = execute(deps.as_mut(), mock_env(), other.clone(), delete.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NotOwner { node_id: 0 }));
+        let res = execute(deps.as_mut(), mock_env(), owner.clone(), delete).unwrap();
+        assert_eq!(res.attributes[2].value, "2");
+        let query_msg = QueryMsg::GetNeighbors {
+            node_id: 1,
+            direction: Direction::Incoming,
+            start_after: None,
+            limit: None,
+        };
+        let res: NeighborsResponse =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert!(res.edges.is_empty());
+        let res: NodeResponse =
+            from_json(query(deps.as_ref(), mock_env(), QueryMsg::GetNode { node_id: 1 }).unwrap())
+                .unwrap();
+        assert!(res.links.is_empty());
+        assert!(query(deps.as_ref(), mock_env(), QueryMsg::GetNode { node_id: 0 }).is_err());
+        let err = execute(deps.as_mut(), mock_env(), other, link(1, 0)).unwrap_err();
+        assert!(matches!(err, ContractError::NodeNotFound { node_id: 0 }));
+    }
+
+    #[test]
+    fn test_links_in_cap() {
+        use crate::links::MAX_LINKS_IN;
+        let max = MAX_LINKS_IN as u64;
+        let mut deps = mock_dependencies();
+        let owner = message_info(&deps.api.addr_make("owner"), &[]);
+        let other = message_info(&deps.api.addr_make("other"), &[]);
+        let msg = InstantiateMsg {
+            admin: None,
+            allow_self_links: false,
+            duplicate_links: DuplicateLinks::Replace,
+            acyclic: Acyclic::Off,
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        for node_id in 0..=max + 1 {
+            let sender = if node_id == 0 { &owner } else { &other };
+            let create = ExecuteMsg::CreateNode {
+                kind: NodeKind::Concept,
+                data: String::new(),
+                editors: vec![],
+            };
+            execute(deps.as_mut(), mock_env(), sender.clone(), create).unwrap();
+        }
+        let link = |from_node_id, weight| ExecuteMsg::CreateLink {
+            from_node_id,
+            to_node_id: 0,
+            edge_type: "path".to_string(),
+            weight,
+        };
+        for from in 1..=max {
+            execute(deps.as_mut(), mock_env(), other.clone(), link(from, 1)).unwrap();
+        }
+        let err = execute(deps.as_mut(), mock_env(), other.clone(), link(max + 1, 1)).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::TooManyLinksIn {
+                node_id: 0,
+                max: MAX_LINKS_IN
+            }
+        ));
+        // Replacing a link keeps the count
+        execute(deps.as_mut(), mock_env(), other.clone(), link(1, 2)).unwrap();
+        // The owner of the node linked to frees room, strangers cannot
+        let remove = ExecuteMsg::RemoveLink {
+            from_node_id: 1,
+            to_node_id: 0,
+        };
+        let stranger = message_info(&deps.api.addr_make("stranger"), &[]);
+        let err = execute(deps.as_mut(), mock_env(), stranger, remove.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { node_id: 1 }));
+        execute(deps.as_mut(), mock_env(), owner.clone(), remove).unwrap();
+        execute(deps.as_mut(), mock_env(), other.clone(), link(max + 1, 1)).unwrap();
+
+        // The owner can still delete the node with all its links in
+        let out = ExecuteMsg::CreateLink {
+            from_node_id: 0,
+            to_node_id: 1,
+            edge_type: "path".to_string(),
+            weight: 1,
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), out).unwrap();
+        let delete = ExecuteMsg::DeleteNode { node_id: 0 };
+        let res = execute(deps.as_mut(), mock_env(), owner, delete).unwrap();
+        assert_eq!(res.attributes[2].value, (max + 1).to_string());
+        let back = ExecuteMsg::CreateLink {
+            from_node_id: 2,
+            to_node_id: 1,
+            edge_type: "path".to_string(),
+            weight: 1,
+        };
+        execute(deps.as_mut(), mock_env(), other, back).unwrap();
+        let query_msg = QueryMsg::GetNeighbors {
+            node_id: 1,
+            direction: Direction::Incoming,
+            start_after: None,
+            limit: None,
+        };
+        let res: NeighborsResponse =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(res.edges.len(), 1);
+    }
+
+    #[test]
+    fn test_links_out_cap() {
+        use crate::links::MAX_LINKS_OUT;
+        let max = MAX_LINKS_OUT as u64;
+        let mut deps = mock_dependencies();
+        let owner = message_info(&deps.api.addr_make("owner"), &[]);
+        let msg = InstantiateMsg {
+            admin: None,
+            allow_self_links: false,
+            duplicate_links: DuplicateLinks::Replace,
+            acyclic: Acyclic::Off,
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        for _ in 0..=max + 1 {
+            let create = ExecuteMsg::CreateNode {
+                kind: NodeKind::Concept,
+                data: String::new(),
+                editors: vec![],
+            };
+            execute(deps.as_mut(), mock_env(), owner.clone(), create).unwrap();
+        }
+        let link = |to_node_id, weight| ExecuteMsg::CreateLink {
+            from_node_id: 0,
+            to_node_id,
+            edge_type: "path".to_string(),
+            weight,
+        };
+        for to in 1..=max {
+            execute(deps.as_mut(), mock_env(), owner.clone(), link(to, 1)).unwrap();
+        }
+        let err = execute(deps.as_mut(), mock_env(), owner.clone(), link(max + 1, 1)).unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::TooManyLinksOut {
+                node_id: 0,
+                max: MAX_LINKS_OUT
+            }
+        ));
+        // Replacing a link keeps the count, removing one frees room
+        execute(deps.as_mut(), mock_env(), owner.clone(), link(1, 2)).unwrap();
+        let remove = ExecuteMsg::RemoveLink {
+            from_node_id: 0,
+            to_node_id: 1,
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), remove).unwrap();
+        execute(deps.as_mut(), mock_env(), owner.clone(), link(max + 1, 1)).unwrap();
+
+        let delete = ExecuteMsg::DeleteNode { node_id: 0 };
+        let res = execute(deps.as_mut(), mock_env(), owner, delete).unwrap();
+        assert_eq!(res.attributes[2].value, max.to_string());
+    }
+}
@@ -1,25 +1,373 @@
-use cosmwasm_std::Binary;
+use cosmwasm_std::{Addr, Binary};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::state::{Acyclic, DuplicateLinks, Edge, KindSchema, NodeKind, NodeVersion};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-pub struct InstantiateMsg {}
+pub struct InstantiateMsg {
+    // Defaults to the instantiating address
+    #[serde(default)]
+    pub admin: Option<String>,
+    #[serde(default)]
+    pub allow_self_links: bool,
+    // Defaults to rejecting a second link between the same nodes
+    #[serde(default)]
+    pub duplicate_links: DuplicateLinks,
+    // Defaults to allowing cycles, the admin may change it with UpdateAcyclic
+    #[serde(default)]
+    pub acyclic: Acyclic,
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub enum ExecuteMsg {
-    CreateNode { data: String },
-    UpdateNode { node_id: u64, data: String },
-    CreateLink { from_node_id: u64, to_node_id: u64 },
+    // The sender owns the new node, the data has to match the schema of its kind
+    CreateNode {
+        #[serde(default)]
+        kind: NodeKind,
+        data: String,
+        #[serde(default)]
+        editors: Vec<String>,
+    },
+    UpdateNode {
+        node_id: u64,
+        data: String,
+    },
+    // Replaces the editor list, owner only
+    SetEditors {
+        node_id: u64,
+        editors: Vec<String>,
+    },
+    CreateLink {
+        from_node_id: u64,
+        to_node_id: u64,
+        edge_type: String,
+        weight: u64,
+    },
+    RemoveLink {
+        from_node_id: u64,
+        to_node_id: u64,
+    },
+    // Applies everything or nothing, see GraphPatch for the order
+    ApplyGraphPatch {
+        patch: GraphPatch,
+    },
+    // Owner and editors, for finding nodes by topic
+    AddLabels {
+        node_id: u64,
+        labels: Vec<String>,
+    },
+    RemoveLabels {
+        node_id: u64,
+        labels: Vec<String>,
+    },
+    // Admin only, replaces the kind's schema, existing nodes are checked
+    // against it on their next update
+    RegisterSchema {
+        kind: NodeKind,
+        schema: KindSchema,
+    },
+    // Admin only. Turning the setting on ranks the graph anew over as many
+    // ContinueAcyclicUpdate calls as it takes, links and nodes cannot be removed
+    // and links not created meanwhile. Should the links the new setting covers
+    // form a cycle, the previous setting is put back
+    UpdateAcyclic {
+        acyclic: Acyclic,
+    },
+    // Anyone may carry a pending acyclic update on
+    ContinueAcyclicUpdate {},
+    // Owner only, removes every link into and out of the node and hides it
+    // everywhere but in the version queries
+    DeleteNode {
+        node_id: u64,
+    },
+}
+
+// Nodes are created first, then updated, then links removed and finally created,
+// so a link can be retyped by removing and adding it in the same patch
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GraphPatch {
+    #[serde(default)]
+    pub create_nodes: Vec<PatchNode>,
+    #[serde(default)]
+    pub update_nodes: Vec<PatchUpdate>,
+    #[serde(default)]
+    pub remove_links: Vec<PatchUnlink>,
+    #[serde(default)]
+    pub create_links: Vec<PatchLink>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PatchNode {
+    pub temp_id: String, // Only meaningful within the patch
+    #[serde(default)]
+    pub kind: NodeKind,
+    pub data: String,
+    #[serde(default)]
+    pub editors: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PatchUpdate {
+    pub node_id: u64,
+    pub data: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PatchUnlink {
+    pub from_node_id: u64,
+    pub to_node_id: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PatchLink {
+    pub from: NodeRef,
+    pub to: NodeRef,
+    pub edge_type: String,
+    pub weight: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum NodeRef {
+    Id(u64),
+    Temp(String),
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub enum QueryMsg {
-    GetNode { node_id: u64 },
+    GetNode {
+        node_id: u64,
+    },
+    // Deleted nodes count against the limit, page on with next
+    ListNodes {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    NodesByLabel {
+        label: String,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    // Nodes carrying every one of the labels, a page may come back short or even
+    // empty while next is set
+    NodesWithLabels {
+        labels: Vec<String>,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    // Nodes per label, in label order
+    LabelCounts {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    GetSchema {
+        kind: NodeKind,
+    },
+    // Pages the live nodes in an order along the links the acyclic setting
+    // covers, only while the setting is on and no update is pending
+    TopologicalOrder {
+        start_after: Option<u64>, // Node id
+        limit: Option<u32>,
+    },
+    GetNodeVersion {
+        node_id: u64,
+        version: u32,
+    },
+    // The latest version written at or before the height
+    GetNodeAtHeight {
+        node_id: u64,
+        height: u64,
+    },
+    GetNodeHistory {
+        node_id: u64,
+        start_after: Option<u32>,
+        limit: Option<u32>,
+    },
+    // A page of nodes with their outgoing links in JSON Graph Format
+    // limit caps the nodes and links a page lists and the deleted nodes it skips
+    ExportGraph {
+        start_after: Option<ExportCursor>,
+        limit: Option<u32>,
+    },
+    GetNeighbors {
+        node_id: u64,
+        direction: Direction,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    // The traversals below look at no more than iterations links per query and hand
+    // back a continuation to pass in unchanged with the same query for the next page
+    Traverse {
+        start: u64,
+        direction: Direction,
+        max_depth: u32,
+        iterations: Option<u32>,
+        continuation: Option<Binary>,
+    },
+    ShortestPath {
+        from: u64,
+        to: u64,
+        iterations: Option<u32>,
+        continuation: Option<Binary>,
+    },
+    IsReachable {
+        from: u64,
+        to: u64,
+        iterations: Option<u32>,
+        continuation: Option<Binary>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+pub enum Direction {
+    Outgoing,
+    Incoming,
+}
+
+// Set as the data of the ApplyGraphPatch response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GraphPatchResponse {
+    pub created: BTreeMap<String, u64>, // Temporary id to node id
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct NodeResponse {
     pub id: u64,
+    pub kind: NodeKind,
+    pub owner: Addr,
+    pub editors: Vec<Addr>,
     pub data: String,
-    pub links: Vec<u64>,
-}
\ No newline at end of file
+    pub version: u32,
+    pub labels: Vec<String>,
+    pub links: Vec<u64>, // The first page of outgoing links, GetNeighbors has the rest
+    pub more_links: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NodesResponse {
+    pub nodes: Vec<NodeResponse>,
+    pub next: Option<u64>, // start_after of the next page, None once the listing is done
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LabelNodesResponse {
+    pub node_ids: Vec<u64>,
+    pub next: Option<u64>, // start_after of the next page, None once the search is done
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LabelCountsResponse {
+    pub labels: Vec<LabelCount>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LabelCount {
+    pub label: String,
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TopologicalOrderResponse {
+    pub order: Vec<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SchemaResponse {
+    pub kind: NodeKind,
+    pub schema: Option<KindSchema>, // None while the kind takes free-form data
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NodeVersionResponse {
+    pub node_id: u64,
+    pub version: NodeVersion,
+    pub deleted: bool, // Whether the node is deleted by now
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NodeHistoryResponse {
+    pub node_id: u64,
+    pub deleted_at: Option<u64>,
+    pub versions: Vec<NodeVersion>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NeighborsResponse {
+    pub node_id: u64,
+    pub edges: Vec<Edge>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Reached {
+    pub node_id: u64,
+    pub depth: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TraverseResponse {
+    pub nodes: Vec<Reached>,
+    pub continuation: Option<Binary>, // None once the traversal is complete
+    pub truncated: bool,              // Stopped early, the search outgrew its node cap
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ShortestPathResponse {
+    pub path: Option<Vec<u64>>, // From first to last node
+    pub continuation: Option<Binary>,
+    pub truncated: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReachableResponse {
+    pub reachable: bool, // false is only final without a continuation or truncation
+    pub continuation: Option<Binary>,
+    pub truncated: bool,
+}
+
+// https://jsongraphformat.info version 1, node ids are the decimal met ids
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GraphExportResponse {
+    pub graph: JsonGraph,
+    pub next: Option<ExportCursor>, // start_after of the next page, None on the last one
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+pub struct ExportCursor {
+    pub node_id: u64, // Last node looked at
+    // Target of its first link still to list, None once they are all listed
+    pub links_from: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct JsonGraph {
+    pub directed: bool,
+    pub nodes: Vec<JsonGraphNode>,
+    pub edges: Vec<JsonGraphEdge>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct JsonGraphNode {
+    pub id: String,
+    pub label: String, // The node data
+    pub metadata: JsonGraphNodeMetadata,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct JsonGraphNodeMetadata {
+    pub kind: NodeKind,
+    pub owner: Addr,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct JsonGraphEdge {
+    pub source: String,
+    pub target: String,
+    pub relation: String, // The edge type
+    pub metadata: JsonGraphEdgeMetadata,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct JsonGraphEdgeMetadata {
+    pub weight: u64,
+}
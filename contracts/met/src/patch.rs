@@ -0,0 +1,271 @@
+use crate::error::ContractError;
+use crate::hod::{insert_node, write_node};
+use crate::links::{delete_link, insert_link};
+use crate::msg::{GraphPatch, GraphPatchResponse, NodeRef};
+use cosmwasm_std::{to_json_binary, DepsMut, Env, MessageInfo, Response};
+use std::collections::{BTreeMap, BTreeSet};
+
+// Nodes and links one patch may create, update or remove in total
+const MAX_PATCH_SIZE: usize = 100;
+
+// Every step runs with the sender's rights. Malformed patches are turned away
+// before anything is written, a step failing later on reverts the whole patch
+// along with the transaction
+pub fn apply_graph_patch(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    patch: GraphPatch,
+) -> Result<Response, ContractError> {
+    check_patch(&patch)?;
+    let mut created = BTreeMap::new();
+    for new_node in patch.create_nodes {
+        let node = insert_node(
+            deps.branch(),
+            &env,
+            &info.sender,
+            new_node.kind,
+            new_node.data,
+            new_node.editors,
+        )?;
+        created.insert(new_node.temp_id, node.id);
+    }
+    let updated = patch.update_nodes.len();
+    for update in patch.update_nodes {
+        write_node(
+            deps.branch(),
+            &env,
+            &info.sender,
+            update.node_id,
+            update.data,
+        )?;
+    }
+    let unlinked = patch.remove_links.len();
+    for unlink in patch.remove_links {
+        delete_link(
+            deps.branch(),
+            &info.sender,
+            unlink.from_node_id,
+            unlink.to_node_id,
+        )?;
+    }
+    let linked = patch.create_links.len();
+    for link in patch.create_links {
+        let from_node_id = resolve(&created, link.from)?;
+        let to_node_id = resolve(&created, link.to)?;
+        insert_link(
+            deps.branch(),
+            &info.sender,
+            from_node_id,
+            to_node_id,
+            link.edge_type,
+            link.weight,
+        )?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "apply_graph_patch")
+        .add_attribute("created_nodes", created.len().to_string())
+        .add_attribute("updated_nodes", updated.to_string())
+        .add_attribute("removed_links", unlinked.to_string())
+        .add_attribute("created_links", linked.to_string())
+        .set_data(to_json_binary(&GraphPatchResponse { created })?))
+}
+
+fn check_patch(patch: &GraphPatch) -> Result<(), ContractError> {
+    let size = patch.create_nodes.len()
+        + patch.update_nodes.len()
+        + patch.remove_links.len()
+        + patch.create_links.len();
+    if size > MAX_PATCH_SIZE {
+        return Err(ContractError::PatchTooLarge {
+            size,
+            max: MAX_PATCH_SIZE,
+        });
+    }
+    let mut temp_ids = BTreeSet::new();
+    for new_node in &patch.create_nodes {
+        if !temp_ids.insert(&new_node.temp_id) {
+            return Err(ContractError::DuplicateTempId {
+                temp_id: new_node.temp_id.clone(),
+            });
+        }
+    }
+    for link in &patch.create_links {
+        for node in [&link.from, &link.to] {
+            match node {
+                NodeRef::Temp(temp_id) if !temp_ids.contains(temp_id) => {
+                    return Err(ContractError::UnknownTempId {
+                        temp_id: temp_id.clone(),
+                    })
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+fn resolve(created: &BTreeMap<String, u64>, node: NodeRef) -> Result<u64, ContractError> {
+    match node {
+        NodeRef::Id(node_id) => Ok(node_id),
+        NodeRef::Temp(temp_id) => created
+            .get(&temp_id)
+            .copied()
+            .ok_or(ContractError::UnknownTempId { temp_id }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ContractError;
+    use crate::hod::{execute, instantiate, query};
+    use crate::msg::{
+        ExecuteMsg, GraphPatch, GraphPatchResponse, InstantiateMsg, NodeRef, NodeResponse,
+        NodesResponse, PatchLink, PatchNode, PatchUnlink, PatchUpdate, QueryMsg,
+    };
+    use crate::patch::MAX_PATCH_SIZE;
+    use crate::state::{Acyclic, DuplicateLinks, NodeKind};
+    use cosmwasm_std::{
+        from_json,
+        testing::{message_info, mock_dependencies, mock_env},
+        Deps,
+    };
+
+    fn new_node(temp_id: &str) -> PatchNode {
+        PatchNode {
+            temp_id: temp_id.to_string(),
+            kind: NodeKind::Concept,
+            data: temp_id.to_string(),
+            editors: vec![],
+        }
+    }
+
+    fn link(from: NodeRef, to: NodeRef, edge_type: &str) -> PatchLink {
+        PatchLink {
+            from,
+            to,
+            edge_type: edge_type.to_string(),
+            weight: 1,
+        }
+    }
+
+    #[test]
+    fn test_graph_patch() {
+        let mut deps = mock_dependencies();
+        let owner = message_info(&deps.api.addr_make("owner"), &[]);
+        let msg = InstantiateMsg {
+            admin: None,
+            allow_self_links: false,
+            duplicate_links: DuplicateLinks::Reject,
+            acyclic: Acyclic::Off,
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        let create = ExecuteMsg::CreateNode {
+            kind: NodeKind::Concept,
+            data: "keter".to_string(),
+            editors: vec![],
+        };
+        execute(deps.as_mut(), mock_env(), owner.clone(), create).unwrap();
+
+        let patch = GraphPatch {
+            create_nodes: vec![new_node("premise"), new_node("conclusion")],
+            update_nodes: vec![PatchUpdate {
+                node_id: 0,
+                data: "question".to_string(),
+            }],
+            remove_links: vec![],
+            create_links: vec![
+                link(NodeRef::Id(0), NodeRef::Temp("premise".to_string()), "asks"),
+                link(
+                    NodeRef::Temp("premise".to_string()),
+                    NodeRef::Temp("conclusion".to_string()),
+                    "implies",
+                ),
+            ],
+        };
+        let msg = ExecuteMsg::ApplyGraphPatch { patch };
+        let res = execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        let res: GraphPatchResponse = from_json(res.data.unwrap()).unwrap();
+        assert_eq!(res.created["premise"], 1);
+        assert_eq!(res.created["conclusion"], 2);
+
+        let node = |deps: Deps, node_id| -> NodeResponse {
+            let query_msg = QueryMsg::GetNode { node_id };
+            from_json(query(deps, mock_env(), query_msg).unwrap()).unwrap()
+        };
+        let question = node(deps.as_ref(), 0);
+        assert_eq!(question.data, "question");
+        assert_eq!(question.links, vec![1]);
+        assert_eq!(node(deps.as_ref(), 1).links, vec![2]);
+
+        // Retyping a link takes a removal and a creation
+        let patch = GraphPatch {
+            create_nodes: vec![],
+            update_nodes: vec![],
+            remove_links: vec![PatchUnlink {
+                from_node_id: 1,
+                to_node_id: 2,
+            }],
+            create_links: vec![link(NodeRef::Id(1), NodeRef::Id(2), "refutes")],
+        };
+        let msg = ExecuteMsg::ApplyGraphPatch { patch };
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+
+        // Malformed patches write nothing, not even the steps in front of the bad one
+        let node_count = |deps: Deps| {
+            let query_msg = QueryMsg::ListNodes {
+                start_after: None,
+                limit: None,
+            };
+            let res: NodesResponse =
+                from_json(query(deps, mock_env(), query_msg).unwrap()).unwrap();
+            res.nodes.len()
+        };
+        let failing = |patch| ExecuteMsg::ApplyGraphPatch { patch };
+        let patch = GraphPatch {
+            create_nodes: (0..=MAX_PATCH_SIZE)
+                .map(|i| new_node(&i.to_string()))
+                .collect(),
+            update_nodes: vec![],
+            remove_links: vec![],
+            create_links: vec![],
+        };
+        let err = execute(deps.as_mut(), mock_env(), owner.clone(), failing(patch)).unwrap_err();
+        assert!(matches!(err, ContractError::PatchTooLarge { .. }));
+        let patch = GraphPatch {
+            create_nodes: vec![new_node("twin"), new_node("twin")],
+            update_nodes: vec![],
+            remove_links: vec![],
+            create_links: vec![],
+        };
+        let err = execute(deps.as_mut(), mock_env(), owner.clone(), failing(patch)).unwrap_err();
+        assert!(matches!(err, ContractError::DuplicateTempId { .. }));
+        assert_eq!(node_count(deps.as_ref()), 3);
+        let patch = GraphPatch {
+            create_nodes: vec![new_node("premise")],
+            update_nodes: vec![],
+            remove_links: vec![],
+            create_links: vec![link(
+                NodeRef::Id(0),
+                NodeRef::Temp("conclusion".to_string()),
+                "asks",
+            )],
+        };
+        let err = execute(deps.as_mut(), mock_env(), owner, failing(patch)).unwrap_err();
+        assert!(matches!(err, ContractError::UnknownTempId { .. }));
+        assert_eq!(node_count(deps.as_ref()), 3);
+        let stranger = message_info(&deps.api.addr_make("stranger"), &[]);
+        let patch = GraphPatch {
+            create_nodes: vec![],
+            update_nodes: vec![PatchUpdate {
+                node_id: 0,
+                data: String::new(),
+            }],
+            remove_links: vec![],
+            create_links: vec![],
+        };
+        let err = execute(deps.as_mut(), mock_env(), stranger, failing(patch)).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized { node_id: 0 }));
+    }
+}
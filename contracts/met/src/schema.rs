@@ -0,0 +1,168 @@
+use crate::error::ContractError;
+use crate::msg::SchemaResponse;
+use crate::state::{FieldType, KindSchema, NodeKind, CONFIG, SCHEMAS};
+use cosmwasm_std::{Deps, DepsMut, MessageInfo, Response, StdResult, Storage};
+use serde_json::Value;
+
+pub fn register_schema(
+    deps: DepsMut,
+    info: MessageInfo,
+    kind: NodeKind,
+    schema: KindSchema,
+) -> Result<Response, ContractError> {
+    if CONFIG.load(deps.storage)?.admin != info.sender {
+        return Err(ContractError::NotAdmin {});
+    }
+    SCHEMAS.save(deps.storage, kind.as_str(), &schema)?;
+    Ok(Response::new()
+        .add_attribute("action", "register_schema")
+        .add_attribute("kind", kind.as_str())
+        .add_attribute("fields", schema.fields.len().to_string()))
+}
+
+// Kinds without a schema take any data
+pub(crate) fn validate(
+    storage: &dyn Storage,
+    kind: NodeKind,
+    data: &str,
+) -> Result<(), ContractError> {
+    let schema = match SCHEMAS.may_load(storage, kind.as_str())? {
+        Some(schema) => schema,
+        None => return Ok(()),
+    };
+    let invalid = |reason: String| ContractError::InvalidPayload {
+        kind: kind.as_str().to_string(),
+        reason,
+    };
+    let payload: Value = serde_json::from_str(data).map_err(|err| invalid(err.to_string()))?;
+    let object = payload
+        .as_object()
+        .ok_or_else(|| invalid("not a JSON object".to_string()))?;
+    for field in &schema.fields {
+        match object.get(&field.name) {
+            None | Some(Value::Null) if field.optional => {}
+            None => return Err(invalid(format!("missing field {}", field.name))),
+            Some(value) if !matches_type(field.field_type, value) => {
+                return Err(invalid(format!(
+                    "field {} is not of type {:?}",
+                    field.name, field.field_type
+                )))
+            }
+            Some(_) => {}
+        }
+    }
+    if !schema.allow_unknown_fields {
+        if let Some(name) = object
+            .keys()
+            .find(|name| !schema.fields.iter().any(|field| field.name == **name))
+        {
+            return Err(invalid(format!("unknown field {}", name)));
+        }
+    }
+    Ok(())
+}
+
+fn matches_type(field_type: FieldType, value: &Value) -> bool {
+    match field_type {
+        FieldType::String => value.is_string(),
+        FieldType::Number => value.is_number(),
+        FieldType::Bool => value.is_boolean(),
+        FieldType::Array => value.is_array(),
+        FieldType::Object => value.is_object(),
+    }
+}
+
+pub fn query_schema(deps: Deps, kind: NodeKind) -> StdResult<SchemaResponse> {
+    Ok(SchemaResponse {
+        kind,
+        schema: SCHEMAS.may_load(deps.storage, kind.as_str())?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ContractError;
+    use crate::hod::{execute, instantiate, query};
+    use crate::msg::{ExecuteMsg, InstantiateMsg, NodeResponse, QueryMsg, SchemaResponse};
+    use crate::state::{Acyclic, DuplicateLinks, FieldSchema, FieldType, KindSchema, NodeKind};
+    use cosmwasm_std::{
+        from_json,
+        testing::{message_info, mock_dependencies, mock_env},
+    };
+
+    #[test]
+    fn test_payload_schemas() {
+        let mut deps = mock_dependencies();
+        let admin = message_info(&deps.api.addr_make("admin"), &[]);
+        let owner = message_info(&deps.api.addr_make("owner"), &[]);
+        let msg = InstantiateMsg {
+            admin: None,
+            allow_self_links: false,
+            duplicate_links: DuplicateLinks::Reject,
+            acyclic: Acyclic::Off,
+        };
+        instantiate(deps.as_mut(), mock_env(), admin.clone(), msg).unwrap();
+
+        let field = |name: &str, field_type, optional| FieldSchema {
+            name: name.to_string(),
+            field_type,
+            optional,
+        };
+        let register = ExecuteMsg::RegisterSchema {
+            kind: NodeKind::Task,
+            schema: KindSchema {
+                fields: vec![
+                    field("title", FieldType::String, false),
+                    field("done", FieldType::Bool, true),
+                ],
+                allow_unknown_fields: false,
+            },
+        };
+        let err = execute(deps.as_mut(), mock_env(), owner.clone(), register.clone()).unwrap_err();
+        assert!(matches!(err, ContractError::NotAdmin {}));
+        execute(deps.as_mut(), mock_env(), admin, register).unwrap();
+        let query_msg = QueryMsg::GetSchema {
+            kind: NodeKind::Task,
+        };
+        let res: SchemaResponse =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert_eq!(res.schema.unwrap().fields.len(), 2);
+
+        let create = |kind, data: &str| ExecuteMsg::CreateNode {
+            kind,
+            data: data.to_string(),
+            editors: vec![],
+        };
+        for data in [
+            "meditate",
+            r#"["meditate"]"#,
+            r#"{"done": false}"#,
+            r#"{"title": 7}"#,
+            r#"{"title": "meditate", "due": 3}"#,
+        ] {
+            let msg = create(NodeKind::Task, data);
+            let err = execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap_err();
+            assert!(matches!(err, ContractError::InvalidPayload { .. }));
+        }
+        let msg = create(NodeKind::Task, r#"{"title": "meditate", "done": null}"#);
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        // Concepts have no schema yet
+        let msg = create(NodeKind::Concept, "keter");
+        execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+
+        // The kind stays with the node through updates
+        let update = |data: &str| ExecuteMsg::UpdateNode {
+            node_id: 0,
+            data: data.to_string(),
+        };
+        let err = execute(deps.as_mut(), mock_env(), owner.clone(), update("done")).unwrap_err();
+        assert!(matches!(err, ContractError::InvalidPayload { .. }));
+        let data = r#"{"title": "meditate", "done": true}"#;
+        execute(deps.as_mut(), mock_env(), owner, update(data)).unwrap();
+        let res: NodeResponse =
+            from_json(query(deps.as_ref(), mock_env(), QueryMsg::GetNode { node_id: 0 }).unwrap())
+                .unwrap();
+        assert_eq!(res.kind, NodeKind::Task);
+        assert_eq!(res.data, data);
+    }
+}
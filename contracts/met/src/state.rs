@@ -1,14 +1,204 @@
-use cosmwasm_std::Addr;
-use cw_storage_plus::{Item, Map};
+use cosmwasm_std::{Addr, Empty};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Config {
+    pub admin: Addr, // Registers the payload schemas
+    pub allow_self_links: bool,
+    pub duplicate_links: DuplicateLinks,
+    #[serde(default)]
+    pub acyclic: Acyclic,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default, JsonSchema)]
+pub enum DuplicateLinks {
+    #[default]
+    Reject,
+    Replace, // The new type and weight overwrite the old ones
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default, JsonSchema)]
+pub enum NodeKind {
+    #[default]
+    Concept,
+    Citizen,
+    Task,
+    ReasoningSession,
+    StorageRef,
+}
+
+impl NodeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeKind::Concept => "Concept",
+            NodeKind::Citizen => "Citizen",
+            NodeKind::Task => "Task",
+            NodeKind::ReasoningSession => "ReasoningSession",
+            NodeKind::StorageRef => "StorageRef",
+        }
+    }
+}
+
+// The data of a node whose kind has a schema must be a JSON object
+// with these fields
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct KindSchema {
+    pub fields: Vec<FieldSchema>,
+    #[serde(default)]
+    pub allow_unknown_fields: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FieldSchema {
+    pub name: String,
+    pub field_type: FieldType,
+    #[serde(default)]
+    pub optional: bool, // May be missing or null
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+pub enum FieldType {
+    String,
+    Number,
+    Bool,
+    Array,
+    Object,
+}
+
+// Which links may not close a cycle
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default, JsonSchema)]
+pub enum Acyclic {
+    #[default]
+    Off,
+    Graph,
+    EdgeTypes(Vec<String>), // Cycles through other types are fine
+}
+
+impl Acyclic {
+    pub fn covers(&self, edge_type: &str) -> bool {
+        match self {
+            Acyclic::Off => false,
+            Acyclic::Graph => true,
+            Acyclic::EdgeTypes(edge_types) => edge_types.iter().any(|covered| covered == edge_type),
+        }
+    }
+}
+
+// A switch to a new acyclic setting, ranking the graph anew a page at a time with
+// Kahn's algorithm before it applies
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AcyclicUpdate {
+    pub acyclic: Acyclic,
+    pub previous: Acyclic, // Put back if the links the new setting covers form a cycle
+    pub phase: RankPhase,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub enum RankPhase {
+    // Covered links raise the in-degree of their to node
+    Count {
+        start_after: Option<(u64, u64)>,
+    },
+    // Live nodes start at rank 0, those without covered links in are ready
+    Seed {
+        start_after: Option<u64>,
+    },
+    // The lowest ready node hands its rank on along its covered links out, a node
+    // with more links out than a call reads is finished before the next one
+    Sort {
+        node: Option<u64>,
+        start_after: Option<u64>,
+    },
+    // Nodes left with links in sit on a cycle, their in-degrees are dropped
+    Clear,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Node {
     pub id: u64,
+    #[serde(default)]
+    pub kind: NodeKind,
+    pub owner: Addr,
+    pub editors: Vec<Addr>, // May update the node and link out of it
+    pub data: String,
+    pub version: u32, // Latest entry in NODE_VERSIONS
+    #[serde(default)]
+    pub deleted_at: Option<u64>, // Block height of the soft delete
+}
+
+impl Node {
+    pub fn can_edit(&self, address: &Addr) -> bool {
+        self.owner == *address || self.editors.contains(address)
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NodeVersion {
+    pub version: u32,
     pub data: String,
-    pub links: Vec<u64>,
+    pub editor: Addr,
+    pub height: u64,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Edge {
+    pub from: u64,
+    pub to: u64,
+    pub edge_type: String,
+    pub weight: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NodeLabel {
+    pub node_id: u64,
+    pub label: String,
+}
+
+pub struct LabelIndexes<'a> {
+    pub label: MultiIndex<'a, String, NodeLabel, (u64, String)>,
+}
+
+impl IndexList<NodeLabel> for LabelIndexes<'_> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<NodeLabel>> + '_> {
+        let v: Vec<&dyn Index<NodeLabel>> = vec![&self.label];
+        Box::new(v.into_iter())
+    }
+}
+
+// Keyed by (node, label), the index finds the nodes of a label
+pub fn node_labels<'a>() -> IndexedMap<(u64, String), NodeLabel, LabelIndexes<'a>> {
+    let indexes = LabelIndexes {
+        label: MultiIndex::new(
+            |_pk, node_label: &NodeLabel| node_label.label.clone(),
+            "node_labels",
+            "node_labels__label",
+        ),
+    };
+    IndexedMap::new("node_labels", indexes)
+}
+
+pub const CONFIG: Item<Config> = Item::new("config");
 pub const NODES: Map<u64, Node> = Map::new("nodes");
 pub const NEXT_NODE_ID: Item<u64> = Item::new("next_node_id");
+pub const SCHEMAS: Map<&str, KindSchema> = Map::new("schemas"); // By kind
+pub const LABEL_COUNTS: Map<&str, u64> = Map::new("label_counts"); // Dropped at 0
+pub const NODE_VERSIONS: Map<(u64, u32), NodeVersion> = Map::new("node_versions");
+// (node, height) to the last version written at that height
+pub const VERSION_HEIGHTS: Map<(u64, u64), u32> = Map::new("version_heights");
+pub const EDGES: Map<(u64, u64), Edge> = Map::new("edges"); // (from, to)
+pub const IN_EDGES: Map<(u64, u64), Empty> = Map::new("in_edges"); // (to, from)
+pub const IN_DEGREES: Map<u64, u32> = Map::new("in_degrees"); // Dropped at 0
+pub const OUT_DEGREES: Map<u64, u32> = Map::new("out_degrees"); // Dropped at 0
+
+// Every covered link climbs to a higher rank while the acyclic setting is on
+pub const RANKS: Map<u64, u32> = Map::new("ranks");
+pub const RANKED: Map<(u32, u64), Empty> = Map::new("ranked"); // (rank, node)
+pub const ACYCLIC_UPDATE: Item<AcyclicUpdate> = Item::new("acyclic_update");
+pub const PENDING_DEGREES: Map<u64, u32> = Map::new("pending_degrees"); // Dropped at 0
+pub const READY: Map<u64, Empty> = Map::new("ready");
@@ -0,0 +1,444 @@
+use crate::hod::load_live;
+use crate::msg::{Direction, ReachableResponse, Reached, ShortestPathResponse, TraverseResponse};
+use crate::state::{EDGES, IN_EDGES};
+use cosmwasm_std::{from_json, to_json_binary, Binary, Deps, Order, StdError, StdResult, Storage};
+use cw_storage_plus::Bound;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+// Neighbor lookups per query, the continuation picks up where the budget ran out
+const DEFAULT_ITERATIONS: u32 = 100;
+const MAX_ITERATIONS: u32 = 1000;
+// Nodes one search may carry over all its pages to expand later, a search that
+// outgrows it ends with a truncated final page. Nodes at the depth limit are not
+// expanded, so they are only kept apart within a page and may come up again later
+const MAX_VISITED: usize = 1000;
+
+// Breadth first search state, handed to the caller as the continuation token
+#[derive(Serialize, Deserialize)]
+struct Search {
+    // The query the search answers, a continuation only resumes the same one
+    start: u64,
+    direction: Direction,
+    max_depth: u32,
+    target: Option<u64>,
+    queue: VecDeque<(u64, u32)>, // (node, depth)
+    parents: Vec<(u64, u64)>,    // (node, parent), the start is its own parent
+    cursor: Option<u64>,         // Last neighbor looked at of the node in front
+    #[serde(skip)]
+    truncated: bool,
+}
+
+impl Search {
+    fn resume(
+        deps: Deps,
+        start: u64,
+        direction: Direction,
+        max_depth: u32,
+        target: Option<u64>,
+        continuation: Option<Binary>,
+    ) -> StdResult<Self> {
+        let continuation = match continuation {
+            Some(continuation) => continuation,
+            None => {
+                load_live(deps.storage, start)?;
+                return Ok(Search {
+                    start,
+                    direction,
+                    max_depth,
+                    target,
+                    queue: VecDeque::from(vec![(start, 0)]),
+                    parents: vec![(start, start)],
+                    cursor: None,
+                    truncated: false,
+                });
+            }
+        };
+        let search: Search = from_json(continuation)?;
+        if search.start != start
+            || search.direction != direction
+            || search.max_depth != max_depth
+            || search.target != target
+        {
+            return Err(StdError::generic_err(
+                "Continuation belongs to a different query",
+            ));
+        }
+        Ok(search)
+    }
+
+    // Expands nodes until the budget runs out or the target turns up,
+    // returns the nodes reached on the way
+    fn run(&mut self, storage: &dyn Storage, iterations: Option<u32>) -> StdResult<Vec<Reached>> {
+        let mut budget = iterations.unwrap_or(DEFAULT_ITERATIONS).min(MAX_ITERATIONS) as usize;
+        let mut parents: BTreeMap<u64, u64> = self.parents.iter().copied().collect();
+        let mut leaves = BTreeSet::new();
+        let mut reached = vec![];
+        while let Some(&(node_id, depth)) = self.queue.front() {
+            if budget == 0
+                || self
+                    .target
+                    .is_some_and(|target| parents.contains_key(&target))
+            {
+                break;
+            }
+            if depth >= self.max_depth {
+                self.queue.pop_front();
+                continue;
+            }
+            let next = neighbors(storage, node_id, self.direction, self.cursor, budget)?;
+            if next.len() < budget {
+                self.queue.pop_front();
+                self.cursor = None;
+            } else {
+                self.cursor = next.last().copied();
+            }
+            budget -= next.len();
+            for neighbor in next {
+                if parents.contains_key(&neighbor) {
+                    continue;
+                }
+                if depth + 1 >= self.max_depth && self.target.is_none() {
+                    if leaves.insert(neighbor) {
+                        reached.push(Reached {
+                            node_id: neighbor,
+                            depth: depth + 1,
+                        });
+                    }
+                    continue;
+                }
+                if parents.len() >= MAX_VISITED {
+                    self.truncated = true;
+                    self.queue.clear();
+                    return Ok(reached);
+                }
+                parents.insert(neighbor, node_id);
+                self.parents.push((neighbor, node_id));
+                self.queue.push_back((neighbor, depth + 1));
+                reached.push(Reached {
+                    node_id: neighbor,
+                    depth: depth + 1,
+                });
+            }
+        }
+        Ok(reached)
+    }
+
+    fn path_to(&self, target: u64) -> Option<Vec<u64>> {
+        let parents: BTreeMap<u64, u64> = self.parents.iter().copied().collect();
+        let mut path = vec![target];
+        let mut node_id = target;
+        loop {
+            let parent = *parents.get(&node_id)?;
+            if parent == node_id {
+                path.reverse();
+                return Some(path);
+            }
+            path.push(parent);
+            node_id = parent;
+        }
+    }
+
+    // None once every reachable node was expanded or the search was truncated
+    fn continuation(&self) -> StdResult<Option<Binary>> {
+        if self.queue.is_empty() {
+            return Ok(None);
+        }
+        to_json_binary(self).map(Some)
+    }
+}
+
+fn neighbors(
+    storage: &dyn Storage,
+    node_id: u64,
+    direction: Direction,
+    after: Option<u64>,
+    limit: usize,
+) -> StdResult<Vec<u64>> {
+    let start = after.map(Bound::exclusive);
+    let keys = match direction {
+        Direction::Outgoing => EDGES
+            .prefix(node_id)
+            .keys(storage, start, None, Order::Ascending),
+        Direction::Incoming => {
+            IN_EDGES
+                .prefix(node_id)
+                .keys(storage, start, None, Order::Ascending)
+        }
+    };
+    keys.take(limit).collect()
+}
+
+// Nodes up to max_depth links away, each page lists the ones it reached first
+pub fn query_traverse(
+    deps: Deps,
+    start: u64,
+    direction: Direction,
+    max_depth: u32,
+    iterations: Option<u32>,
+    continuation: Option<Binary>,
+) -> StdResult<TraverseResponse> {
+    let mut nodes = vec![];
+    if continuation.is_none() {
+        nodes.push(Reached {
+            node_id: start,
+            depth: 0,
+        });
+    }
+    let mut search = Search::resume(deps, start, direction, max_depth, None, continuation)?;
+    nodes.extend(search.run(deps.storage, iterations)?);
+    Ok(TraverseResponse {
+        nodes,
+        continuation: search.continuation()?,
+        truncated: search.truncated,
+    })
+}
+
+// Follows outgoing links, the path is only final without a continuation
+pub fn query_shortest_path(
+    deps: Deps,
+    from: u64,
+    to: u64,
+    iterations: Option<u32>,
+    continuation: Option<Binary>,
+) -> StdResult<ShortestPathResponse> {
+    load_live(deps.storage, to)?;
+    let mut search = Search::resume(
+        deps,
+        from,
+        Direction::Outgoing,
+        u32::MAX,
+        Some(to),
+        continuation,
+    )?;
+    search.run(deps.storage, iterations)?;
+    match search.path_to(to) {
+        Some(path) => Ok(ShortestPathResponse {
+            path: Some(path),
+            continuation: None,
+            truncated: false,
+        }),
+        None => Ok(ShortestPathResponse {
+            path: None,
+            continuation: search.continuation()?,
+            truncated: search.truncated,
+        }),
+    }
+}
+
+pub fn query_reachable(
+    deps: Deps,
+    from: u64,
+    to: u64,
+    iterations: Option<u32>,
+    continuation: Option<Binary>,
+) -> StdResult<ReachableResponse> {
+    let res = query_shortest_path(deps, from, to, iterations, continuation)?;
+    Ok(ReachableResponse {
+        reachable: res.path.is_some(),
+        continuation: res.continuation,
+        truncated: res.truncated,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hod::{execute, instantiate, query};
+    use crate::msg::{
+        Direction, ExecuteMsg, InstantiateMsg, QueryMsg, ReachableResponse, ShortestPathResponse,
+        TraverseResponse,
+    };
+    use crate::state::{Acyclic, DuplicateLinks, NodeKind};
+    use crate::traversal::MAX_VISITED;
+    use cosmwasm_std::{
+        from_json,
+        testing::{message_info, mock_dependencies, mock_env},
+        Binary, Deps,
+    };
+
+    #[test]
+    fn test_traversals() {
+        let mut deps = mock_dependencies();
+        let owner = message_info(&deps.api.addr_make("owner"), &[]);
+        let msg = InstantiateMsg {
+            admin: None,
+            allow_self_links: false,
+            duplicate_links: DuplicateLinks::Reject,
+            acyclic: Acyclic::Off,
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        for _ in 0..5 {
+            let create = ExecuteMsg::CreateNode {
+                kind: NodeKind::Concept,
+                data: String::new(),
+                editors: vec![],
+            };
+            execute(deps.as_mut(), mock_env(), owner.clone(), create).unwrap();
+        }
+        // 0 -> 1 -> 2 -> 3 and the shortcut 0 -> 4 -> 3
+        for (from_node_id, to_node_id) in [(0, 1), (1, 2), (2, 3), (0, 4), (4, 3)] {
+            let link = ExecuteMsg::CreateLink {
+                from_node_id,
+                to_node_id,
+                edge_type: "path".to_string(),
+                weight: 1,
+            };
+            execute(deps.as_mut(), mock_env(), owner.clone(), link).unwrap();
+        }
+
+        let traverse = |deps: Deps, start, direction, max_depth| -> Vec<(u64, u32)> {
+            let query_msg = QueryMsg::Traverse {
+                start,
+                direction,
+                max_depth,
+                iterations: None,
+                continuation: None,
+            };
+            let res: TraverseResponse =
+                from_json(query(deps, mock_env(), query_msg).unwrap()).unwrap();
+            assert!(res.continuation.is_none());
+            res.nodes
+                .into_iter()
+                .map(|reached| (reached.node_id, reached.depth))
+                .collect()
+        };
+        assert_eq!(
+            traverse(deps.as_ref(), 0, Direction::Outgoing, 1),
+            vec![(0, 0), (1, 1), (4, 1)]
+        );
+        assert_eq!(
+            traverse(deps.as_ref(), 3, Direction::Incoming, 2),
+            vec![(3, 0), (2, 1), (4, 1), (1, 2), (0, 2)]
+        );
+
+        // One link per page still ends up at the shortest path
+        let mut continuation: Option<Binary> = None;
+        let mut pages = 0;
+        let path = loop {
+            pages += 1;
+            let query_msg = QueryMsg::ShortestPath {
+                from: 0,
+                to: 3,
+                iterations: Some(1),
+                continuation: continuation.take(),
+            };
+            let res: ShortestPathResponse =
+                from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+            if let Some(path) = res.path {
+                break path;
+            }
+            continuation = res.continuation;
+            assert!(continuation.is_some());
+        };
+        assert_eq!(path, vec![0, 4, 3]);
+        assert!(pages > 1);
+        let query_msg = QueryMsg::ShortestPath {
+            from: 0,
+            to: 3,
+            iterations: Some(1),
+            continuation: None,
+        };
+        let res: ShortestPathResponse =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        let query_msg = QueryMsg::ShortestPath {
+            from: 1,
+            to: 3,
+            iterations: Some(1),
+            continuation: res.continuation,
+        };
+        assert!(query(deps.as_ref(), mock_env(), query_msg).is_err());
+
+        let reachable = |deps: Deps, from, to| -> ReachableResponse {
+            let query_msg = QueryMsg::IsReachable {
+                from,
+                to,
+                iterations: None,
+                continuation: None,
+            };
+            from_json(query(deps, mock_env(), query_msg).unwrap()).unwrap()
+        };
+        assert!(reachable(deps.as_ref(), 1, 3).reachable);
+        let res = reachable(deps.as_ref(), 3, 0);
+        assert!(!res.reachable);
+        assert!(res.continuation.is_none());
+        assert!(reachable(deps.as_ref(), 2, 2).reachable);
+    }
+
+    #[test]
+    fn test_visit_cap() {
+        let mut deps = mock_dependencies();
+        let owner = message_info(&deps.api.addr_make("owner"), &[]);
+        let msg = InstantiateMsg {
+            admin: None,
+            allow_self_links: false,
+            duplicate_links: DuplicateLinks::Reject,
+            acyclic: Acyclic::Off,
+        };
+        instantiate(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+        // A star with more leaves than a search may carry in its continuation, and a
+        // node off to the side
+        for to_node_id in 0..=MAX_VISITED as u64 + 2 {
+            let create = ExecuteMsg::CreateNode {
+                kind: NodeKind::Concept,
+                data: String::new(),
+                editors: vec![],
+            };
+            execute(deps.as_mut(), mock_env(), owner.clone(), create).unwrap();
+            if to_node_id > 0 && to_node_id <= MAX_VISITED as u64 + 1 {
+                let link = ExecuteMsg::CreateLink {
+                    from_node_id: 0,
+                    to_node_id,
+                    edge_type: "ray".to_string(),
+                    weight: 1,
+                };
+                execute(deps.as_mut(), mock_env(), owner.clone(), link).unwrap();
+            }
+        }
+        let traverse = |deps: Deps, max_depth, continuation| -> TraverseResponse {
+            let query_msg = QueryMsg::Traverse {
+                start: 0,
+                direction: Direction::Outgoing,
+                max_depth,
+                iterations: Some(100),
+                continuation,
+            };
+            from_json(query(deps, mock_env(), query_msg).unwrap()).unwrap()
+        };
+        // The leaves are not expanded, so the search pages through all of them
+        let mut nodes = vec![];
+        let mut continuation = None;
+        loop {
+            let res = traverse(deps.as_ref(), 1, continuation);
+            assert!(!res.truncated);
+            nodes.extend(res.nodes.into_iter().map(|reached| reached.node_id));
+            continuation = res.continuation;
+            if continuation.is_none() {
+                break;
+            }
+        }
+        assert_eq!(nodes, (0..=MAX_VISITED as u64 + 1).collect::<Vec<_>>());
+
+        // One level deeper they would all have to be carried along
+        let mut continuation = None;
+        let res = loop {
+            let res = traverse(deps.as_ref(), 2, continuation);
+            if res.continuation.is_none() {
+                break res;
+            }
+            continuation = res.continuation;
+        };
+        assert!(res.truncated);
+
+        let query_msg = QueryMsg::IsReachable {
+            from: 0,
+            to: MAX_VISITED as u64 + 2,
+            iterations: Some(1000),
+            continuation: None,
+        };
+        let res: ReachableResponse =
+            from_json(query(deps.as_ref(), mock_env(), query_msg).unwrap()).unwrap();
+        assert!(!res.reachable);
+        assert!(res.continuation.is_none());
+        assert!(res.truncated);
+    }
+}
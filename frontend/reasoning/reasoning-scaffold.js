@@ -5,28 +5,57 @@ import { Button } from "@/components/ui/button";
 import { motion } from "framer-motion";
 import * as d3 from "d3";
 
+// Where the met contract holding the reasoning graph lives
+const LCD_URL = process.env.NEXT_PUBLIC_LCD_URL || "http://localhost:1317";
+const MET_CONTRACT = process.env.NEXT_PUBLIC_MET_CONTRACT;
+const PAGE_SIZE = 30;
+
+async function queryMet(msg) {
+  const query = btoa(JSON.stringify(msg));
+  const res = await fetch(`${LCD_URL}/cosmwasm/wasm/v1/contract/${MET_CONTRACT}/smart/${query}`);
+  if (!res.ok) throw new Error(`met query failed: ${res.status}`);
+  return (await res.json()).data;
+}
+
+// Pages through ExportGraph and turns the JSON Graph Format into d3 nodes and links
+async function fetchGraph() {
+  const nodes = [];
+  const edges = [];
+  let startAfter = null;
+  do {
+    const page = await queryMet({ ExportGraph: { start_after: startAfter, limit: PAGE_SIZE } });
+    nodes.push(...page.graph.nodes);
+    edges.push(...page.graph.edges);
+    startAfter = page.next;
+  } while (startAfter !== null && startAfter !== undefined);
+
+  // Sources, paths in between and the sinks they sync into
+  const incoming = new Set(edges.map(e => e.target));
+  const outgoing = new Set(edges.map(e => e.source));
+  return {
+    nodes: nodes.map(n => ({
+      id: n.id,
+      label: n.label,
+      group: !incoming.has(n.id) ? 1 : !outgoing.has(n.id) ? 3 : 2,
+    })),
+    links: edges.map(e => ({ source: e.source, target: e.target, relation: e.relation })),
+  };
+}
+
 export default function ReasoningUI() {
   const [graph, setGraph] = useState({ nodes: [], links: [] });
+  const [error, setError] = useState(null);
 
-  useEffect(() => {
-    // Placeholder for live-streamed reasoning path from model
-    const seedGraph = {
-      nodes: [
-        { id: "you", group: 1 },
-        { id: "me", group: 1 },
-        { id: "path1", group: 2 },
-        { id: "path2", group: 2 },
-        { id: "sync", group: 3 },
-      ],
-      links: [
-        { source: "you", target: "path1" },
-        { source: "me", target: "path2" },
-        { source: "path1", target: "sync" },
-        { source: "path2", target: "sync" },
-      ],
-    };
-    setGraph(seedGraph);
-  }, []);
+  const refresh = () => {
+    fetchGraph()
+      .then(graph => {
+        setGraph(graph);
+        setError(null);
+      })
+      .catch(err => setError(err.message));
+  };
+
+  useEffect(refresh, []);
 
   useEffect(() => {
     if (!graph.nodes.length) return;
@@ -61,7 +90,7 @@ export default function ReasoningUI() {
         .on("drag", dragged)
         .on("end", dragended));
 
-    node.append("title").text(d => d.id);
+    node.append("title").text(d => d.label);
 
     simulation.on("tick", () => {
       link
@@ -110,7 +139,8 @@ export default function ReasoningUI() {
           Synced paths are highlighted in green. Interactive nodes will reflect live embeddings.
         </p>
       </motion.div>
-      <Button>Refresh Sync</Button>
+      {error && <p className="text-sm text-red-600">{error}</p>}
+      <Button onClick={refresh}>Refresh Sync</Button>
     </div>
   );
 }